@@ -1,34 +1,87 @@
-use std::sync::mpsc::{channel, Sender};
+use std::sync::{
+    mpsc::{channel, Sender},
+    Arc, Mutex,
+};
 
+//后台任务的取消标志,任务在耗时操作前后检查,已取消就直接放弃结果
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<Mutex<bool>>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        *self.0.lock().unwrap() = true;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.lock().unwrap()
+    }
+}
+
+pub type Job = Box<dyn FnOnce(&CancelToken) + Send>;
+
+//固定数量的工作线程,慢的请求只占一个线程,不会挡住其他请求
 #[derive(Clone)]
 pub struct Executor {
     task_sender: Sender<Task>,
+    cancel: CancelToken,
+    workers: usize,
 }
 pub enum Task {
     Println(String),
+    Run(Job),
     Exit,
 }
 
 impl Executor {
-    pub fn new() -> Self {
+    pub fn new(workers: usize) -> Self {
         let (sender, receiver) = channel();
-        std::thread::spawn(move || loop {
-            match receiver.recv() {
-                Ok(task) => match task {
-                    Task::Println(string) => println!("{}", string),
-                    Task::Exit => return,
-                },
-                Err(_) => {
-                    return;
+        //多个线程共用一个receiver,谁空闲谁取任务
+        let receiver = Arc::new(Mutex::new(receiver));
+        let cancel = CancelToken::default();
+        for _ in 0..workers {
+            let receiver = receiver.clone();
+            let cancel = cancel.clone();
+            std::thread::spawn(move || loop {
+                let task = receiver.lock().unwrap().recv();
+                match task {
+                    Ok(task) => match task {
+                        Task::Println(string) => println!("{}", string),
+                        Task::Run(job) => {
+                            if !cancel.is_cancelled() {
+                                job(&cancel);
+                            }
+                        }
+                        Task::Exit => return,
+                    },
+                    Err(_) => {
+                        return;
+                    }
                 }
-            }
-        });
+            });
+        }
         Executor {
             task_sender: sender,
+            cancel,
+            workers,
         }
     }
 
     pub fn println(&self, string: String) {
         self.task_sender.send(Task::Println(string)).unwrap()
     }
+
+    //关闭后提交的任务会被忽略
+    pub fn spawn<F: FnOnce(&CancelToken) + Send + 'static>(&self, job: F) {
+        self.task_sender
+            .send(Task::Run(Box::new(job)))
+            .unwrap_or_default();
+    }
+
+    //取消所有正在执行和排队的任务,工作线程处理完手上的请求后退出
+    pub fn shutdown(&self) {
+        self.cancel.cancel();
+        for _ in 0..self.workers {
+            self.task_sender.send(Task::Exit).unwrap_or_default();
+        }
+    }
 }
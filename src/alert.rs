@@ -0,0 +1,384 @@
+use std::{
+    process::{Command, Stdio},
+    thread,
+};
+
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{expr, News, Stock};
+
+//提醒的触发方式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertMode {
+    //条件从不满足变成满足时触发
+    #[default]
+    Cross,
+    //触发一次后禁用
+    Once,
+    //每天最多触发一次
+    Daily,
+    //条件满足期间每隔interval分钟触发一次
+    Repeat,
+}
+
+//价格提醒,目前只能在.stocks.json里手工配置,例如:
+//{"code": "0600519", "alerts": [{"above": 1800, "mode": "daily", "exec": "notify-send {title} {price}"}]}
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Alert {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub above: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub below: Option<f64>,
+    //表达式条件,比如"price > 1800 && percent > 2",和above/below是或的关系
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub condition: String,
+    #[serde(default)]
+    pub mode: AlertMode,
+    //repeat模式的间隔分钟数
+    #[serde(default = "default_interval")]
+    pub interval: i64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    //触发时执行的命令,支持{code},{title},{price},{percent}占位符
+    //占位符会换成环境变量STOCK_CODE,STOCK_TITLE,STOCK_PRICE,STOCK_PERCENT的引用,也可以直接用环境变量
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub exec: String,
+    //上次检查时条件是否满足,cross模式用
+    #[serde(skip)]
+    pub fired: bool,
+    #[serde(skip)]
+    pub last_fired: Option<DateTime<Local>>,
+    #[serde(skip)]
+    pub snooze_until: Option<DateTime<Local>>,
+}
+
+fn default_interval() -> i64 {
+    5
+}
+
+//触发后交给界面弹出的提示,index是提醒在stock.alerts里的位置,稍后提醒时用
+#[derive(Clone, Debug)]
+pub struct AlertNotice {
+    pub code: String,
+    //关键词提醒不是stock.alerts里配置的,没有index,不能稍后提醒
+    pub index: Option<usize>,
+    //弹框和推送的标题
+    pub title: &'static str,
+    pub message: String,
+}
+
+//新闻和公告的标题包含这些词时提醒,配置里的keywords可以修改,空列表表示不提醒
+pub fn default_keywords() -> Vec<String> {
+    ["减持", "重组", "停牌"]
+        .iter()
+        .map(|k| k.to_string())
+        .collect()
+}
+
+impl Alert {
+    //表达式出错时按不满足处理,错误由condition_errors报告
+    pub fn is_triggered(&self, stock: &Stock) -> bool {
+        //价格为0说明还没取到行情
+        stock.price > 0.0
+            && (self.above.map_or(false, |above| stock.price >= above)
+                || self.below.map_or(false, |below| stock.price <= below)
+                || (!self.condition.is_empty()
+                    && expr::eval(&self.condition, stock).map_or(false, |v| v != 0.0)))
+    }
+
+    fn should_fire(&self, now: DateTime<Local>) -> bool {
+        if self.disabled || self.snooze_until.map_or(false, |until| now < until) {
+            return false;
+        }
+        match self.mode {
+            AlertMode::Cross => !self.fired,
+            AlertMode::Once => true,
+            AlertMode::Daily => self
+                .last_fired
+                .map_or(true, |last| last.date_naive() != now.date_naive()),
+            AlertMode::Repeat => self
+                .last_fired
+                .map_or(true, |last| now - last >= Duration::minutes(self.interval)),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = vec![];
+        if let Some(above) = self.above {
+            parts.push(format!("高于{}", above));
+        }
+        if let Some(below) = self.below {
+            parts.push(format!("低于{}", below));
+        }
+        if !self.condition.is_empty() {
+            parts.push(format!("满足{}", self.condition));
+        }
+        parts.join("或")
+    }
+}
+
+//每次刷新后检查stock上的提醒,返回这次触发的提醒
+pub fn check_alerts(stock: &mut Stock) -> Vec<AlertNotice> {
+    let now = Local::now();
+    let mut notices = vec![];
+    let mut alerts = std::mem::take(&mut stock.alerts);
+    for (index, alert) in alerts.iter_mut().enumerate() {
+        let triggered = alert.is_triggered(stock);
+        if triggered && alert.should_fire(now) {
+            if !alert.exec.is_empty() {
+                run_exec(&alert.exec, stock);
+            }
+            notices.push(AlertNotice {
+                code: stock.code.clone(),
+                index: Some(index),
+                title: "价格提醒",
+                message: format!(
+                    "{}({}) 当前价{} {}",
+                    stock.title,
+                    stock.code,
+                    stock.price,
+                    alert.describe()
+                ),
+            });
+            alert.last_fired = Some(now);
+            if let AlertMode::Once = alert.mode {
+                alert.disabled = true;
+            }
+        }
+        alert.fired = triggered;
+    }
+    stock.alerts = alerts;
+    notices
+}
+
+//标题包含关键词的新闻和公告,只看当天发布的,同一条只提醒一次
+pub fn check_keywords(stock: &mut Stock, items: &[News], keywords: &[String]) -> Vec<AlertNotice> {
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let mut notices = vec![];
+    for item in items {
+        if !item.time.starts_with(&today) || stock.keyword_alerted.contains(&item.url) {
+            continue;
+        }
+        let matched: Vec<&str> = keywords
+            .iter()
+            .filter(|keyword| !keyword.is_empty() && item.title.contains(keyword.as_str()))
+            .map(String::as_str)
+            .collect();
+        if matched.is_empty() {
+            continue;
+        }
+        stock.keyword_alerted.push(item.url.clone());
+        notices.push(AlertNotice {
+            code: stock.code.clone(),
+            index: None,
+            title: "关键词提醒",
+            message: format!(
+                "{}({}) {} 包含{}",
+                stock.title,
+                stock.code,
+                item.title,
+                matched.join("、")
+            ),
+        });
+    }
+    notices
+}
+
+//检查表达式条件是否有错,有错时返回错误信息显示在stock上
+pub fn condition_errors(stock: &Stock) -> String {
+    stock
+        .alerts
+        .iter()
+        .filter(|alert| !alert.condition.is_empty())
+        .filter_map(|alert| expr::eval(&alert.condition, stock).err())
+        .map(|err| format!("提醒条件错误:{}", err))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+//表达式里可以用的变量,percent和比例都按百分比,和界面上显示的一致,
+//turnover是成交额,volume是最近一根日K线的成交量,基本面和日K线还没加载时这些变量报错
+impl expr::Env for Stock {
+    fn var(&self, name: &str) -> Option<f64> {
+        let fundamentals = self.fundamentals.as_ref();
+        match name {
+            "price" => Some(self.price),
+            "percent" => Some(self.percent * 100.0),
+            "open" => Some(self.open),
+            "yestclose" => Some(self.yestclose),
+            "high" => Some(self.high),
+            "low" => Some(self.low),
+            "turnover" => Some(self.turnover),
+            "volume" => self.candles.last().map(|candle| candle.volume),
+            //换手率按成交额除以流通市值估算
+            "turnover_rate" => fundamentals
+                .filter(|f| f.float_cap > 0.0)
+                .map(|f| self.turnover / f.float_cap * 100.0),
+            "pe" => fundamentals.map(|f| f.pe),
+            "pb" => fundamentals.map(|f| f.pb),
+            "eps" => fundamentals.map(|f| f.eps),
+            "roe" => fundamentals.map(|f| f.roe),
+            "market_cap" => fundamentals.map(|f| f.market_cap),
+            "float_cap" => fundamentals.map(|f| f.float_cap),
+            "revenue_growth" => fundamentals.map(|f| f.revenue_growth),
+            "profit_growth" => fundamentals.map(|f| f.profit_growth),
+            "gross_margin" => fundamentals.map(|f| f.gross_margin),
+            "debt_ratio" => fundamentals.map(|f| f.debt_ratio),
+            _ => None,
+        }
+    }
+
+    fn call(&self, name: &str, args: &[f64]) -> Option<f64> {
+        match (name, args) {
+            ("abs", [x]) => Some(x.abs()),
+            ("min", [x, y]) => Some(x.min(*y)),
+            ("max", [x, y]) => Some(x.max(*y)),
+            //n日均线,用日K线的收盘价,当天的收盘价用现价代替,K线不够n根时报错
+            ("ma", [n]) if *n >= 1.0 => {
+                let n = *n as usize;
+                let today = self
+                    .trade_time()
+                    .map(|time| time.format("%Y-%m-%d").to_string());
+                let mut closes: Vec<f64> = self
+                    .candles
+                    .iter()
+                    .filter(|candle| Some(&candle.date) != today.as_ref())
+                    .map(|candle| candle.close)
+                    .collect();
+                if self.price > 0.0 {
+                    closes.push(self.price);
+                }
+                if closes.len() < n {
+                    return None;
+                }
+                Some(closes.iter().rev().take(n).sum::<f64>() / n as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+//稍后提醒,在这段时间内不再触发
+pub fn snooze(stocks: &mut [Stock], notice: &AlertNotice, minutes: i64) {
+    if let Some(alert) = stocks
+        .iter_mut()
+        .find(|stock| stock.code == notice.code)
+        .and_then(|stock| stock.alerts.get_mut(notice.index?))
+    {
+        alert.snooze_until = Some(Local::now() + Duration::minutes(minutes));
+        //once模式稍后提醒时需要重新启用
+        alert.disabled = false;
+    }
+}
+
+//名称等数据来自接口,不能直接拼进命令行,通过环境变量传给shell,由shell展开
+fn run_exec(template: &str, stock: &Stock) {
+    const VARS: [(&str, &str); 4] = [
+        ("{code}", "STOCK_CODE"),
+        ("{title}", "STOCK_TITLE"),
+        ("{price}", "STOCK_PRICE"),
+        ("{percent}", "STOCK_PERCENT"),
+    ];
+    //cmd的%VAR%展开后还会解释&和|,用/V:ON延迟展开的!VAR!才安全
+    let cmd = VARS
+        .iter()
+        .fold(template.to_string(), |cmd, (placeholder, name)| {
+            let var = if cfg!(windows) {
+                format!("!{}!", name)
+            } else {
+                format!("\"${}\"", name)
+            };
+            cmd.replace(placeholder, &var)
+        });
+    tracing::debug!("执行提醒命令 {}", cmd);
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/V:ON", "/C"]).arg(cmd);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd);
+        command
+    };
+    command
+        .env("STOCK_CODE", &stock.code)
+        .env("STOCK_TITLE", &stock.title)
+        .env("STOCK_PRICE", stock.price.to_string())
+        .env("STOCK_PERCENT", format!("{:.2}", stock.percent * 100.0));
+    //输出会破坏TUI界面,全部丢掉;单独开线程等待,不阻塞刷新
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    thread::spawn(move || {
+        if let Err(err) = command.status() {
+            tracing::error!("执行提醒命令失败: {}", err);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Candle, Fundamentals};
+
+    //20根收盘价从1到20的日K线,现价21,当天的K线还没有
+    fn stock() -> Stock {
+        let mut stock = Stock::new(&String::from("0600519"));
+        stock.price = 21.0;
+        stock.turnover = 1e8;
+        stock.time = String::from("2024/01/31 15:00:00");
+        stock.candles = (1..=20)
+            .map(|i| Candle {
+                date: format!("2024-01-{:02}", i),
+                open: i as f64,
+                close: i as f64,
+                high: i as f64,
+                low: i as f64,
+                volume: 1000.0,
+            })
+            .collect();
+        stock.fundamentals = Some(Fundamentals {
+            pe: 30.0,
+            float_cap: 1e10,
+            ..Default::default()
+        });
+        stock
+    }
+
+    #[test]
+    fn ma_uses_candles() {
+        let mut stock = stock();
+        //最近20个收盘价是2到20加上现价21
+        assert_eq!(expr::eval("ma(20)", &stock), Ok(11.5));
+        assert_eq!(expr::eval("ma(1)", &stock), Ok(21.0));
+        assert!(expr::eval("ma(22)", &stock).is_err());
+        //当天的K线用现价代替
+        stock.candles.last_mut().unwrap().date = String::from("2024-01-31");
+        assert_eq!(expr::eval("ma(2)", &stock), Ok(20.0));
+        //刷新的历史价格不影响均线
+        stock.history.extend([100.0, 200.0]);
+        assert_eq!(expr::eval("ma(2)", &stock), Ok(20.0));
+    }
+
+    #[test]
+    fn turnover_and_fundamentals() {
+        let mut stock = stock();
+        assert_eq!(expr::eval("turnover_rate", &stock), Ok(1.0));
+        assert_eq!(expr::eval("volume", &stock), Ok(1000.0));
+        assert_eq!(expr::eval("pe", &stock), Ok(30.0));
+        assert_eq!(
+            expr::eval("price > ma(20) && turnover > 0.05", &stock),
+            Ok(1.0)
+        );
+        stock.price = 10.0;
+        assert_eq!(
+            expr::eval("price > ma(20) && turnover > 0.05", &stock),
+            Ok(0.0)
+        );
+        stock.fundamentals = None;
+        assert!(expr::eval("pe < 40", &stock).is_err());
+    }
+}
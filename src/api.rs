@@ -0,0 +1,2630 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::TryFrom,
+    env, fmt,
+    io::Read,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use http_req::{request::Request, uri::Uri};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    market::{self, Market},
+    notify, proxy, AhPair, BlockTrade, Board, Breadth, Candle, Dividend, Fundamentals, Margin,
+    MoneyFlow, News, Profile, Rating, Report, Stock, Tick,
+};
+
+//接口返回的一个代码的行情,percent是小数,没有的字段是0或空
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quote {
+    pub name: String,
+    pub price: f64,
+    pub percent: f64,
+    pub open: f64,
+    pub yestclose: f64,
+    pub high: f64,
+    pub low: f64,
+    pub turnover: f64,
+    //所在市场的当地时间,格式是2024/01/05 15:00:03
+    pub time: String,
+    //五档买卖盘的价格和数量,从买一/卖一开始,没有挂单的档位不放进来
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+    //基金估值对应的净值日期
+    pub nav_date: String,
+    //哪个接口返回的
+    pub source: String,
+}
+
+impl Quote {
+    //网易格式的对象,缓存里保存的也是这个格式,字段缺失时按0处理,类型不对时返回错误
+    pub fn from_json(value: &Value) -> Result<Quote, String> {
+        let obj = value.as_object().ok_or("返回数据格式错误")?;
+        let num = |key: &str| -> Result<f64, String> {
+            match obj.get(key) {
+                Some(v) => v.as_f64().ok_or(format!("字段{}格式错误", key)),
+                None => Ok(0.0),
+            }
+        };
+        let text = |key: &str| -> Result<String, String> {
+            match obj.get(key) {
+                Some(v) => Ok(v.as_str().ok_or(format!("字段{}格式错误", key))?.to_owned()),
+                None => Ok(String::new()),
+            }
+        };
+        //bid1~bid5和bidvol1~bidvol5,卖盘是ask
+        let book = |side: &str| {
+            orders((1..=5).map_while(|i| {
+                let price = obj.get(&format!("{}{}", side, i))?.as_f64()?;
+                let volume = obj.get(&format!("{}vol{}", side, i))?.as_f64()?;
+                Some((price, volume))
+            }))
+        };
+        Ok(Quote {
+            name: text("name")?,
+            price: num("price")?,
+            percent: num("percent")?,
+            open: num("open")?,
+            yestclose: num("yestclose")?,
+            high: num("high")?,
+            low: num("low")?,
+            turnover: num("turnover")?,
+            time: text("time")?,
+            bids: book("bid"),
+            asks: book("ask"),
+            nav_date: obj
+                .get("nav_date")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_owned(),
+            source: obj
+                .get("source")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_owned(),
+        })
+    }
+}
+
+//价格为0的档位没有挂单
+fn orders(levels: impl Iterator<Item = (f64, f64)>) -> Vec<(f64, f64)> {
+    levels.filter(|(price, _)| *price > 0.0).collect()
+}
+
+//单个代码这次没有取到行情的原因,显示在这个stock上,保留上次的行情
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuoteError {
+    //接口的请求次数用完了,等下次刷新
+    Throttled,
+    //这个代码所在的请求失败了
+    Failed(String),
+}
+
+impl fmt::Display for QuoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuoteError::Throttled => write!(f, "超过请求次数限制,等待下次刷新"),
+            QuoteError::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+//以stock代码为key的行情,找不到的代码不放进去
+pub type Quotes = BTreeMap<String, Result<Quote, QuoteError>>;
+
+//行情来源,启动时按配置里的provider选一个,后台刷新时调用
+//整体失败时返回Err,单个代码没有取到但代码没错的放一个QuoteError
+pub trait DataProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String>;
+    //保存到配置里的名字,备用链是多个
+    fn names(&self) -> Vec<&'static str> {
+        vec![self.name()]
+    }
+    //能不能支撑快速刷新每隔几秒一次的请求,逐个代码请求或者有次数限制的不行
+    fn fast_refresh(&self) -> bool {
+        true
+    }
+}
+
+//这些代码不管配置的是哪个行情来源,都从固定的接口取
+const ROUTES: &[(&str, &dyn DataProvider)] = &[
+    ("CRYPTO_", &Binance),
+    ("FX_", &Forex),
+    ("FUND_", &Fund),
+    ("CMDTY_", &Commodity),
+    ("BJ_", &Beijing),
+];
+
+//每次请求最多带这么多代码,太长的URL会被服务器截断或拒绝
+const BATCH_SIZE: usize = 50;
+
+//加密货币、外汇、基金和商品按前缀交给各自的接口,其余的交给provider,结果合在一起
+pub fn fetch(provider: &dyn DataProvider, codes: &[String]) -> Result<Quotes, String> {
+    let mut others = codes.to_vec();
+    let mut routed = vec![];
+    for (prefix, route) in ROUTES {
+        let (matched, rest): (Vec<String>, Vec<String>) = others
+            .into_iter()
+            .partition(|code| code.starts_with(prefix));
+        others = rest;
+        if !matched.is_empty() {
+            routed.push((*route, matched));
+        }
+    }
+    let mut quotes = fetch_batches(provider, &others)?;
+    tag_source(&mut quotes, provider.name());
+    //这些接口请求失败不影响其他行情
+    for (route, codes) in routed {
+        match route.fetch(&codes) {
+            Ok(mut routed) => {
+                tag_source(&mut routed, route.name());
+                quotes.extend(routed);
+            }
+            Err(err) => {
+                tracing::error!("请求{}行情失败: {}", route.name(), err);
+                for code in codes {
+                    quotes.insert(code, Err(QuoteError::Failed(err.clone())));
+                }
+            }
+        }
+    }
+    Ok(quotes)
+}
+
+//代码多时分批依次请求,部分批次失败时这些代码记上错误,全部失败才算整体失败
+fn fetch_batches(provider: &dyn DataProvider, codes: &[String]) -> Result<Quotes, String> {
+    let mut quotes = Quotes::new();
+    let mut last_err = None;
+    let mut failed = 0;
+    for batch in codes.chunks(BATCH_SIZE) {
+        match with_retry(|| provider.fetch(batch)) {
+            Ok(batch) => quotes.extend(batch),
+            Err(err) => {
+                tracing::error!("请求{}个代码的行情失败: {}", batch.len(), err);
+                for code in batch {
+                    quotes.insert(code.clone(), Err(QuoteError::Failed(err.clone())));
+                }
+                failed += 1;
+                last_err = Some(err);
+            }
+        }
+    }
+    match last_err {
+        Some(err) if failed == codes.chunks(BATCH_SIZE).len() => Err(err),
+        _ => Ok(quotes),
+    }
+}
+
+//网络抖动时的重试次数
+const MAX_RETRIES: u32 = 2;
+
+//断网、连接重置、5xx这类临时错误等一会儿再试,等待时间翻倍并加上随机的抖动,
+//被限流和4xx重试也没用,直接返回
+fn with_retry<T>(mut fetch: impl FnMut() -> Result<T, String>) -> Result<T, String> {
+    let mut attempt = 0;
+    loop {
+        match fetch() {
+            Err(err)
+                if attempt < MAX_RETRIES
+                    && !err.starts_with(THROTTLED)
+                    && !err.starts_with("服务器返回错误: 4") =>
+            {
+                //没有随机数库,用当前时间的毫秒部分做抖动
+                let jitter = Utc::now().timestamp_subsec_millis() as u64 % 500;
+                let wait = Duration::from_millis(1000 * 2u64.pow(attempt) + jitter);
+                tracing::warn!("请求失败,{}毫秒后重试: {}", wait.as_millis(), err);
+                std::thread::sleep(wait);
+                attempt += 1;
+            }
+            ret => return ret,
+        }
+    }
+}
+
+//需要注册的行情接口的key,在.stocks.json的api_keys里配置
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ApiKeys {
+    //没有配置时读环境变量ALPHAVANTAGE_API_KEY
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alphavantage: String,
+}
+
+impl ApiKeys {
+    pub fn is_empty(&self) -> bool {
+        self.alphavantage.is_empty()
+    }
+}
+
+//在行情里记下是哪个接口返回的,已经记过的不覆盖
+fn tag_source(quotes: &mut Quotes, source: &str) {
+    for quote in quotes.values_mut().flatten() {
+        if quote.source.is_empty() {
+            quote.source = source.to_string();
+        }
+    }
+}
+
+//配置里的provider可以是一个名字,也可以是按顺序的名字列表,前面的失败时用后面的
+pub fn provider(config: &Value, keys: &ApiKeys) -> Box<dyn DataProvider> {
+    let names: Vec<&str> = match config {
+        Value::Array(names) => names.iter().filter_map(|name| name.as_str()).collect(),
+        _ => vec![config.as_str().unwrap_or_default()],
+    };
+    if names.len() > 1 {
+        Box::new(Failover(
+            names.iter().map(|name| single(name, keys)).collect(),
+        ))
+    } else {
+        single(names.first().copied().unwrap_or_default(), keys)
+    }
+}
+
+//按名字选行情来源,不认识的名字用默认的网易
+fn single(name: &str, keys: &ApiKeys) -> Box<dyn DataProvider> {
+    match name {
+        "" | "netease" => Box::new(Netease),
+        "sina" => Box::new(Sina),
+        "tencent" => Box::new(Tencent),
+        "yahoo" => Box::new(Yahoo),
+        "alphavantage" => Box::new(AlphaVantage::new(keys)),
+        _ => {
+            tracing::warn!("不认识的行情来源{},使用网易", name);
+            Box::new(Netease)
+        }
+    }
+}
+
+//备用链,依次请求,前一个整体失败或者缺了部分代码时,剩下的代码交给下一个
+pub struct Failover(Vec<Box<dyn DataProvider>>);
+
+impl DataProvider for Failover {
+    fn name(&self) -> &'static str {
+        "failover"
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        self.0
+            .iter()
+            .flat_map(|provider| provider.names())
+            .collect()
+    }
+
+    //前一个失败时会用到后面的,所以要每个都支持
+    fn fast_refresh(&self) -> bool {
+        self.0.iter().all(|provider| provider.fast_refresh())
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let mut quotes = Quotes::new();
+        let mut last_err = None;
+        for provider in self.0.iter() {
+            let rest: Vec<String> = codes
+                .iter()
+                .filter(|code| !quotes.get(*code).map_or(false, |quote| quote.is_ok()))
+                .cloned()
+                .collect();
+            if rest.is_empty() {
+                break;
+            }
+            match provider.fetch(&rest) {
+                Ok(mut fetched) => {
+                    tag_source(&mut fetched, provider.name());
+                    //单个代码的错误先记下,后面的接口取到了会覆盖
+                    quotes.extend(fetched);
+                }
+                Err(err) => {
+                    tracing::warn!("{}请求失败,换下一个: {}", provider.name(), err);
+                    last_err = Some(err);
+                }
+            }
+        }
+        match last_err {
+            Some(err) if quotes.is_empty() => Err(err),
+            _ => Ok(quotes),
+        }
+    }
+}
+
+pub struct Netease;
+
+impl DataProvider for Netease {
+    fn name(&self) -> &'static str {
+        "netease"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let query: Vec<String> = codes.iter().flat_map(|code| netease_codes(code)).collect();
+        let url = format!("http://api.money.126.net/data/feed/{}", query.join(","));
+        tracing::debug!("请求行情 {}", url);
+        let writer = get_with_headers(&url, &[])?;
+        let content = String::from_utf8_lossy(&writer);
+        if !content.starts_with("_ntes_quote_callback") {
+            tracing::error!("服务器返回错误: {}", content);
+            return Err(String::from("服务器返回错误"));
+        }
+        //网易的返回包了一个js call，取第一个(和最后一个)之间的内容剥掉它
+        let body = match (content.find('('), content.rfind(')')) {
+            (Some(start), Some(end)) if start < end => &content[start + 1..end],
+            _ => "",
+        };
+        let json: Map<String, Value> = serde_json::from_str(body).map_err(|err| {
+            tracing::error!("解析返回数据失败: {} {}", err, body);
+            format!("解析返回数据失败: {}", err)
+        })?;
+        //6位数字的代码两个市场都查了,取有结果的那个,记下交易所下次只查一个
+        Ok(codes
+            .iter()
+            .filter_map(|code| {
+                let (query, value) = netease_codes(code)
+                    .into_iter()
+                    .find_map(|query| Some((query.clone(), json.get(&query)?)))?;
+                if market::is_blind(code) {
+                    let market = if query.starts_with('0') { "1" } else { "0" };
+                    remember_secid(code, &format!("{}.{}", market, code));
+                }
+                let quote = Quote::from_json(value).map_err(QuoteError::Failed);
+                Some((code.clone(), quote))
+            })
+            .collect())
+    }
+}
+
+//网易的查询代码,0开头是上海,1开头是深圳,和东方财富的市场编号正好相反
+fn netease_codes(code: &str) -> Vec<String> {
+    match resolved_secid(code) {
+        Some(secid) if market::is_blind(code) => {
+            vec![format!(
+                "{}{}",
+                if secid.starts_with("1.") { "0" } else { "1" },
+                code
+            )]
+        }
+        _ => market::query_codes(code),
+    }
+}
+
+//带请求头的GET,有些接口不带Referer或User-Agent会拒绝
+fn get_with_headers(url: &str, headers: &[(&str, &str)]) -> Result<Vec<u8>, String> {
+    let uri = Uri::try_from(url).map_err(|err| err.to_string())?;
+    let mut writer = Vec::new();
+    //多只stock的返回比较大,压缩后传得快
+    let mut headers = headers.to_vec();
+    if !headers
+        .iter()
+        .any(|(key, _)| key.eq_ignore_ascii_case("Accept-Encoding"))
+    {
+        headers.push(("Accept-Encoding", "gzip, deflate"));
+    }
+    //配置了代理时自己建连接,http_req本身不支持代理
+    let res = if let Some(proxy) = proxy::current(uri.scheme(), uri.host().unwrap_or_default()) {
+        proxy::get(&proxy, &uri, &headers, &mut writer)?
+    } else {
+        let mut req = Request::new(&uri);
+        for (key, value) in headers.iter() {
+            req.header(key, value);
+        }
+        req.send(&mut writer).map_err(|err| format!("{:?}", err))?
+    };
+    check_status(u16::from(res.status_code()))?;
+    let encoding = res
+        .headers()
+        .get("Content-Encoding")
+        .map(|encoding| encoding.to_lowercase())
+        .unwrap_or_default();
+    decompress(writer, &encoding)
+}
+
+//按Content-Encoding解压,有的镜像不带这个头也返回gzip,按开头的魔数判断
+fn decompress(body: Vec<u8>, encoding: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let ret = if encoding.contains("gzip") || body.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(&body[..]).read_to_end(&mut out)
+    } else if encoding.contains("deflate") {
+        //deflate按标准应该带zlib头,但有的服务器直接返回裸的deflate数据
+        ZlibDecoder::new(&body[..])
+            .read_to_end(&mut out)
+            .or_else(|_| {
+                out.clear();
+                DeflateDecoder::new(&body[..]).read_to_end(&mut out)
+            })
+    } else {
+        return Ok(body);
+    };
+    ret.map_err(|err| format!("解压返回数据失败: {}", err))?;
+    Ok(out)
+}
+
+//被限流时的错误以THROTTLED开头,刷新线程据此退避
+pub const THROTTLED: &str = "请求太频繁被限流";
+
+fn check_status(status: u16) -> Result<(), String> {
+    match status {
+        200..=299 => Ok(()),
+        429 | 403 => Err(format!("{}: {}", THROTTLED, status)),
+        _ => Err(format!("服务器返回错误: {}", status)),
+    }
+}
+
+//刷新限速:两次请求至少间隔MIN_GAP,被限流后按2的指数退避,最长MAX_BACKOFF
+#[derive(Default)]
+pub struct Throttle {
+    last: Option<Instant>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Throttle {
+    const MIN_GAP: Duration = Duration::from_secs(2);
+    const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+    //现在能不能发请求,能的话记下这次请求的时间
+    pub fn acquire(&mut self) -> bool {
+        if self.remaining().is_some() {
+            return false;
+        }
+        if self
+            .last
+            .map_or(false, |last| last.elapsed() < Self::MIN_GAP)
+        {
+            return false;
+        }
+        self.last = Some(Instant::now());
+        true
+    }
+
+    //被限流了,返回要等多久
+    pub fn throttled(&mut self) -> Duration {
+        let wait = (Duration::from_secs(5) * 2u32.pow(self.failures.min(6))).min(Self::MAX_BACKOFF);
+        self.failures += 1;
+        self.retry_at = Some(Instant::now() + wait);
+        wait
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    //退避还剩多久
+    pub fn remaining(&self) -> Option<Duration> {
+        self.retry_at
+            .and_then(|at| at.checked_duration_since(Instant::now()))
+            .filter(|left| !left.is_zero())
+    }
+
+    //退避结束了该重试,只返回一次true
+    pub fn retry_due(&mut self) -> bool {
+        if self.retry_at.is_some() && self.remaining().is_none() {
+            self.retry_at = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn num(fields: &[&str], index: usize) -> f64 {
+    fields
+        .get(index)
+        .and_then(|field| field.trim().parse().ok())
+        .unwrap_or(0.0)
+}
+
+//新浪的接口,网易被限制时可以在配置里改用,返回GBK编码的js变量
+//var hq_str_sh600000="浦发银行,今开,昨收,现价,最高,最低,...";
+pub struct Sina;
+
+impl Sina {
+    //沪深是sh/sz加6位数字,港股是hk加5位数字,美股是gb_加小写代码
+    fn symbols(code: &str) -> Vec<String> {
+        if market::is_blind(code) {
+            return vec![format!("sh{}", code), format!("sz{}", code)];
+        }
+        let stock = Stock::new(&code.to_string());
+        match stock.market() {
+            Market::SH => vec![format!("sh{}", &code[1..])],
+            Market::SZ => vec![format!("sz{}", &code[1..])],
+            Market::HK => vec![format!("hk{}", &code[2..])],
+            Market::US => vec![format!("gb_{}", code[3..].to_lowercase())],
+            Market::BJ => vec![format!("bj{}", &code[3..])],
+            Market::Crypto | Market::Forex | Market::Fund | Market::Commodity | Market::Other => {
+                vec![code.to_lowercase()]
+            }
+        }
+    }
+
+    //各个市场的字段顺序不一样,转成和网易一样的格式
+    fn quote(symbol: &str, fields: &[&str]) -> Option<Quote> {
+        if symbol.starts_with("hk") {
+            //英文名,中文名,今开,昨收,最高,最低,现价,涨跌,涨跌幅%,买,卖,成交额,成交量,...,日期,时间
+            let time = fields.get(18)?;
+            let time = if time.len() == 5 {
+                format!("{}:00", time)
+            } else {
+                time.to_string()
+            };
+            Some(Quote {
+                name: fields.get(1)?.to_string(),
+                open: num(fields, 2),
+                yestclose: num(fields, 3),
+                high: num(fields, 4),
+                low: num(fields, 5),
+                price: num(fields, 6),
+                percent: num(fields, 8) / 100.0,
+                turnover: num(fields, 11),
+                time: format!("{} {}", fields.get(17)?, time),
+                ..Default::default()
+            })
+        } else if symbol.starts_with("gb_") {
+            //名称,现价,涨跌幅%,北京时间,涨跌,今开,最高,最低,...,第26个是昨收
+            //时间是北京时间,转成美东时间和网易保持一致
+            let beijing =
+                NaiveDateTime::parse_from_str(fields.get(3)?, "%Y-%m-%d %H:%M:%S").ok()?;
+            let beijing = FixedOffset::east_opt(8 * 3600)?
+                .from_local_datetime(&beijing)
+                .single()?;
+            let local = beijing.with_timezone(&Market::US.utc_offset(beijing.date_naive()));
+            Some(Quote {
+                name: fields.first()?.to_string(),
+                price: num(fields, 1),
+                percent: num(fields, 2) / 100.0,
+                open: num(fields, 5),
+                high: num(fields, 6),
+                low: num(fields, 7),
+                yestclose: num(fields, 26),
+                time: local.format("%Y/%m/%d %H:%M:%S").to_string(),
+                ..Default::default()
+            })
+        } else {
+            //名称,今开,昨收,现价,最高,最低,买,卖,成交量,成交额,买一量,买一价...卖五量,卖五价,日期,时间
+            let yestclose = num(fields, 2);
+            let price = num(fields, 3);
+            let percent = if yestclose > 0.0 && price > 0.0 {
+                price / yestclose - 1.0
+            } else {
+                0.0
+            };
+            Some(Quote {
+                name: fields.first()?.to_string(),
+                open: num(fields, 1),
+                yestclose,
+                price,
+                high: num(fields, 4),
+                low: num(fields, 5),
+                percent,
+                turnover: num(fields, 9),
+                time: format!("{} {}", fields.get(30)?.replace('-', "/"), fields.get(31)?),
+                bids: orders((0..5).map(|i| (num(fields, 11 + i * 2), num(fields, 10 + i * 2)))),
+                asks: orders((0..5).map(|i| (num(fields, 21 + i * 2), num(fields, 20 + i * 2)))),
+                ..Default::default()
+            })
+        }
+    }
+
+    //代码不存在时返回空字符串,这里不放进结果
+    fn parse(content: &str) -> BTreeMap<String, Quote> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let (symbol, rest) = line.trim().strip_prefix("var hq_str_")?.split_once("=\"")?;
+                let fields: Vec<&str> = rest.trim_end_matches("\";").split(',').collect();
+                Some((symbol.to_string(), Sina::quote(symbol, &fields)?))
+            })
+            .collect()
+    }
+}
+
+impl DataProvider for Sina {
+    fn name(&self) -> &'static str {
+        "sina"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let symbols: Vec<String> = codes.iter().flat_map(|code| Sina::symbols(code)).collect();
+        let url = format!("https://hq.sinajs.cn/list={}", symbols.join(","));
+        tracing::debug!("请求行情 {}", url);
+        //不带Referer会返回403
+        let body = get_with_headers(&url, &[("Referer", "https://finance.sina.com.cn")])?;
+        let (content, _, _) = encoding_rs::GBK.decode(&body);
+        let quotes = Sina::parse(&content);
+        Ok(codes
+            .iter()
+            .filter_map(|code| {
+                let quote = Sina::symbols(code)
+                    .iter()
+                    .find_map(|symbol| quotes.get(symbol))?;
+                Some((code.clone(), Ok(quote.clone())))
+            })
+            .collect())
+    }
+}
+
+//腾讯的接口,也是GBK编码的js变量,字段用~分隔
+//v_sh600000="1~浦发银行~600000~现价~昨收~今开~...";
+pub struct Tencent;
+
+impl Tencent {
+    //沪深和新浪一样,港股是hk加5位数字,美股是us加大写代码
+    fn symbols(code: &str) -> Vec<String> {
+        let stock = Stock::new(&code.to_string());
+        match stock.market() {
+            Market::US => vec![format!("us{}", code[3..].to_uppercase())],
+            _ => Sina::symbols(code),
+        }
+    }
+
+    //三个市场的字段位置相同,只是时间格式和成交额单位不一样
+    fn quote(symbol: &str, fields: &[&str]) -> Option<Quote> {
+        //沪深是20240105150003,港股是2024/01/05 16:08:19,美股是2024-01-05 16:00:01
+        let time = fields.get(30)?;
+        let time = if time.len() == 14 && time.chars().all(|c| c.is_ascii_digit()) {
+            NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%S")
+                .ok()?
+                .format("%Y/%m/%d %H:%M:%S")
+                .to_string()
+        } else {
+            time.replace('-', "/")
+        };
+        //沪深的成交额单位是万元
+        let cn = symbol.starts_with("sh") || symbol.starts_with("sz");
+        let turnover = if cn {
+            num(fields, 37) * 10000.0
+        } else {
+            num(fields, 37)
+        };
+        let mut quote = Quote {
+            name: fields.get(1)?.to_string(),
+            price: num(fields, 3),
+            yestclose: num(fields, 4),
+            open: num(fields, 5),
+            percent: num(fields, 32) / 100.0,
+            high: num(fields, 33),
+            low: num(fields, 34),
+            turnover,
+            time,
+            ..Default::default()
+        };
+        //沪深第9个开始是买一价,买一量...卖五价,卖五量,数量单位是手
+        if cn {
+            quote.bids =
+                orders((0..5).map(|i| (num(fields, 9 + i * 2), num(fields, 10 + i * 2) * 100.0)));
+            quote.asks =
+                orders((0..5).map(|i| (num(fields, 19 + i * 2), num(fields, 20 + i * 2) * 100.0)));
+        }
+        Some(quote)
+    }
+
+    //代码不存在时返回v_pv_none_match="1";
+    fn parse(content: &str) -> BTreeMap<String, Quote> {
+        content
+            .split(';')
+            .filter_map(|line| {
+                let (symbol, rest) = line.trim().strip_prefix("v_")?.split_once("=\"")?;
+                let fields: Vec<&str> = rest.trim_end_matches('"').split('~').collect();
+                Some((symbol.to_string(), Tencent::quote(symbol, &fields)?))
+            })
+            .collect()
+    }
+}
+
+impl DataProvider for Tencent {
+    fn name(&self) -> &'static str {
+        "tencent"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let symbols: Vec<String> = codes
+            .iter()
+            .flat_map(|code| Tencent::symbols(code))
+            .collect();
+        let url = format!("https://qt.gtimg.cn/q={}", symbols.join(","));
+        tracing::debug!("请求行情 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let (content, _, _) = encoding_rs::GBK.decode(&body);
+        let quotes = Tencent::parse(&content);
+        Ok(codes
+            .iter()
+            .filter_map(|code| {
+                let quote = Tencent::symbols(code)
+                    .iter()
+                    .find_map(|symbol| quotes.get(symbol))?;
+                Some((code.clone(), Ok(quote.clone())))
+            })
+            .collect())
+    }
+}
+
+//雅虎财经,覆盖日本、欧洲等其他市场,这些代码直接按雅虎的格式输入,比如7203.T、SAP.DE、BTC-USD
+//chart接口一次只能查一个代码,不需要cookie
+pub struct Yahoo;
+
+impl Yahoo {
+    fn symbols(code: &str) -> Vec<String> {
+        if market::is_blind(code) {
+            return vec![format!("{}.SS", code), format!("{}.SZ", code)];
+        }
+        let stock = Stock::new(&code.to_string());
+        match stock.market() {
+            Market::SH => vec![format!("{}.SS", &code[1..])],
+            Market::SZ => vec![format!("{}.SZ", &code[1..])],
+            //港股指数前面加^,股票代码是4位
+            Market::HK if stock.is_index() => vec![format!("^{}", &code[2..])],
+            Market::HK => vec![format!("{:0>4}.HK", code[2..].trim_start_matches('0'))],
+            Market::US => match &code[3..] {
+                "DJI" => vec![String::from("^DJI")],
+                "IXIC" => vec![String::from("^IXIC")],
+                "SPX" => vec![String::from("^GSPC")],
+                symbol => vec![symbol.to_uppercase()],
+            },
+            Market::Forex => vec![format!("{}=X", &code[3..])],
+            Market::BJ => vec![format!("{}.BJ", &code[3..])],
+            Market::Crypto | Market::Fund | Market::Commodity | Market::Other => {
+                vec![code.to_uppercase()]
+            }
+        }
+    }
+
+    fn quote(symbol: &str) -> Result<Quote, String> {
+        let url = format!(
+            "https://query1.finance.yahoo.com/v8/finance/chart/{}?interval=1d&range=1d",
+            symbol
+        );
+        tracing::debug!("请求行情 {}", url);
+        let body = get_with_headers(&url, &[("User-Agent", "Mozilla/5.0")])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        Yahoo::parse(symbol, &json)
+    }
+
+    fn parse(symbol: &str, json: &Value) -> Result<Quote, String> {
+        let result = json
+            .pointer("/chart/result/0")
+            .ok_or(format!("{} 代码不存在", symbol))?;
+        let meta = &result["meta"];
+        let field = |key: &str| meta[key].as_f64().unwrap_or(0.0);
+        let price = field("regularMarketPrice");
+        let yestclose = field("chartPreviousClose");
+        let percent = if yestclose > 0.0 && price > 0.0 {
+            price / yestclose - 1.0
+        } else {
+            0.0
+        };
+        //时间是UTC秒数,加上交易所的时差转成当地时间
+        let offset = FixedOffset::east_opt(meta["gmtoffset"].as_i64().unwrap_or(0) as i32)
+            .ok_or("时区错误")?;
+        let time = Utc
+            .timestamp_opt(meta["regularMarketTime"].as_i64().unwrap_or(0), 0)
+            .single()
+            .map(|time| {
+                time.with_timezone(&offset)
+                    .format("%Y/%m/%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        let name = meta["shortName"]
+            .as_str()
+            .or(meta["longName"].as_str())
+            .unwrap_or(symbol);
+        Ok(Quote {
+            name: name.to_string(),
+            price,
+            yestclose,
+            open: result
+                .pointer("/indicators/quote/0/open/0")
+                .and_then(|open| open.as_f64())
+                .unwrap_or(0.0),
+            high: field("regularMarketDayHigh"),
+            low: field("regularMarketDayLow"),
+            percent,
+            time,
+            ..Default::default()
+        })
+    }
+}
+
+impl DataProvider for Yahoo {
+    fn name(&self) -> &'static str {
+        "yahoo"
+    }
+
+    fn fast_refresh(&self) -> bool {
+        false
+    }
+
+    //逐个请求,单个代码失败只记日志,全部失败才返回错误
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let mut quotes = Quotes::new();
+        let mut last_err = None;
+        for code in codes {
+            for symbol in Yahoo::symbols(code) {
+                match Yahoo::quote(&symbol) {
+                    Ok(quote) => {
+                        quotes.insert(code.clone(), Ok(quote));
+                        break;
+                    }
+                    Err(err) => {
+                        tracing::debug!("{} {}", symbol, err);
+                        last_err = Some(err);
+                    }
+                }
+            }
+        }
+        match last_err {
+            Some(err) if quotes.is_empty() => Err(err),
+            _ => Ok(quotes),
+        }
+    }
+}
+
+//Alpha Vantage,有正式文档的美股接口,需要免费申请key
+//免费key每分钟最多5次请求,每次刷新只请求额度内的代码,其余的等下次刷新
+pub struct AlphaVantage {
+    key: String,
+    requests: Mutex<VecDeque<Instant>>,
+}
+
+const ALPHAVANTAGE_PER_MINUTE: usize = 5;
+
+impl AlphaVantage {
+    pub fn new(keys: &ApiKeys) -> Self {
+        let key = if keys.alphavantage.is_empty() {
+            env::var("ALPHAVANTAGE_API_KEY").unwrap_or_default()
+        } else {
+            keys.alphavantage.clone()
+        };
+        Self {
+            key,
+            requests: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    //只支持美股,沪深港股的代码Alpha Vantage基本查不到
+    fn symbol(code: &str) -> String {
+        code.strip_prefix("US_").unwrap_or(code).to_uppercase()
+    }
+
+    //一分钟内的请求次数还没用完时记下这次请求
+    fn acquire(&self) -> bool {
+        let mut requests = self.requests.lock().unwrap();
+        while requests
+            .front()
+            .map_or(false, |time| time.elapsed() > Duration::from_secs(60))
+        {
+            requests.pop_front();
+        }
+        if requests.len() >= ALPHAVANTAGE_PER_MINUTE {
+            return false;
+        }
+        requests.push_back(Instant::now());
+        true
+    }
+
+    fn quote(&self, symbol: &str) -> Result<Quote, String> {
+        let url = format!(
+            "https://www.alphavantage.co/query?function=GLOBAL_QUOTE&symbol={}&apikey={}",
+            symbol, self.key
+        );
+        tracing::debug!("请求行情 {}", symbol);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        AlphaVantage::parse(symbol, &json)
+    }
+
+    fn parse(symbol: &str, json: &Value) -> Result<Quote, String> {
+        //超过次数限制时返回Note或Information,不是错误码
+        if let Some(note) = json
+            .get("Note")
+            .or(json.get("Information"))
+            .and_then(|note| note.as_str())
+        {
+            tracing::warn!("Alpha Vantage: {}", note);
+            return Err(String::from("超过请求次数限制"));
+        }
+        let quote = json
+            .get("Global Quote")
+            .filter(|quote| quote.get("05. price").is_some())
+            .ok_or("代码不存在")?;
+        let field = |key: &str| {
+            quote[key]
+                .as_str()
+                .and_then(|v| v.trim_end_matches('%').parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+        //只有交易日,没有时间
+        let day = quote["07. latest trading day"].as_str().unwrap_or_default();
+        Ok(Quote {
+            name: symbol.to_string(),
+            open: field("02. open"),
+            high: field("03. high"),
+            low: field("04. low"),
+            price: field("05. price"),
+            yestclose: field("08. previous close"),
+            percent: field("10. change percent") / 100.0,
+            time: format!("{} 16:00:00", day.replace('-', "/")),
+            ..Default::default()
+        })
+    }
+}
+
+impl DataProvider for AlphaVantage {
+    fn name(&self) -> &'static str {
+        "alphavantage"
+    }
+
+    fn fast_refresh(&self) -> bool {
+        false
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        if self.key.is_empty() {
+            return Err(String::from(
+                "没有配置Alpha Vantage的key,在api_keys里设置或设置环境变量ALPHAVANTAGE_API_KEY",
+            ));
+        }
+        let mut quotes = Quotes::new();
+        for code in codes {
+            if !self.acquire() {
+                quotes.insert(code.clone(), Err(QuoteError::Throttled));
+                continue;
+            }
+            match self.quote(&AlphaVantage::symbol(code)) {
+                Ok(quote) => {
+                    quotes.insert(code.clone(), Ok(quote));
+                }
+                Err(err) if err == "代码不存在" => {}
+                Err(err) => {
+                    quotes.insert(code.clone(), Err(QuoteError::Failed(err)));
+                }
+            }
+        }
+        Ok(quotes)
+    }
+}
+
+//币安现货的24小时行情,没有昨收,用24小时前的开盘价代替
+pub struct Binance;
+
+impl Binance {
+    fn quote(ticker: &Value) -> Option<Quote> {
+        let field = |key: &str| ticker[key].as_str().and_then(|v| v.parse::<f64>().ok());
+        let time = Utc
+            .timestamp_millis_opt(ticker["closeTime"].as_i64()?)
+            .single()?;
+        Some(Quote {
+            name: ticker["symbol"].as_str()?.to_string(),
+            price: field("lastPrice")?,
+            open: field("openPrice")?,
+            yestclose: field("openPrice")?,
+            high: field("highPrice")?,
+            low: field("lowPrice")?,
+            percent: field("priceChangePercent")? / 100.0,
+            turnover: field("quoteVolume").unwrap_or(0.0),
+            time: time.format("%Y/%m/%d %H:%M:%S").to_string(),
+            ..Default::default()
+        })
+    }
+
+    //symbols参数是JSON数组,要做URL编码
+    fn tickers(symbols: &[String]) -> Result<Vec<Value>, String> {
+        let list = format!("[\"{}\"]", symbols.join("\",\""));
+        let url = format!(
+            "https://api.binance.com/api/v3/ticker/24hr?symbols={}",
+            list.replace('"', "%22")
+                .replace('[', "%5B")
+                .replace(']', "%5D")
+                .replace(',', "%2C")
+        );
+        tracing::debug!("请求行情 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        json.as_array()
+            .cloned()
+            .ok_or(String::from("返回数据格式错误"))
+    }
+}
+
+impl DataProvider for Binance {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let symbols: Vec<String> = codes
+            .iter()
+            .map(|code| code.trim_start_matches("CRYPTO_").to_string())
+            .collect();
+        //有一个交易对不存在整个请求都会返回400,这时逐个请求,找出不存在的那个
+        let tickers = match Binance::tickers(&symbols) {
+            Ok(tickers) => tickers,
+            Err(_) if symbols.len() > 1 => symbols
+                .iter()
+                .filter_map(|symbol| Binance::tickers(&[symbol.clone()]).ok())
+                .flatten()
+                .collect(),
+            Err(err) => return Err(err),
+        };
+        Ok(codes
+            .iter()
+            .zip(symbols.iter())
+            .filter_map(|(code, symbol)| {
+                let ticker = tickers
+                    .iter()
+                    .find(|ticker| ticker["symbol"].as_str() == Some(symbol))?;
+                Some((code.clone(), Ok(Binance::quote(ticker)?)))
+            })
+            .collect())
+    }
+}
+
+//外汇用雅虎的USDCNY=X,价格保留4位小数,日元等单价低的币种保留3位
+pub struct Forex;
+
+impl DataProvider for Forex {
+    fn name(&self) -> &'static str {
+        "forex"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let mut quotes = Yahoo.fetch(codes)?;
+        for (code, quote) in quotes.iter_mut() {
+            let quote = match quote {
+                Ok(quote) => quote,
+                Err(_) => continue,
+            };
+            let digits = if matches!(&code[code.len() - 3..], "JPY" | "KRW") {
+                3
+            } else {
+                4
+            };
+            let scale = 10f64.powi(digits);
+            for value in [
+                &mut quote.price,
+                &mut quote.open,
+                &mut quote.yestclose,
+                &mut quote.high,
+                &mut quote.low,
+            ] {
+                *value = (*value * scale).round() / scale;
+            }
+        }
+        Ok(quotes)
+    }
+}
+
+//天天基金的盘中估值,一次只能查一个基金
+//jsonpgz({"fundcode":"161725","name":"...","jzrq":"2024-01-04","dwjz":"1.0230","gsz":"1.0310","gszzl":"0.78","gztime":"2024-01-05 15:00"});
+pub struct Fund;
+
+impl Fund {
+    fn quote(code: &str) -> Result<Quote, String> {
+        let url = format!("http://fundgz.1234567.com.cn/js/{}.js", code);
+        tracing::debug!("请求行情 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        Fund::parse(&String::from_utf8_lossy(&body))
+    }
+
+    fn parse(content: &str) -> Result<Quote, String> {
+        //代码不存在时返回jsonpgz();
+        let body = match (content.find('('), content.rfind(')')) {
+            (Some(start), Some(end)) if start + 1 < end => &content[start + 1..end],
+            _ => return Err(String::from("代码不存在")),
+        };
+        let json: Value = serde_json::from_str(body).map_err(|err| err.to_string())?;
+        let field = |key: &str| json[key].as_str().unwrap_or_default();
+        let num = |key: &str| field(key).parse::<f64>().unwrap_or(0.0);
+        Ok(Quote {
+            name: field("name").to_string(),
+            price: num("gsz"),
+            yestclose: num("dwjz"),
+            percent: num("gszzl") / 100.0,
+            time: format!("{}:00", field("gztime").replace('-', "/")),
+            nav_date: field("jzrq").to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+impl DataProvider for Fund {
+    fn name(&self) -> &'static str {
+        "fund"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let mut quotes = Quotes::new();
+        for code in codes {
+            match Fund::quote(code.trim_start_matches("FUND_")) {
+                Ok(quote) => {
+                    quotes.insert(code.clone(), Ok(quote));
+                }
+                Err(err) if err == "代码不存在" => {}
+                Err(err) => {
+                    quotes.insert(code.clone(), Err(QuoteError::Failed(err)));
+                }
+            }
+        }
+        Ok(quotes)
+    }
+}
+
+//商品用东方财富的ulist接口,一次请求所有品种,fltt=2时涨跌幅是百分数
+pub struct Commodity;
+
+//ulist返回的一条行情,商品和北交所的字段一样
+fn ulist_quote(item: &Value) -> Option<Quote> {
+    //没有成交时价格是"-"
+    let field = |key: &str| item[key].as_f64().unwrap_or(0.0);
+    //f124是最新成交的时间戳,按北京时间显示
+    let time = Utc
+        .timestamp_opt(item["f124"].as_i64()?, 0)
+        .single()?
+        .with_timezone(&FixedOffset::east_opt(8 * 3600)?);
+    Some(Quote {
+        name: item["f14"].as_str()?.to_string(),
+        price: field("f2"),
+        percent: field("f3") / 100.0,
+        high: field("f15"),
+        low: field("f16"),
+        open: field("f17"),
+        yestclose: field("f18"),
+        turnover: field("f6"),
+        time: time.format("%Y/%m/%d %H:%M:%S").to_string(),
+        ..Default::default()
+    })
+}
+
+impl DataProvider for Commodity {
+    fn name(&self) -> &'static str {
+        "commodity"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let secids: Vec<(&String, String)> = codes
+            .iter()
+            .filter_map(|code| Some((code, market::commodity(code)?.1.to_string())))
+            .collect();
+        fetch_ulist(&secids)
+    }
+}
+
+//北交所和新三板也用东方财富的ulist接口,字段和商品一样
+pub struct Beijing;
+
+impl DataProvider for Beijing {
+    fn name(&self) -> &'static str {
+        "beijing"
+    }
+
+    fn fetch(&self, codes: &[String]) -> Result<Quotes, String> {
+        let secids: Vec<(&String, String)> = codes
+            .iter()
+            .filter_map(|code| Some((code, guess_secids(code).pop()?)))
+            .collect();
+        fetch_ulist(&secids)
+    }
+}
+
+//一次请求所有的secid,返回的顺序和请求的不一定一样,按f13.f12对应回代码
+fn fetch_ulist(secids: &[(&String, String)]) -> Result<Quotes, String> {
+    if secids.is_empty() {
+        return Ok(Quotes::new());
+    }
+    let url = format!(
+        "https://push2.eastmoney.com/api/qt/ulist.np/get?fltt=2&secids={}&fields=f2,f3,f6,f12,f13,f14,f15,f16,f17,f18,f124",
+        secids
+            .iter()
+            .map(|(_, secid)| secid.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    tracing::debug!("请求东方财富行情 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let diff = match json.pointer("/data/diff").and_then(|d| d.as_array()) {
+        Some(diff) => diff,
+        None => return Ok(Quotes::new()),
+    };
+    Ok(secids
+        .iter()
+        .filter_map(|(code, secid)| {
+            let item = diff.iter().find(|item| {
+                format!(
+                    "{}.{}",
+                    item["f13"],
+                    item["f12"].as_str().unwrap_or_default()
+                ) == *secid
+            })?;
+            Some(((*code).clone(), Ok(ulist_quote(item)?)))
+        })
+        .collect())
+}
+
+//已经确定交易所的代码,key是stock代码,value是东方财富的secid,保存在配置里
+static SECIDS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+pub fn resolved_secid(code: &str) -> Option<String> {
+    SECIDS.lock().unwrap().get(code).cloned()
+}
+
+//请求成功后记下用的是哪个secid,只有一个可能的不用记
+pub fn remember_secid(code: &str, secid: &str) {
+    if guess_secids(code).len() > 1 {
+        SECIDS
+            .lock()
+            .unwrap()
+            .insert(code.to_string(), secid.to_string());
+    }
+}
+
+//确定过的只请求一次,否则依次尝试所有可能的
+fn secids(code: &str) -> Vec<String> {
+    match resolved_secid(code) {
+        Some(secid) => vec![secid],
+        None => guess_secids(code),
+    }
+}
+
+//东方财富的证券代码是市场编号加代码,美股分纳斯达克、纽交所和美交所,不知道是哪个时都试一下
+fn guess_secids(code: &str) -> Vec<String> {
+    if market::is_blind(code) {
+        return vec![format!("1.{}", code), format!("0.{}", code)];
+    }
+    let stock = Stock::new(&code.to_string());
+    match stock.market() {
+        Market::SH => vec![format!("1.{}", &code[1..])],
+        Market::SZ => vec![format!("0.{}", &code[1..])],
+        Market::HK if stock.is_index() => vec![format!("100.{}", &code[2..])],
+        Market::HK => vec![format!("116.{}", &code[2..])],
+        Market::BJ => vec![format!("0.{}", &code[3..])],
+        Market::US => match &code[3..] {
+            "DJI" => vec![String::from("100.DJIA")],
+            //100.NDX是纳斯达克100,综合指数是100.IXIC
+            "IXIC" => vec![String::from("100.IXIC")],
+            "SPX" => vec![String::from("100.SPX")],
+            symbol => ["105", "106", "107"]
+                .iter()
+                .map(|market| format!("{}.{}", market, symbol.to_uppercase()))
+                .collect(),
+        },
+        _ => vec![],
+    }
+}
+
+//最近days天的前复权日K线,从旧到新排列
+pub fn fetch_kline(code: &str, days: usize) -> Result<Vec<Candle>, String> {
+    for secid in secids(code) {
+        let url = format!(
+            "https://push2his.eastmoney.com/api/qt/stock/kline/get?secid={}&fields1=f1,f2,f3&fields2=f51,f52,f53,f54,f55,f56&klt=101&fqt=1&end=20500101&lmt={}",
+            secid, days
+        );
+        tracing::debug!("请求K线 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        //代码不对时data是null
+        let klines = match json.pointer("/data/klines").and_then(|k| k.as_array()) {
+            Some(klines) => klines,
+            None => continue,
+        };
+        remember_secid(code, &secid);
+        return Ok(klines.iter().filter_map(parse_candle).collect());
+    }
+    Err(String::from("不支持或代码不存在"))
+}
+
+//每根K线是"日期,开盘,收盘,最高,最低,成交量"
+fn parse_candle(line: &Value) -> Option<Candle> {
+    let fields: Vec<&str> = line.as_str()?.split(',').collect();
+    Some(Candle {
+        date: fields.first()?.to_string(),
+        open: fields.get(1)?.parse().ok()?,
+        close: fields.get(2)?.parse().ok()?,
+        high: fields.get(3)?.parse().ok()?,
+        low: fields.get(4)?.parse().ok()?,
+        volume: fields.get(5)?.parse().ok()?,
+    })
+}
+
+//当天的分时价格,沪深用网易的接口,港股美股用东方财富的
+pub fn fetch_timeline(code: &str) -> Result<Vec<(String, f64)>, String> {
+    let stock = Stock::new(&code.to_string());
+    if stock.is_cn() {
+        //{"data": [["0930", 价格, 均价, 成交量], ...]}
+        let url = format!("http://img1.money.126.net/data/hs/time/today/{}.json", code);
+        tracing::debug!("请求分时 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        let data = json["data"].as_array().ok_or("返回数据格式错误")?;
+        return Ok(data
+            .iter()
+            .filter_map(|item| {
+                let time = item.get(0)?.as_str()?;
+                let time = format!("{}:{}", time.get(..2)?, time.get(2..)?);
+                Some((time, item.get(1)?.as_f64()?))
+            })
+            .collect());
+    }
+    for secid in secids(code) {
+        let url = format!(
+            "https://push2his.eastmoney.com/api/qt/stock/trends2/get?secid={}&fields1=f1&fields2=f51,f53&iscr=0",
+            secid
+        );
+        tracing::debug!("请求分时 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        //每个点是"2024-01-05 09:31,价格"
+        let trends = match json.pointer("/data/trends").and_then(|t| t.as_array()) {
+            Some(trends) => trends,
+            None => continue,
+        };
+        remember_secid(code, &secid);
+        return Ok(trends
+            .iter()
+            .filter_map(|item| {
+                let (time, price) = item.as_str()?.split_once(',')?;
+                let time = time.split_once(' ').map_or(time, |(_, time)| time);
+                Some((time.to_string(), price.parse().ok()?))
+            })
+            .collect());
+    }
+    Err(String::from("不支持或代码不存在"))
+}
+
+//最近count笔逐笔成交,东方财富的接口,从旧到新排列
+pub fn fetch_ticks(code: &str, count: usize) -> Result<Vec<Tick>, String> {
+    for secid in secids(code) {
+        let url = format!(
+            "https://push2.eastmoney.com/api/qt/stock/details/get?secid={}&fields1=f1,f2,f3,f4&fields2=f51,f52,f53,f54,f55&pos=-{}",
+            secid, count
+        );
+        tracing::debug!("请求逐笔成交 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        let details = match json.pointer("/data/details").and_then(|d| d.as_array()) {
+            Some(details) => details,
+            None => continue,
+        };
+        //每笔是"时间,价格,成交量,笔数,方向",方向1是卖盘,2是买盘,4是中性,沪深的成交量单位是手
+        let lot = if Stock::new(&code.to_string()).is_cn() || market::is_blind(code) {
+            100.0
+        } else {
+            1.0
+        };
+        remember_secid(code, &secid);
+        return Ok(details
+            .iter()
+            .filter_map(|item| {
+                let fields: Vec<&str> = item.as_str()?.split(',').collect();
+                Some(Tick {
+                    time: fields.first()?.to_string(),
+                    price: fields.get(1)?.parse().ok()?,
+                    volume: fields.get(2)?.parse::<f64>().ok()? * lot,
+                    direction: match *fields.get(4)? {
+                        "2" => 1,
+                        "1" => -1,
+                        _ => 0,
+                    },
+                })
+            })
+            .collect());
+    }
+    Err(String::from("不支持或代码不存在"))
+}
+
+//东方财富的个股基本面,fltt=2时返回的是实际数值,没有的数据返回"-"
+pub fn fetch_fundamentals(code: &str) -> Result<Fundamentals, String> {
+    for secid in secids(code) {
+        let url = format!(
+            "https://push2.eastmoney.com/api/qt/stock/get?secid={}&fltt=2&invt=2&fields=f55,f105,f116,f117,f127,f162,f167,f173,f183,f184,f185,f186,f188",
+            secid
+        );
+        tracing::debug!("请求基本面 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        let data = match json.get("data").filter(|data| data.is_object()) {
+            Some(data) => data,
+            None => continue,
+        };
+        let field = |key: &str| data[key].as_f64().unwrap_or(0.0);
+        remember_secid(code, &secid);
+        return Ok(Fundamentals {
+            eps: field("f55"),
+            market_cap: field("f116"),
+            float_cap: field("f117"),
+            pe: field("f162"),
+            pb: field("f167"),
+            roe: field("f173"),
+            revenue: field("f183"),
+            revenue_growth: field("f184"),
+            profit_growth: field("f185"),
+            gross_margin: field("f186"),
+            net_profit: field("f105"),
+            debt_ratio: field("f188"),
+            industry: data["f127"]
+                .as_str()
+                .filter(|industry| *industry != "-")
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+    Err(String::from("不支持或代码不存在"))
+}
+
+//公司资料,行业、上市日期和股本来自行情接口,主营业务来自F10的公司概况,只有沪深有F10
+pub fn fetch_profile(code: &str) -> Result<Profile, String> {
+    let stock = Stock::new(&code.to_string());
+    if stock.is_index() {
+        return Err(String::from("指数没有公司资料"));
+    }
+    for secid in secids(code) {
+        let url = format!(
+            "https://push2.eastmoney.com/api/qt/stock/get?secid={}&fltt=2&invt=2&fields=f84,f85,f127,f189",
+            secid
+        );
+        tracing::debug!("请求公司资料 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        let data = match json.get("data").filter(|data| data.is_object()) {
+            Some(data) => data,
+            None => continue,
+        };
+        remember_secid(code, &secid);
+        let mut profile = parse_profile(data);
+        if stock.is_cn() || market::is_blind(code) {
+            profile.main_business = fetch_main_business(&secid).unwrap_or_else(|err| {
+                tracing::warn!("{} 请求主营业务失败: {}", code, err);
+                String::new()
+            });
+        }
+        return Ok(profile);
+    }
+    Err(String::from("不支持或代码不存在"))
+}
+
+//f189是20010827格式的整数,没有时是"-"
+fn parse_profile(data: &Value) -> Profile {
+    let field = |key: &str| data[key].as_f64().unwrap_or(0.0);
+    Profile {
+        industry: data["f127"]
+            .as_str()
+            .filter(|industry| *industry != "-")
+            .unwrap_or_default()
+            .to_string(),
+        listing_date: data["f189"]
+            .as_i64()
+            .and_then(|date| NaiveDate::parse_from_str(&date.to_string(), "%Y%m%d").ok())
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+        total_shares: field("f84"),
+        float_shares: field("f85"),
+        main_business: String::new(),
+    }
+}
+
+//F10的代码是SH600519这样的格式,主营业务没有时用经营范围
+fn fetch_main_business(secid: &str) -> Result<String, String> {
+    let (market, symbol) = secid.split_once('.').ok_or("代码格式错误")?;
+    let url = format!(
+        "https://emweb.securities.eastmoney.com/PC_HSF10/CompanySurvey/PageAjax?code={}{}",
+        if market == "1" { "SH" } else { "SZ" },
+        symbol
+    );
+    tracing::debug!("请求主营业务 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let info = json.pointer("/jbzl/0").ok_or("返回数据格式错误")?;
+    Ok(["MAIN_BUSINESS", "BUSINESS_SCOPE"]
+        .iter()
+        .find_map(|key| info[*key].as_str().filter(|text| !text.is_empty()))
+        .unwrap_or_default()
+        .trim()
+        .to_string())
+}
+
+//东方财富最近半年的个股研报,统计出现最多的评级和平均目标价,没有研报时返回None
+pub fn fetch_rating(code: &str) -> Result<Option<Rating>, String> {
+    //指数去掉前缀后会和个股代码重复,比如0000001会变成平安银行的000001
+    if Stock::new(&code.to_string()).is_index() {
+        return Err(String::from("指数没有机构评级"));
+    }
+    let code = if market::is_blind(code) {
+        code
+    } else {
+        &code[1..]
+    };
+    let end = Utc::now().date_naive();
+    let begin = end - chrono::Duration::days(182);
+    let url = format!(
+        "https://reportapi.eastmoney.com/report/list?industryCode=*&pageSize=100&industry=*&rating=*&ratingChange=*&beginTime={}&endTime={}&pageNo=1&qType=0&code={}",
+        begin, end, code
+    );
+    tracing::debug!("请求机构评级 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let reports = match json["data"].as_array() {
+        Some(reports) if !reports.is_empty() => reports,
+        _ => return Ok(None),
+    };
+    let mut ratings: Vec<(String, usize)> = vec![];
+    let mut targets = vec![];
+    for report in reports {
+        if let Some(rating) = report["emRatingName"].as_str().filter(|r| !r.is_empty()) {
+            match ratings.iter_mut().find(|(r, _)| r == rating) {
+                Some((_, count)) => *count += 1,
+                None => ratings.push((rating.to_string(), 1)),
+            }
+        }
+        //目标价是一个区间时取中间值,接口返回的可能是字符串
+        let price = |key: &str| {
+            report[key]
+                .as_str()
+                .and_then(|p| p.parse::<f64>().ok())
+                .or(report[key].as_f64())
+                .filter(|p| *p > 0.0)
+        };
+        match (price("indvAimPriceT"), price("indvAimPriceL")) {
+            (Some(high), Some(low)) => targets.push((high + low) / 2.0),
+            (Some(price), None) | (None, Some(price)) => targets.push(price),
+            (None, None) => {}
+        }
+    }
+    let rating = match ratings.iter().max_by_key(|(_, count)| *count) {
+        Some((rating, _)) => rating.clone(),
+        None => return Ok(None),
+    };
+    Ok(Some(Rating {
+        rating,
+        target: if targets.is_empty() {
+            0.0
+        } else {
+            targets.iter().sum::<f64>() / targets.len() as f64
+        },
+        reports: reports.len(),
+    }))
+}
+
+//数据中心一次查询的代码个数,结果最多取一页500条
+const DATACENTER_BATCH: usize = 50;
+
+//数据中心的接口用不带市场前缀的代码
+fn datacenter_symbol(code: &str) -> &str {
+    if market::is_blind(code) {
+        code
+    } else {
+        &code[1..]
+    }
+}
+
+//按代码批量查询东方财富数据中心的报表,每批一个请求,依次请求,不会同时发出很多请求
+//返回每个代码的记录,没有记录的是空的,记录按sort倒序
+fn fetch_datacenter(
+    report: &str,
+    columns: &str,
+    filter: &str,
+    sort: &str,
+    codes: &[String],
+) -> Result<Vec<(String, Vec<Value>)>, String> {
+    fetch_datacenter_by("SECURITY_CODE", report, columns, filter, sort, codes)
+}
+
+//有的报表代码列不叫SECURITY_CODE,比如融资融券的是SCODE
+fn fetch_datacenter_by(
+    key: &str,
+    report: &str,
+    columns: &str,
+    filter: &str,
+    sort: &str,
+    codes: &[String],
+) -> Result<Vec<(String, Vec<Value>)>, String> {
+    let mut result = vec![];
+    for batch in codes.chunks(DATACENTER_BATCH) {
+        let symbols: Vec<_> = batch
+            .iter()
+            .map(|code| format!("%22{}%22", datacenter_symbol(code)))
+            .collect();
+        let url = format!(
+            "https://datacenter-web.eastmoney.com/api/data/v1/get?reportName={}&columns={},{}&filter=({}%20in%20({})){}&sortColumns={}&sortTypes=-1&pageSize=500&pageNumber=1",
+            report,
+            key,
+            columns,
+            key,
+            symbols.join(","),
+            filter,
+            sort
+        );
+        tracing::debug!("请求数据中心 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        //没有记录时result是null
+        let data = json
+            .pointer("/result/data")
+            .and_then(|d| d.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for code in batch {
+            let symbol = datacenter_symbol(code);
+            let rows = data
+                .iter()
+                .filter(|row| row[key].as_str() == Some(symbol))
+                .cloned()
+                .collect();
+            result.push((code.clone(), rows));
+        }
+    }
+    Ok(result)
+}
+
+//东方财富数据中心的分红送配,只有沪深的数据,最近的在前面,只取最近3年公告的方案
+pub fn fetch_dividends(codes: &[String]) -> Result<Vec<(String, Vec<Dividend>)>, String> {
+    let since = Utc::now().date_naive() - chrono::Duration::days(3 * 365);
+    let rows = fetch_datacenter(
+        "RPT_SHAREBONUS_DET",
+        "EX_DIVIDEND_DATE,IMPL_PLAN_PROFILE,ASSIGN_PROGRESS",
+        &format!("(PLAN_NOTICE_DATE%3E%3D%27{}%27)", since),
+        "PLAN_NOTICE_DATE",
+        codes,
+    )?;
+    Ok(rows
+        .into_iter()
+        .map(|(code, rows)| {
+            let dividends = rows
+                .iter()
+                .take(20)
+                .map(|item| {
+                    let field = |key: &str| item[key].as_str().unwrap_or_default().to_string();
+                    Dividend {
+                        //"2023-07-20 00:00:00"只取日期
+                        ex_date: field("EX_DIVIDEND_DATE").chars().take(10).collect(),
+                        plan: field("IMPL_PLAN_PROFILE"),
+                        progress: field("ASSIGN_PROGRESS"),
+                    }
+                })
+                .collect();
+            (code, dividends)
+        })
+        .collect())
+}
+
+//东方财富数据中心的大宗交易,只取最近3个月的前50笔
+pub fn fetch_block_trades(code: &str) -> Result<Vec<BlockTrade>, String> {
+    let since = Utc::now().date_naive() - chrono::Duration::days(92);
+    let rows = fetch_datacenter(
+        "RPT_DATA_BLOCKTRADE",
+        "TRADE_DATE,DEAL_PRICE,PREMIUM_RATIO,DEAL_VOLUME,DEAL_AMT,BUYER_NAME,SELLER_NAME",
+        &format!("(TRADE_DATE%3E%3D%27{}%27)", since),
+        "TRADE_DATE",
+        &[code.to_string()],
+    )?;
+    Ok(rows
+        .into_iter()
+        .flat_map(|(_, rows)| rows)
+        .take(50)
+        .map(|item| parse_block_trade(&item))
+        .collect())
+}
+
+//溢价率是小数,0.05表示溢价5%
+fn parse_block_trade(item: &Value) -> BlockTrade {
+    let text = |key: &str| item[key].as_str().unwrap_or_default().to_string();
+    let field = |key: &str| item[key].as_f64().unwrap_or(0.0);
+    BlockTrade {
+        date: text("TRADE_DATE").chars().take(10).collect(),
+        price: field("DEAL_PRICE"),
+        premium: field("PREMIUM_RATIO") * 100.0,
+        volume: field("DEAL_VOLUME"),
+        amount: field("DEAL_AMT"),
+        buyer: text("BUYER_NAME"),
+        seller: text("SELLER_NAME"),
+    }
+}
+
+//东方财富数据中心的融资融券明细,只取最近20个交易日
+pub fn fetch_margins(code: &str) -> Result<Vec<Margin>, String> {
+    let since = Utc::now().date_naive() - chrono::Duration::days(45);
+    let rows = fetch_datacenter_by(
+        "SCODE",
+        "RPTA_WEB_RZRQ_GGMX",
+        "DATE,RZYE,RZMRE,RQYE",
+        &format!("(DATE%3E%3D%27{}%27)", since),
+        "DATE",
+        &[code.to_string()],
+    )?;
+    Ok(rows
+        .into_iter()
+        .flat_map(|(_, rows)| rows)
+        .take(20)
+        .map(|item| {
+            let field = |key: &str| item[key].as_f64().unwrap_or(0.0);
+            Margin {
+                date: item["DATE"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .chars()
+                    .take(10)
+                    .collect(),
+                balance: field("RZYE"),
+                buy: field("RZMRE"),
+                short_balance: field("RQYE"),
+            }
+        })
+        .collect())
+}
+
+//东方财富数据中心的财报预约披露时间,取最近的报告期,已经披露了是None
+pub fn fetch_reports(codes: &[String]) -> Result<Vec<(String, Option<Report>)>, String> {
+    //最近的报告期一定在一年以内
+    let since = Utc::now().date_naive() - chrono::Duration::days(366);
+    let rows = fetch_datacenter(
+        "RPT_PUBLIC_BS_APPOIN",
+        "REPORT_DATE,FIRST_APPOINT_DATE,FIRST_CHANGE_DATE,SECOND_CHANGE_DATE,THIRD_CHANGE_DATE,ACTUAL_PUBLISH_DATE",
+        &format!("(REPORT_DATE%3E%3D%27{}%27)", since),
+        "REPORT_DATE",
+        codes,
+    )?;
+    Ok(rows
+        .into_iter()
+        .map(|(code, rows)| (code, rows.first().and_then(parse_report)))
+        .collect())
+}
+
+fn parse_report(item: &Value) -> Option<Report> {
+    //"2024-03-29 00:00:00"只取日期
+    let field = |key: &str| -> Option<String> {
+        item[key]
+            .as_str()
+            .map(|date| date.chars().take(10).collect())
+            .filter(|date: &String| !date.is_empty())
+    };
+    if field("ACTUAL_PUBLISH_DATE").is_some() {
+        return None;
+    }
+    //变更过的以最后一次变更为准
+    let date = field("THIRD_CHANGE_DATE")
+        .or_else(|| field("SECOND_CHANGE_DATE"))
+        .or_else(|| field("FIRST_CHANGE_DATE"))
+        .or_else(|| field("FIRST_APPOINT_DATE"))?;
+    //报告期按截止月份区分
+    let period = field("REPORT_DATE").unwrap_or_default();
+    let name = match period.get(5..7) {
+        Some("03") => "一季报",
+        Some("06") => "中报",
+        Some("09") => "三季报",
+        _ => "年报",
+    };
+    Some(Report {
+        period: format!("{}{}", period.get(..4).unwrap_or_default(), name),
+        date,
+    })
+}
+
+//东方财富按代码搜索的相关新闻,最新的在前面
+pub fn fetch_news(code: &str) -> Result<Vec<News>, String> {
+    //指数的代码搜出来的是同代码个股的新闻
+    if Stock::new(&code.to_string()).is_index() {
+        return Err(String::from("指数没有新闻"));
+    }
+    //搜索关键字用不带市场前缀的代码
+    let symbol = guess_secids(code)
+        .first()
+        .and_then(|secid| secid.split_once('.'))
+        .map(|(_, symbol)| symbol.to_string())
+        .ok_or("不支持或代码不存在")?;
+    let param = json!({
+        "uid": "",
+        "keyword": symbol,
+        "type": ["cmsArticleWebOld"],
+        "client": "web",
+        "clientType": "web",
+        "clientVersion": "curr",
+        "param": {"cmsArticleWebOld": {
+            "searchScope": "default",
+            "sort": "time",
+            "pageIndex": 1,
+            "pageSize": 20,
+            "preTag": "",
+            "postTag": "",
+        }},
+    });
+    let url = format!(
+        "https://search-api-web.eastmoney.com/search/jsonp?cb=news&param={}",
+        notify::url_encode(&param.to_string())
+    );
+    tracing::debug!("请求新闻 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let content = String::from_utf8_lossy(&body);
+    //返回的是news({...}),去掉外面的函数调用
+    let body = match (content.find('('), content.rfind(')')) {
+        (Some(start), Some(end)) if start < end => &content[start + 1..end],
+        _ => return Err(String::from("返回数据格式错误")),
+    };
+    let json: Value = serde_json::from_str(body).map_err(|err| err.to_string())?;
+    let articles = match json
+        .pointer("/result/cmsArticleWebOld")
+        .and_then(|a| a.as_array())
+    {
+        Some(articles) => articles,
+        None => return Ok(vec![]),
+    };
+    Ok(articles
+        .iter()
+        .filter_map(|item| {
+            Some(News {
+                time: item["date"].as_str()?.to_string(),
+                title: item["title"].as_str()?.to_string(),
+                url: item["url"].as_str()?.to_string(),
+                source: item["mediaName"].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
+}
+
+//东方财富的沪深公司公告,最新的在前面
+pub fn fetch_announcements(code: &str) -> Result<Vec<News>, String> {
+    let symbol = announcement_symbol(code)?;
+    Ok(query_announcements(&[symbol], 20)?
+        .into_iter()
+        .map(|(_, news)| news)
+        .collect())
+}
+
+//一次请求多个代码最近的公告,按发布时间混在一起,只用来检查关键词
+pub fn fetch_recent_announcements(codes: &[String]) -> Result<Vec<(String, News)>, String> {
+    let mut list = vec![];
+    let symbols: BTreeMap<String, String> = codes
+        .iter()
+        .filter_map(|code| Some((announcement_symbol(code).ok()?, code.clone())))
+        .collect();
+    let keys: Vec<String> = symbols.keys().cloned().collect();
+    for batch in keys.chunks(DATACENTER_BATCH) {
+        for (symbol, news) in query_announcements(batch, 100)? {
+            if let Some(code) = symbols.get(&symbol) {
+                list.push((code.clone(), news));
+            }
+        }
+    }
+    Ok(list)
+}
+
+//公告接口用6位代码,指数去掉前缀后会变成个股代码,必须先排除
+fn announcement_symbol(code: &str) -> Result<String, String> {
+    let stock = Stock::new(&code.to_string());
+    if stock.is_index() {
+        Err(String::from("指数没有公告"))
+    } else if market::is_blind(code) {
+        Ok(code.to_string())
+    } else if stock.is_cn() {
+        Ok(code[1..].to_string())
+    } else {
+        Err(String::from("只支持沪深的公告"))
+    }
+}
+
+//返回(6位代码,公告),一条公告涉及多个代码时取请求的第一个
+fn query_announcements(symbols: &[String], size: usize) -> Result<Vec<(String, News)>, String> {
+    let url = format!(
+        "https://np-anotice-stock.eastmoney.com/api/security/ann?sr=-1&page_size={}&page_index=1&ann_type=A&client_source=web&stock_list={}&f_node=0&s_node=0",
+        size,
+        symbols.join(",")
+    );
+    tracing::debug!("请求公告 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let list = match json.pointer("/data/list").and_then(|l| l.as_array()) {
+        Some(list) => list,
+        None => return Ok(vec![]),
+    };
+    Ok(list
+        .iter()
+        .filter_map(|item| parse_announcement(item, symbols))
+        .collect())
+}
+
+fn parse_announcement(item: &Value, symbols: &[String]) -> Option<(String, News)> {
+    let art_code = item["art_code"].as_str()?;
+    //只请求一个代码时不用看codes
+    let symbol = item["codes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c["stock_code"].as_str())
+        .find(|code| symbols.iter().any(|symbol| symbol == code))
+        .map(String::from)
+        .or_else(|| (symbols.len() == 1).then(|| symbols[0].clone()))?;
+    let news = News {
+        time: item["notice_date"].as_str()?.to_string(),
+        title: item["title"].as_str()?.to_string(),
+        url: format!(
+            "https://data.eastmoney.com/notices/detail/{}/{}.html",
+            symbol, art_code
+        ),
+        source: String::new(),
+    };
+    Some((symbol, news))
+}
+
+//东方财富的资金流向,一次请求所有沪深的代码,fltt=2时单位是元
+pub fn fetch_money_flow(codes: &[String]) -> Result<BTreeMap<String, MoneyFlow>, String> {
+    //secid对应的stock代码,还没确定交易所的两个都请求
+    let mut secid_codes = BTreeMap::new();
+    for code in codes {
+        let stock = Stock::new(code);
+        if (stock.is_cn() || market::is_blind(code)) && !stock.is_index() {
+            for secid in secids(code) {
+                secid_codes.insert(secid, code.clone());
+            }
+        }
+    }
+    if secid_codes.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let secids: Vec<&str> = secid_codes.keys().map(|secid| secid.as_str()).collect();
+    let url = format!(
+        "https://push2.eastmoney.com/api/qt/ulist.np/get?fltt=2&secids={}&fields=f12,f13,f62,f66,f72",
+        secids.join(",")
+    );
+    tracing::debug!("请求资金流向 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let diff = match json.pointer("/data/diff").and_then(|d| d.as_array()) {
+        Some(diff) => diff,
+        None => return Ok(BTreeMap::new()),
+    };
+    //停牌等没有数据时返回"-"
+    let amount = |value: &Value| value.as_f64().unwrap_or(0.0);
+    Ok(diff
+        .iter()
+        .filter_map(|item| {
+            let secid = format!("{}.{}", item["f13"].as_i64()?, item["f12"].as_str()?);
+            let code = secid_codes.get(&secid)?;
+            Some((
+                code.clone(),
+                MoneyFlow {
+                    main: amount(&item["f62"]),
+                    super_large: amount(&item["f66"]),
+                    large: amount(&item["f72"]),
+                },
+            ))
+        })
+        .collect())
+}
+
+//A+H两地上市的对照表,key和value分别是A股和H股的代码,两个方向都有,date是请求的日期
+static AH_PAIRS: Mutex<Option<(String, BTreeMap<String, String>)>> = Mutex::new(None);
+
+//东方财富的AH股比价列表,f12是H股代码,f191是对应的A股代码,对照表每天请求一次
+fn ah_pairs() -> Result<BTreeMap<String, String>, String> {
+    let today = Utc::now().date_naive().to_string();
+    if let Some((date, pairs)) = AH_PAIRS.lock().unwrap().as_ref() {
+        if *date == today {
+            return Ok(pairs.clone());
+        }
+    }
+    let url = "https://push2.eastmoney.com/api/qt/clist/get?pn=1&pz=500&po=1&np=1&fltt=2&invt=2&fid=f3&fs=b:DLMK0101&fields=f12,f191";
+    tracing::debug!("请求A+H对照表 {}", url);
+    let body = get_with_headers(url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let diff = json
+        .pointer("/data/diff")
+        .and_then(|d| d.as_array())
+        .ok_or("返回数据格式错误")?;
+    let pairs = parse_ah_pairs(diff);
+    *AH_PAIRS.lock().unwrap() = Some((today, pairs.clone()));
+    Ok(pairs)
+}
+
+//A股6开头的是沪市,0和3开头的是深市
+fn parse_ah_pairs(diff: &[Value]) -> BTreeMap<String, String> {
+    let mut pairs = BTreeMap::new();
+    for item in diff {
+        let (h, a) = match (item["f12"].as_str(), item["f191"].as_str()) {
+            (Some(h), Some(a)) if a.len() == 6 => (format!("hk{}", h), a),
+            _ => continue,
+        };
+        let a = match a.chars().next() {
+            Some('6') => format!("0{}", a),
+            Some('0' | '3') => format!("1{}", a),
+            _ => continue,
+        };
+        pairs.insert(a.clone(), h.clone());
+        pairs.insert(h, a);
+    }
+    pairs
+}
+
+//codes里A+H两地上市的另一边的现价,一次请求所有的另一边
+pub fn fetch_ah_pairs(codes: &[String]) -> Result<BTreeMap<String, AhPair>, String> {
+    let pairs = ah_pairs()?;
+    //secid对应的codes里的代码
+    let mut secid_codes = BTreeMap::new();
+    for code in codes {
+        if let Some(pair) = pairs.get(code) {
+            for secid in guess_secids(pair) {
+                secid_codes.insert(secid, (code.clone(), pair.clone()));
+            }
+        }
+    }
+    if secid_codes.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let secids: Vec<&str> = secid_codes.keys().map(|secid| secid.as_str()).collect();
+    let url = format!(
+        "https://push2.eastmoney.com/api/qt/ulist.np/get?fltt=2&secids={}&fields=f2,f12,f13",
+        secids.join(",")
+    );
+    tracing::debug!("请求A+H行情 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let diff = match json.pointer("/data/diff").and_then(|d| d.as_array()) {
+        Some(diff) => diff,
+        None => return Ok(BTreeMap::new()),
+    };
+    Ok(diff
+        .iter()
+        .filter_map(|item| {
+            let secid = format!("{}.{}", item["f13"].as_i64()?, item["f12"].as_str()?);
+            let (code, pair) = secid_codes.get(&secid)?;
+            //停牌时是"-"
+            let price = item["f2"].as_f64().filter(|price| *price > 0.0)?;
+            Some((
+                code.clone(),
+                AhPair {
+                    code: pair.clone(),
+                    price,
+                },
+            ))
+        })
+        .collect())
+}
+
+//沪深A股的代码范围,包括北交所
+const A_SHARES: &str = "m:0+t:6,m:0+t:80,m:1+t:2,m:1+t:23,m:0+t:81+s:2048";
+//翻页找涨停股时最多请求的页数
+const BREADTH_PAGES: usize = 10;
+
+//涨跌家数是上证指数和深证成指行情里的f104/f105/f106,
+//涨停数用clist按涨幅从高到低翻页,比较现价和涨停价f350,涨幅低于5%后不可能再有涨停
+pub fn fetch_breadth() -> Result<Breadth, String> {
+    let url = "https://push2.eastmoney.com/api/qt/ulist.np/get?fltt=2&secids=1.000001,0.399001&fields=f12,f104,f105,f106";
+    tracing::debug!("请求涨跌家数 {}", url);
+    let body = get_with_headers(url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let diff = json
+        .pointer("/data/diff")
+        .and_then(|d| d.as_array())
+        .ok_or("返回数据格式错误")?;
+    let count = |key: &str| -> u32 {
+        diff.iter()
+            .map(|item| item[key].as_u64().unwrap_or(0) as u32)
+            .sum()
+    };
+    let mut breadth = Breadth {
+        up: count("f104"),
+        down: count("f105"),
+        flat: count("f106"),
+        limit_up: 0,
+    };
+    for page in 1..=BREADTH_PAGES {
+        let url = format!(
+            "https://push2.eastmoney.com/api/qt/clist/get?pn={}&pz=100&po=1&np=1&fltt=2&invt=2&fid=f3&fs={}&fields=f2,f3,f350",
+            page, A_SHARES
+        );
+        tracing::debug!("请求涨停股 {}", url);
+        let body = get_with_headers(&url, &[])?;
+        let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        let diff = match json.pointer("/data/diff").and_then(|d| d.as_array()) {
+            Some(diff) if !diff.is_empty() => diff,
+            _ => break,
+        };
+        let (limit_up, more) = count_limit_up(diff);
+        breadth.limit_up += limit_up;
+        if !more {
+            break;
+        }
+    }
+    Ok(breadth)
+}
+
+//一页里涨停的个数,以及还要不要翻下一页
+fn count_limit_up(diff: &[Value]) -> (u32, bool) {
+    //停牌的现价是"-"
+    let count = diff
+        .iter()
+        .filter(|item| match (item["f2"].as_f64(), item["f350"].as_f64()) {
+            (Some(price), Some(limit)) => limit > 0.0 && (price - limit).abs() < 0.001,
+            _ => false,
+        })
+        .count() as u32;
+    let more = diff
+        .last()
+        .and_then(|item| item["f3"].as_f64())
+        .map_or(false, |percent| percent >= 5.0);
+    (count, more)
+}
+
+//东方财富的板块行情,按涨跌幅从高到低,fltt=2时涨跌幅是百分数
+pub fn fetch_boards(concept: bool) -> Result<Vec<Board>, String> {
+    let url = format!(
+        "https://push2.eastmoney.com/api/qt/clist/get?pn=1&pz=500&po=1&np=1&fltt=2&invt=2&fid=f3&fs=m:90+t:{}&fields=f3,f14,f128,f136",
+        if concept { 3 } else { 2 }
+    );
+    tracing::debug!("请求板块 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let diff = json
+        .pointer("/data/diff")
+        .and_then(|d| d.as_array())
+        .ok_or("返回数据格式错误")?;
+    Ok(diff
+        .iter()
+        .filter_map(|item| {
+            Some(Board {
+                name: item["f14"].as_str()?.to_string(),
+                percent: item["f3"].as_f64()? / 100.0,
+                leader: item["f128"].as_str().unwrap_or_default().to_string(),
+                leader_percent: item["f136"].as_f64().unwrap_or(0.0) / 100.0,
+            })
+        })
+        .collect())
+}
+
+//搜索联想的一个结果,code已经转成网易的格式
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub code: String,
+    pub name: String,
+    //证券类型,比如沪A、港股、美股
+    pub kind: String,
+}
+
+//东方财富的搜索联想,可以按名称、拼音或代码搜索,只保留沪深港美的结果
+pub fn search(keyword: &str) -> Result<Vec<Suggestion>, String> {
+    let url = format!(
+        "https://searchapi.eastmoney.com/api/suggest/get?input={}&type=14&token=D43BF722C8E33BDC906FB84D85E326E8&count=10",
+        notify::url_encode(keyword)
+    );
+    tracing::debug!("搜索 {}", url);
+    let body = get_with_headers(&url, &[])?;
+    let json: Value = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+    let data = match json
+        .pointer("/QuotationCodeTable/Data")
+        .and_then(|d| d.as_array())
+    {
+        Some(data) => data,
+        None => return Ok(vec![]),
+    };
+    Ok(data
+        .iter()
+        .filter_map(|item| {
+            let code = item["Code"].as_str()?;
+            //市场编号和secid里的一样
+            let code = match item["MktNum"].as_str()? {
+                "1" => format!("0{}", code),
+                "0" => format!("1{}", code),
+                "116" => format!("hk{}", code),
+                "105" | "106" | "107" => format!("US_{}", code),
+                _ => return None,
+            };
+            Some(Suggestion {
+                code,
+                name: item["Name"].as_str()?.to_string(),
+                kind: item["SecurityTypeName"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{
+        write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+        Compression,
+    };
+
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn sina_cn() {
+        let content = "var hq_str_sh600000=\"浦发银行,7.030,7.040,7.050,7.080,7.010,7.050,7.060,12345678,87000000.000,100,7.050,200,7.040,300,7.030,400,7.020,500,7.010,600,7.060,700,7.070,800,7.080,900,7.090,1000,7.100,2024-01-05,15:00:03,00,\";\nvar hq_str_sh688999=\"\";\n";
+        let quotes = Sina::parse(content);
+        assert_eq!(quotes.len(), 1);
+        let quote = &quotes["sh600000"];
+        assert_eq!(quote.name, "浦发银行");
+        assert_eq!(quote.open, 7.03);
+        assert_eq!(quote.yestclose, 7.04);
+        assert_eq!(quote.price, 7.05);
+        assert_eq!(quote.high, 7.08);
+        assert_eq!(quote.low, 7.01);
+        assert!(close(quote.percent, 7.05 / 7.04 - 1.0));
+        assert_eq!(quote.turnover, 87000000.0);
+        assert_eq!(quote.time, "2024/01/05 15:00:03");
+        assert_eq!(quote.bids.len(), 5);
+        assert_eq!(quote.bids[0], (7.05, 100.0));
+        assert_eq!(quote.asks[4], (7.1, 1000.0));
+    }
+
+    #[test]
+    fn sina_hk_us() {
+        let content = "var hq_str_hk00700=\"TENCENT,腾讯控股,290.000,288.000,295.000,287.600,294.200,6.200,2.153,294.000,294.200,5123456789.000,17500000,18.540,0.000,0.000,0.000,2024/01/05,16:08\";\nvar hq_str_gb_aapl=\"苹果,181.1800,-0.40,2024-01-06 05:59:48,-0.7300,181.9900,182.7600,180.1700,199.6200,164.0800,62379661,63159233,2817992040000,6.13,29.560000,0.00,0.00,0.96,0.00,0,0,15553809260,181.1800,0.00,0.00,0.00,181.9100,Jan 05 04:00PM EST,0\";\n";
+        let quotes = Sina::parse(content);
+        let hk = &quotes["hk00700"];
+        assert_eq!(hk.name, "腾讯控股");
+        assert_eq!(hk.price, 294.2);
+        assert_eq!(hk.yestclose, 288.0);
+        assert!(close(hk.percent, 0.02153));
+        assert_eq!(hk.turnover, 5123456789.0);
+        assert_eq!(hk.time, "2024/01/05 16:08:00");
+        //北京时间转成美东时间
+        let us = &quotes["gb_aapl"];
+        assert_eq!(us.name, "苹果");
+        assert_eq!(us.price, 181.18);
+        assert!(close(us.percent, -0.004));
+        assert_eq!(us.yestclose, 181.91);
+        assert_eq!(us.time, "2024/01/05 16:59:48");
+    }
+
+    #[test]
+    fn tencent() {
+        let content = "v_sh600000=\"1~浦发银行~600000~7.05~7.04~7.03~123456~60000~63456~7.05~1000~7.04~2000~7.03~3000~7.02~4000~7.01~5000~7.06~1100~7.07~2100~7.08~3100~7.09~4100~7.10~5100~~20240105150003~0.01~0.14~7.08~7.01~7.05/123456/87000000~123456~8700~0.04~5.20~\";\nv_hk00700=\"100~腾讯控股~00700~294.200~288.000~290.000~17500000.0~0~0~294.200~0~0~0~0~0~0~0~0~0~294.400~0~0~0~0~0~0~0~0~0~~2024/01/05 16:08:19~6.200~2.15~295.000~287.600~294.200~17500000.0~5123456789.000~\";\nv_pv_none_match=\"1\";\n";
+        let quotes = Tencent::parse(content);
+        assert_eq!(quotes.len(), 2);
+        let cn = &quotes["sh600000"];
+        assert_eq!(cn.name, "浦发银行");
+        assert_eq!(cn.price, 7.05);
+        assert_eq!(cn.yestclose, 7.04);
+        assert_eq!(cn.open, 7.03);
+        assert!(close(cn.percent, 0.0014));
+        assert_eq!(cn.high, 7.08);
+        assert_eq!(cn.low, 7.01);
+        //成交额是万元,挂单量是手
+        assert_eq!(cn.turnover, 87000000.0);
+        assert_eq!(cn.time, "2024/01/05 15:00:03");
+        assert_eq!(cn.bids[0], (7.05, 100000.0));
+        assert_eq!(cn.asks[0], (7.06, 110000.0));
+        let hk = &quotes["hk00700"];
+        assert_eq!(hk.price, 294.2);
+        assert_eq!(hk.turnover, 5123456789.0);
+        assert_eq!(hk.time, "2024/01/05 16:08:19");
+        assert!(hk.bids.is_empty());
+    }
+
+    #[test]
+    fn yahoo() {
+        let json = json!({"chart": {"result": [{
+            "meta": {
+                "currency": "JPY",
+                "symbol": "7203.T",
+                "regularMarketPrice": 2700.5,
+                "chartPreviousClose": 2650.0,
+                "gmtoffset": 32400,
+                "regularMarketTime": 1704436200,
+                "regularMarketDayHigh": 2710.0,
+                "regularMarketDayLow": 2640.0,
+                "shortName": "TOYOTA MOTOR CORP",
+            },
+            "indicators": {"quote": [{"open": [2660.0]}]},
+        }], "error": null}});
+        let quote = Yahoo::parse("7203.T", &json).unwrap();
+        assert_eq!(quote.name, "TOYOTA MOTOR CORP");
+        assert_eq!(quote.price, 2700.5);
+        assert_eq!(quote.open, 2660.0);
+        assert!(close(quote.percent, 2700.5 / 2650.0 - 1.0));
+        //东京时间
+        assert_eq!(quote.time, "2024/01/05 15:30:00");
+        let missing = json!({"chart": {"result": null, "error": {"code": "Not Found"}}});
+        assert!(Yahoo::parse("XXXX", &missing).is_err());
+    }
+
+    #[test]
+    fn alphavantage() {
+        let json = json!({"Global Quote": {
+            "01. symbol": "IBM",
+            "02. open": "160.9300",
+            "03. high": "162.1500",
+            "04. low": "160.5100",
+            "05. price": "161.1000",
+            "06. volume": "3914467",
+            "07. latest trading day": "2024-01-05",
+            "08. previous close": "160.7200",
+            "09. change": "0.3800",
+            "10. change percent": "0.2364%",
+        }});
+        let quote = AlphaVantage::parse("IBM", &json).unwrap();
+        assert_eq!(quote.price, 161.1);
+        assert_eq!(quote.yestclose, 160.72);
+        assert!(close(quote.percent, 0.002364));
+        assert_eq!(quote.time, "2024/01/05 16:00:00");
+        let note = json!({"Note": "Thank you for using Alpha Vantage!"});
+        assert_eq!(
+            AlphaVantage::parse("IBM", &note),
+            Err(String::from("超过请求次数限制"))
+        );
+        let missing = json!({"Global Quote": {}});
+        assert_eq!(
+            AlphaVantage::parse("XXXX", &missing),
+            Err(String::from("代码不存在"))
+        );
+    }
+
+    #[test]
+    fn binance() {
+        let ticker = json!({
+            "symbol": "BTCUSDT",
+            "priceChange": "543.21",
+            "priceChangePercent": "1.250",
+            "lastPrice": "44000.00",
+            "openPrice": "43456.79",
+            "highPrice": "44500.00",
+            "lowPrice": "43000.00",
+            "quoteVolume": "1234567890.12",
+            "closeTime": 1704412800000u64,
+        });
+        let quote = Binance::quote(&ticker).unwrap();
+        assert_eq!(quote.name, "BTCUSDT");
+        assert_eq!(quote.price, 44000.0);
+        assert_eq!(quote.yestclose, 43456.79);
+        assert!(close(quote.percent, 0.0125));
+        assert_eq!(quote.turnover, 1234567890.12);
+        assert_eq!(quote.time, "2024/01/05 00:00:00");
+        assert!(Binance::quote(&json!({"symbol": "BTCUSDT"})).is_none());
+    }
+
+    #[test]
+    fn fund() {
+        let content = "jsonpgz({\"fundcode\":\"161725\",\"name\":\"招商中证白酒指数(LOF)A\",\"jzrq\":\"2024-01-04\",\"dwjz\":\"1.0230\",\"gsz\":\"1.0310\",\"gszzl\":\"0.78\",\"gztime\":\"2024-01-05 15:00\"});";
+        let quote = Fund::parse(content).unwrap();
+        assert_eq!(quote.name, "招商中证白酒指数(LOF)A");
+        assert_eq!(quote.price, 1.031);
+        assert_eq!(quote.yestclose, 1.023);
+        assert!(close(quote.percent, 0.0078));
+        assert_eq!(quote.time, "2024/01/05 15:00:00");
+        assert_eq!(quote.nav_date, "2024-01-04");
+        assert_eq!(Fund::parse("jsonpgz();"), Err(String::from("代码不存在")));
+    }
+
+    #[test]
+    fn commodity() {
+        let item = json!({"f2": 2050.3, "f3": 0.52, "f6": 123456789.0, "f12": "GC00Y", "f13": 101,
+            "f14": "COMEX黄金", "f15": 2060.0, "f16": 2040.1, "f17": 2045.0, "f18": 2039.7,
+            "f124": 1704412800});
+        let quote = ulist_quote(&item).unwrap();
+        assert_eq!(quote.name, "COMEX黄金");
+        assert_eq!(quote.price, 2050.3);
+        assert!(close(quote.percent, 0.0052));
+        assert_eq!(quote.open, 2045.0);
+        assert_eq!(quote.yestclose, 2039.7);
+        //按北京时间显示
+        assert_eq!(quote.time, "2024/01/05 08:00:00");
+        //没有成交时价格是"-"
+        let idle = json!({"f2": "-", "f3": "-", "f14": "COMEX白银", "f124": 1704412800});
+        assert_eq!(ulist_quote(&idle).unwrap().price, 0.0);
+    }
+
+    #[test]
+    fn decompress_encodings() {
+        let text = "var hq_str_sh600000=\"浦发银行\";".repeat(20);
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(decompress(gzip.clone(), "gzip").unwrap(), text.as_bytes());
+        //没有Content-Encoding时按魔数识别gzip
+        assert_eq!(decompress(gzip, "").unwrap(), text.as_bytes());
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(text.as_bytes()).unwrap();
+        assert_eq!(
+            decompress(zlib.finish().unwrap(), "deflate").unwrap(),
+            text.as_bytes()
+        );
+        //裸的deflate
+        let mut raw = DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(text.as_bytes()).unwrap();
+        assert_eq!(
+            decompress(raw.finish().unwrap(), "deflate").unwrap(),
+            text.as_bytes()
+        );
+        assert_eq!(
+            decompress(text.as_bytes().to_vec(), "").unwrap(),
+            text.as_bytes()
+        );
+        assert!(decompress(vec![0x1f, 0x8b, 0, 0], "gzip").is_err());
+    }
+
+    #[test]
+    fn status() {
+        assert!(check_status(200).is_ok());
+        assert!(check_status(204).is_ok());
+        assert!(check_status(429).unwrap_err().starts_with(THROTTLED));
+        assert!(check_status(403).unwrap_err().starts_with(THROTTLED));
+        assert_eq!(check_status(404), Err(String::from("服务器返回错误: 404")));
+        assert_eq!(check_status(502), Err(String::from("服务器返回错误: 502")));
+    }
+
+    #[test]
+    fn throttle() {
+        let mut throttle = Throttle::default();
+        assert!(throttle.acquire());
+        //两次请求的间隔太短
+        assert!(!throttle.acquire());
+        //退避时间翻倍,最长MAX_BACKOFF
+        assert_eq!(throttle.throttled(), Duration::from_secs(5));
+        assert_eq!(throttle.throttled(), Duration::from_secs(10));
+        assert_eq!(throttle.throttled(), Duration::from_secs(20));
+        for _ in 0..5 {
+            throttle.throttled();
+        }
+        assert_eq!(throttle.throttled(), Throttle::MAX_BACKOFF);
+        assert!(throttle.remaining().is_some());
+        assert!(!throttle.retry_due());
+        throttle.last = None;
+        assert!(!throttle.acquire());
+        //退避结束后只提示一次重试
+        throttle.retry_at = Some(Instant::now());
+        assert!(throttle.retry_due());
+        assert!(!throttle.retry_due());
+        throttle.succeeded();
+        assert_eq!(throttle.failures, 0);
+        assert!(throttle.acquire());
+    }
+
+    #[test]
+    fn retry() {
+        //被限流和4xx不重试
+        let mut calls = 0;
+        let ret: Result<(), String> = with_retry(|| {
+            calls += 1;
+            Err(format!("{}: 429", THROTTLED))
+        });
+        assert!(ret.is_err());
+        assert_eq!(calls, 1);
+        let mut calls = 0;
+        let ret: Result<(), String> = with_retry(|| {
+            calls += 1;
+            Err(String::from("服务器返回错误: 404"))
+        });
+        assert!(ret.is_err());
+        assert_eq!(calls, 1);
+        //临时错误等一会儿再试
+        let mut calls = 0;
+        let ret = with_retry(|| {
+            calls += 1;
+            if calls == 1 {
+                Err(String::from("服务器返回错误: 503"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(ret, Ok(2));
+    }
+
+    #[test]
+    fn report() {
+        let item = json!({
+            "REPORT_DATE": "2024-03-31 00:00:00",
+            "FIRST_APPOINT_DATE": "2024-04-20 00:00:00",
+            "FIRST_CHANGE_DATE": "2024-04-27 00:00:00",
+            "SECOND_CHANGE_DATE": null,
+            "THIRD_CHANGE_DATE": null,
+            "ACTUAL_PUBLISH_DATE": null,
+        });
+        let report = parse_report(&item).unwrap();
+        assert_eq!(report.period, "2024一季报");
+        assert_eq!(report.date, "2024-04-27");
+        let annual = json!({
+            "REPORT_DATE": "2023-12-31 00:00:00",
+            "FIRST_APPOINT_DATE": "2024-03-29 00:00:00",
+        });
+        assert_eq!(parse_report(&annual).unwrap().period, "2023年报");
+        //已经披露了
+        let published = json!({
+            "REPORT_DATE": "2023-09-30 00:00:00",
+            "FIRST_APPOINT_DATE": "2023-10-28 00:00:00",
+            "ACTUAL_PUBLISH_DATE": "2023-10-28 00:00:00",
+        });
+        assert!(parse_report(&published).is_none());
+    }
+
+    #[test]
+    fn announcement() {
+        let item = json!({
+            "art_code": "AN202401051234567890",
+            "codes": [{"stock_code": "600000"}, {"stock_code": "000001"}],
+            "notice_date": "2024-01-05 00:00:00",
+            "title": "关于召开临时股东大会的公告",
+        });
+        let symbols = vec![String::from("000001"), String::from("600519")];
+        let (symbol, news) = parse_announcement(&item, &symbols).unwrap();
+        assert_eq!(symbol, "000001");
+        assert_eq!(news.title, "关于召开临时股东大会的公告");
+        assert_eq!(
+            news.url,
+            "https://data.eastmoney.com/notices/detail/000001/AN202401051234567890.html"
+        );
+        assert!(
+            parse_announcement(&item, &[String::from("600519"), String::from("000002")]).is_none()
+        );
+        //只请求一个代码时不看codes
+        let item = json!({
+            "art_code": "AN202401051234567891",
+            "notice_date": "2024-01-05 00:00:00",
+            "title": "年度报告",
+        });
+        let (symbol, _) = parse_announcement(&item, &[String::from("600519")]).unwrap();
+        assert_eq!(symbol, "600519");
+    }
+
+    #[test]
+    fn breadth_paging() {
+        let page = json!([
+            {"f2": 11.0, "f3": 10.0, "f350": 11.0},
+            {"f2": "-", "f3": "-", "f350": 12.1},
+            {"f2": 22.02, "f3": 20.0, "f350": 22.02},
+            {"f2": 5.5, "f3": 6.0, "f350": 6.05},
+        ]);
+        assert_eq!(count_limit_up(page.as_array().unwrap()), (2, true));
+        //涨幅低于5%后不用再翻页
+        let last = json!([
+            {"f2": 9.9, "f3": 5.2, "f350": 10.4},
+            {"f2": 3.1, "f3": 4.9, "f350": 3.26},
+        ]);
+        assert_eq!(count_limit_up(last.as_array().unwrap()), (0, false));
+        assert_eq!(count_limit_up(&[]), (0, false));
+    }
+
+    #[test]
+    fn kline() {
+        let candle = parse_candle(&json!("2024-01-05,7.03,7.05,7.08,7.01,123456")).unwrap();
+        assert_eq!(candle.date, "2024-01-05");
+        assert_eq!(candle.open, 7.03);
+        assert_eq!(candle.close, 7.05);
+        assert_eq!(candle.high, 7.08);
+        assert_eq!(candle.low, 7.01);
+        assert_eq!(candle.volume, 123456.0);
+        assert!(parse_candle(&json!("2024-01-05,-,7.05,7.08,7.01,123456")).is_none());
+        assert!(parse_candle(&json!("2024-01-05,7.03")).is_none());
+    }
+
+    #[test]
+    fn ah_pairs() {
+        let diff = json!([
+            {"f12": "00939", "f191": "601939"},
+            {"f12": "01211", "f191": "002594"},
+            {"f12": "00001", "f191": "-"},
+        ]);
+        let pairs = parse_ah_pairs(diff.as_array().unwrap());
+        assert_eq!(pairs.len(), 4);
+        assert_eq!(pairs["hk00939"], "0601939");
+        assert_eq!(pairs["0601939"], "hk00939");
+        assert_eq!(pairs["hk01211"], "1002594");
+        assert_eq!(pairs["1002594"], "hk01211");
+    }
+
+    #[test]
+    fn block_trade() {
+        let item = json!({
+            "TRADE_DATE": "2024-01-05 00:00:00",
+            "DEAL_PRICE": 7.0,
+            "PREMIUM_RATIO": -0.0071,
+            "DEAL_VOLUME": 1000000.0,
+            "DEAL_AMT": 7000000.0,
+            "BUYER_NAME": "机构专用",
+            "SELLER_NAME": "中信证券股份有限公司上海分公司",
+        });
+        let trade = parse_block_trade(&item);
+        assert_eq!(trade.date, "2024-01-05");
+        assert_eq!(trade.price, 7.0);
+        assert!(close(trade.premium, -0.71));
+        assert_eq!(trade.amount, 7000000.0);
+        assert_eq!(trade.buyer, "机构专用");
+    }
+}
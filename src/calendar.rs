@@ -0,0 +1,86 @@
+use chrono::{Local, NaiveDate, Utc};
+
+use crate::Stock;
+
+//除权除息日和财报披露日导出成iCalendar格式,都是全天事件,已经过去的不导出
+//返回文件内容和事件个数
+pub fn ics(stocks: &[Stock]) -> (String, usize) {
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut events = vec![];
+    for stock in stocks {
+        for dividend in stock
+            .dividends
+            .iter()
+            .filter(|dividend| !dividend.ex_date.is_empty() && dividend.ex_date >= today)
+        {
+            events.push((
+                format!("{}-dividend-{}", stock.code, dividend.ex_date),
+                dividend.ex_date.clone(),
+                format!("{} 除权除息", stock.title),
+                format!("{} {}", dividend.plan, dividend.progress),
+            ));
+        }
+        if let Some(report) = stock.next_report() {
+            events.push((
+                format!("{}-report-{}", stock.code, report.date),
+                report.date.clone(),
+                format!("{} 披露{}", stock.title, report.period),
+                String::new(),
+            ));
+        }
+    }
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        format!("PRODID:-//stock//{}//CN", env!("CARGO_PKG_VERSION")),
+        String::from("CALSCALE:GREGORIAN"),
+    ];
+    let mut count = 0;
+    for (uid, date, summary, description) in events {
+        let date = match NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            Ok(date) => date,
+            Err(_) => continue,
+        };
+        lines.push(String::from("BEGIN:VEVENT"));
+        lines.push(format!("UID:{}@stock", uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+        lines.push(format!(
+            "DTEND;VALUE=DATE:{}",
+            date.succ_opt().unwrap_or(date).format("%Y%m%d")
+        ));
+        lines.push(format!("SUMMARY:{}", escape(&summary)));
+        if !description.trim().is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape(description.trim())));
+        }
+        lines.push(String::from("END:VEVENT"));
+        count += 1;
+    }
+    lines.push(String::from("END:VCALENDAR"));
+    let content: String = lines.iter().map(|line| fold(line) + "\r\n").collect();
+    (content, count)
+}
+
+//文本里的逗号、分号、反斜杠和换行要转义
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+//每行不能超过75个字节,超过的折到下一行,续行以空格开头,不能把一个汉字拆开
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
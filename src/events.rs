@@ -1,12 +1,49 @@
-use crossterm::event::{Event, KeyCode, MouseEventKind};
+use std::time::{Duration, Instant};
 
-use crate::{App, AppState, Stock};
+use crossterm::event::{Event, KeyCode, KeyModifiers, MouseEventKind};
+
+use crate::{
+    alert, mark_stale, market, open_url, paper::Side, widget, App, AppState, DetailTab, ListRow,
+    Replay, Stock, Tab, MAX_PINNED,
+};
 
 //处理键盘、鼠标事件
 pub fn on_events(event: Event, app: &mut App) {
+    let selected = app.stocks_state.selected();
+    handle_event(event, app);
+    //换了stock后新闻从第一条开始
+    if app.stocks_state.selected() != selected {
+        app.news_index = 0;
+    }
+}
+
+fn handle_event(event: Event, app: &mut App) {
+    //键盘鼠标和窗口大小变化都可能改变界面,鼠标移动除外
+    if !matches!(event, Event::Mouse(mouse) if mouse.kind == MouseEventKind::Moved) {
+        *app.dirty.lock().unwrap() = true;
+        app.last_input = Instant::now();
+    }
     let total = app.stocks.lock().unwrap().len();
     let sel = app.stocks_state.selected().unwrap_or(0);
     let selsome = app.stocks_state.selected().is_some() && sel < total;
+
+    //有提醒弹框时先处理弹框,关闭后才能继续其他操作
+    if let (AppState::Normal, Event::Key(key)) = (&app.state, &event) {
+        let notice = app.notices.lock().unwrap().first().cloned();
+        if let Some(notice) = notice {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => {}
+                KeyCode::Char('s') => {
+                    alert::snooze(&mut app.stocks.lock().unwrap(), &notice, 30);
+                }
+                _ => return,
+            }
+            app.notices.lock().unwrap().remove(0);
+            //once模式触发后被禁用,需要保存下来
+            app.save_stocks().unwrap();
+            return;
+        }
+    }
     match app.state {
         AppState::Normal => {
             if let Event::Key(key) = event {
@@ -17,11 +54,25 @@ pub fn on_events(event: Event, app: &mut App) {
                     app.refresh_stocks();
                 } else if code == KeyCode::Char('n') {
                     //新建stock
+                    tracing::debug!("进入新建状态");
                     app.state = AppState::Adding;
                     app.input = String::new();
+                } else if code == KeyCode::Tab {
+                    app.tab = app.tab.next();
+                } else if let Some(tab) = match code {
+                    KeyCode::Char(c @ '1'..='9') => Tab::ALL.get(c as usize - '1' as usize),
+                    _ => None,
+                } {
+                    app.tab = *tab;
+                } else if code == KeyCode::Char('/') {
+                    //在详情里搜索字段名或数值
+                    tracing::debug!("进入搜索状态");
+                    app.state = AppState::Searching;
+                    app.search = String::new();
                 } else if code == KeyCode::Char('d') && selsome {
                     //删除当前选中的stock
-                    app.stocks.lock().unwrap().remove(sel);
+                    let stock = app.stocks.lock().unwrap().remove(sel);
+                    tracing::info!("删除 {}", stock.code);
                     app.save_stocks().unwrap();
                     app.stocks_state.select(None);
                 } else if code == KeyCode::Char('u') && selsome && sel > 0 {
@@ -34,44 +85,417 @@ pub fn on_events(event: Event, app: &mut App) {
                     app.stocks.lock().unwrap().swap(sel, sel + 1);
                     app.save_stocks().unwrap();
                     app.stocks_state.select(Some(sel + 1));
-                } else if code == KeyCode::Up && total > 0 {
-                    //注意这里如果不加判断直接用sel - 1, 在sel为0时会导致异常
-                    app.stocks_state
-                        .select(Some(if sel > 0 { sel - 1 } else { 0 }));
-                } else if code == KeyCode::Down && total > 0 {
-                    app.stocks_state
-                        .select(Some(if sel < total - 1 { sel + 1 } else { sel }));
+                } else if code == KeyCode::Char('t') {
+                    //详情区依次切换走势、日K、逐笔、基本面、新闻和公告
+                    app.detail_tab = app.detail_tab.next();
+                    app.news_index = 0;
+                } else if code == KeyCode::Char('o') {
+                    //切换离线模式,恢复在线时马上刷新一次
+                    let offline = !*app.offline.lock().unwrap();
+                    *app.offline.lock().unwrap() = offline;
+                    tracing::info!("离线模式 {}", offline);
+                    if offline {
+                        mark_stale(&mut app.stocks.lock().unwrap());
+                    } else {
+                        *app.failures.lock().unwrap() = 0;
+                        app.refresh_stocks();
+                    }
+                } else if code == KeyCode::Char('i') {
+                    //切换快速刷新,打开时马上刷新一次,雅虎和Alpha Vantage撑不住这么频繁的请求
+                    if !app.fast_refresh && !app.provider.fast_refresh() {
+                        *app.error.lock().unwrap() =
+                            format!("行情来源{}不支持快速刷新", app.provider.name());
+                        return;
+                    }
+                    app.fast_refresh = !app.fast_refresh;
+                    tracing::info!("快速刷新 {}", app.fast_refresh);
+                    app.save_stocks().unwrap();
+                    if app.fast_refresh {
+                        app.refresh_stocks();
+                    }
+                } else if let (KeyCode::Char('[') | KeyCode::Char(']'), true) = (
+                    code,
+                    matches!(app.detail_tab, DetailTab::News | DetailTab::Announcements),
+                ) {
+                    //在新闻或公告列表里上下移动
+                    let count = app
+                        .stocks
+                        .lock()
+                        .unwrap()
+                        .get(sel)
+                        .map_or(0, |stock| app.news_items(stock).len());
+                    app.news_index = if code == KeyCode::Char('[') {
+                        app.news_index.saturating_sub(1)
+                    } else {
+                        (app.news_index + 1).min(count.saturating_sub(1))
+                    };
+                } else if code == KeyCode::Char('w') {
+                    //用浏览器打开选中的新闻或公告
+                    if let Some(news) = app.selected_news() {
+                        tracing::info!("打开 {}", news.url);
+                        open_url(&news.url);
+                    }
+                } else if code == KeyCode::Char('a') {
+                    //切换纯文本模式
+                    app.plain = !app.plain;
+                    app.save_stocks().unwrap();
+                } else if code == KeyCode::Char('f') {
+                    app.focus = !app.focus;
+                    app.save_stocks().unwrap();
+                } else if code == KeyCode::Char('g') {
+                    //按市场分组显示
+                    app.grouped = !app.grouped;
+                    app.save_stocks().unwrap();
+                } else if code == KeyCode::Char('y') && selsome {
+                    //从开盘回放选中stock当天的分时
+                    let stock = &app.stocks.lock().unwrap()[sel];
+                    if stock.timeline.len() >= 2 {
+                        app.replay = Some(Replay {
+                            code: stock.code.clone(),
+                            pos: 0,
+                            speed: 1,
+                            playing: true,
+                            last_step: Instant::now(),
+                        });
+                        app.state = AppState::Replaying;
+                        app.tab = Tab::List;
+                    } else {
+                        *app.error.lock().unwrap() = String::from("还没有加载当天的分时数据");
+                    }
+                } else if let (KeyCode::Char('b') | KeyCode::Char('s'), true) = (code, selsome) {
+                    //模拟交易下单,先输入数量
+                    let side = if code == KeyCode::Char('b') {
+                        Side::Buy
+                    } else {
+                        Side::Sell
+                    };
+                    app.state = AppState::Trading(side);
+                    app.input = String::new();
+                } else if code == KeyCode::Char('m') && selsome {
+                    //标记或取消标记,超过两个时去掉最早标记的
+                    let code = app.stocks.lock().unwrap()[sel].code.clone();
+                    if let Some(pos) = app.marked.iter().position(|c| *c == code) {
+                        app.marked.remove(pos);
+                    } else {
+                        app.marked.push(code);
+                        if app.marked.len() > 2 {
+                            app.marked.remove(0);
+                        }
+                    }
+                } else if code == KeyCode::Char('c') && app.marked.len() == 2 {
+                    //市盈率、市值和年初至今的涨幅要用基本面和日K线
+                    app.load_fundamentals_and_kline(&app.marked);
+                    app.state = AppState::Comparing;
+                } else if code == KeyCode::Char('z') && app.grouped && selsome {
+                    //折叠或展开选中stock所在的分组
+                    let section = app.stocks.lock().unwrap()[sel].market().section();
+                    app.toggle_section(section);
+                } else if code == KeyCode::Char('p') && selsome {
+                    //固定或取消固定选中的stock,固定的会一直显示在详情区
+                    let mut stocks = app.stocks.lock().unwrap();
+                    let pinned = stocks.iter().filter(|stock| stock.pinned).count();
+                    if stocks[sel].pinned || pinned < MAX_PINNED {
+                        stocks[sel].pinned = !stocks[sel].pinned;
+                        drop(stocks);
+                        app.save_stocks().unwrap();
+                    } else {
+                        *app.error.lock().unwrap() = format!("最多固定{}个", MAX_PINNED);
+                    }
+                } else if key.modifiers.contains(KeyModifiers::CONTROL)
+                    && (code == KeyCode::Left || code == KeyCode::Right)
+                {
+                    //调整列表和详情的宽度比例
+                    let step: i32 = if code == KeyCode::Left { -5 } else { 5 };
+                    app.set_list_percent(app.list_percent as i32 + step);
+                    app.save_stocks().unwrap();
+                } else if code == KeyCode::Left {
+                    app.list_column = app.list_column.saturating_sub(1);
+                } else if code == KeyCode::Right {
+                    app.list_column = (app.list_column + 1).min(widget::LIST_COLUMNS - 1);
+                } else if code == KeyCode::Char('x') {
+                    //切换是否换算成统一的币种显示,打开时马上请求汇率
+                    app.convert = !app.convert;
+                    *app.list_stale.lock().unwrap() = true;
+                    tracing::info!("换算成{} {}", app.display_currency, app.convert);
+                    app.save_stocks().unwrap();
+                    app.load_fx_rates();
+                } else if code == KeyCode::Char('v') {
+                    //显示或隐藏大盘指数条
+                    app.show_ticker = !app.show_ticker;
+                    app.save_stocks().unwrap();
+                    if app.show_ticker {
+                        app.refresh_stocks();
+                    }
+                } else if code == KeyCode::Char('k') {
+                    //打开板块页,已经在板块页时切换行业和概念
+                    if app.tab == Tab::Boards {
+                        app.concept = !app.concept;
+                        app.boards.lock().unwrap().clear();
+                    } else {
+                        app.tab = Tab::Boards;
+                    }
+                    app.board_scroll = 0;
+                    app.load_boards();
+                    app.load_positions();
+                } else if code == KeyCode::Up && app.tab == Tab::Boards {
+                    app.board_scroll = app.board_scroll.saturating_sub(1);
+                } else if code == KeyCode::Down && app.tab == Tab::Boards {
+                    app.board_scroll += 1;
+                } else if code == KeyCode::Char('e') {
+                    //打开财报日历
+                    app.tab = Tab::Reports;
+                    app.report_scroll = 0;
+                } else if code == KeyCode::Up && app.tab == Tab::Reports {
+                    app.report_scroll = app.report_scroll.saturating_sub(1);
+                } else if code == KeyCode::Down && app.tab == Tab::Reports {
+                    app.report_scroll += 1;
+                } else if code == KeyCode::Char('l') && app.tab == Tab::Log {
+                    app.log_level = app.log_level.next();
+                    app.log_scroll = 0;
+                } else if code == KeyCode::Up && app.tab == Tab::Log {
+                    //日志页上下键滚动日志
+                    app.log_scroll += 1;
+                } else if code == KeyCode::Down && app.tab == Tab::Log {
+                    app.log_scroll = app.log_scroll.saturating_sub(1);
+                } else if code == KeyCode::Up {
+                    //分组显示时列表的顺序和stock的顺序不一样,按显示的行移动
+                    app.move_selection(-1);
+                } else if code == KeyCode::Down {
+                    app.move_selection(1);
                 }
             } else if let Event::Mouse(mouse) = event {
-                if let MouseEventKind::Up(_button) = mouse.kind {
-                    let row = mouse.row as usize;
-                    //list是从第三行开始，所以要减去2, 这里本来还应该考虑list的滚动，
-                    // 但是app.stocks_state的滚动位置字段是private的，取不到。
-                    if row >= 2 && row < total + 2 {
-                        app.stocks_state.select(Some(row - 2));
+                //列表右边框就是分隔线,按住拖动可以调整宽度比例
+                let width = crossterm::terminal::size().map_or(0, |(w, _)| w as i32);
+                let divider = width * app.list_percent as i32 / 100;
+                let column = mouse.column as i32;
+                //专注模式没有标题栏和边框,列表从第一行开始,正常模式上面还有标题栏、指数条和边框
+                let top = if app.focus {
+                    0
+                } else if app.plain {
+                    1
+                } else if app.show_ticker {
+                    3
+                } else {
+                    2
+                };
+                match (mouse.kind, app.tab) {
+                    (MouseEventKind::Down(_), Tab::List)
+                        if !app.focus && !app.plain && (column - divider).abs() <= 1 =>
+                    {
+                        app.dragging = true;
+                    }
+                    (MouseEventKind::Drag(_), _) if app.dragging && width > 0 => {
+                        app.set_list_percent(column * 100 / width);
+                    }
+                    (MouseEventKind::Up(_), _) if app.dragging => {
+                        app.dragging = false;
+                        app.save_stocks().unwrap();
+                    }
+                    //只有列表页可以用鼠标选择
+                    (MouseEventKind::Up(_button), _)
+                        if app.focus || app.plain || app.tab == Tab::List =>
+                    {
+                        let row = mouse.row as usize;
+                        //list是从第四行开始，所以要减去3, 再加上list的滚动位置
+                        let rows = app.list_rows(&app.stocks.lock().unwrap());
+                        if row >= top {
+                            //点击分组标题折叠或展开
+                            match rows.get(row - top + app.list_offset) {
+                                Some(ListRow::Stock(i)) => app.stocks_state.select(Some(*i)),
+                                Some(ListRow::Header(section)) => app.toggle_section(*section),
+                                None => {}
+                            }
+                        }
                     }
+                    _ => {}
                 }
             }
         }
 
         AppState::Adding => match event {
             Event::Key(key) => match key.code {
-                KeyCode::Enter => {
+                //选中了候选时直接用候选的代码和名称
+                KeyCode::Enter if app.suggestion.is_some() => {
                     app.state = AppState::Normal;
-                    if app.input.len() > 0 {
-                        app.stocks.lock().unwrap().push(Stock::new(&app.input));
+                    let suggestion = app
+                        .suggestion
+                        .and_then(|i| app.suggestions.lock().unwrap().get(i).cloned());
+                    app.clear_suggestions();
+                    if let Some(suggestion) = suggestion {
+                        tracing::info!("新建 {}", suggestion.code);
+                        let mut stock = Stock::new(&suggestion.code);
+                        stock.title = suggestion.name;
+                        app.stocks.lock().unwrap().push(stock);
                         app.refresh_stocks();
                         app.save_stocks().unwrap();
                     }
                 }
+                KeyCode::Up | KeyCode::Down => {
+                    let len = app.suggestions.lock().unwrap().len();
+                    app.suggestion = match (app.suggestion, key.code) {
+                        _ if len == 0 => None,
+                        (None, KeyCode::Down) => Some(0),
+                        (None, _) => Some(len - 1),
+                        (Some(i), KeyCode::Down) => Some((i + 1) % len),
+                        (Some(i), _) => Some((i + len - 1) % len),
+                    };
+                }
+                KeyCode::Enter => {
+                    app.state = AppState::Normal;
+                    app.clear_suggestions();
+                    if app.input.len() > 0 {
+                        //自定义的别名优先
+                        let name = app.input.trim().to_string();
+                        let alias = app.aliases.iter().find(|(n, _)| *n == name).cloned();
+                        let code = match &alias {
+                            Some((_, code)) => Ok(code.clone()),
+                            None => market::normalize_code(&app.input),
+                        };
+                        match code {
+                            Ok(code) => {
+                                tracing::info!("新建 {}", code);
+                                let mut stock = Stock::new(&code);
+                                if let Some((name, _)) = alias {
+                                    stock.title = name.clone();
+                                    stock.alias = name;
+                                }
+                                app.stocks.lock().unwrap().push(stock);
+                                app.refresh_stocks();
+                                app.save_stocks().unwrap();
+                            }
+                            Err(err) => {
+                                tracing::warn!("新建失败: {}", err);
+                                *app.error.lock().unwrap() = err;
+                            }
+                        }
+                    }
+                }
                 KeyCode::Esc => {
                     app.state = AppState::Normal;
+                    app.clear_suggestions();
                 }
                 KeyCode::Char(c) => {
                     app.input.push(c);
+                    app.suggest();
                 }
                 KeyCode::Backspace => {
                     app.input.pop();
+                    app.suggest();
+                }
+                _ => {}
+            },
+            _ => {}
+        },
+
+        AppState::Replaying => {
+            let len = app
+                .replay
+                .as_ref()
+                .and_then(|replay| {
+                    let stocks = app.stocks.lock().unwrap();
+                    let stock = stocks.iter().find(|stock| stock.code == replay.code)?;
+                    Some(stock.timeline.len())
+                })
+                .unwrap_or(0);
+            if let (Event::Key(key), Some(replay)) = (event, app.replay.as_mut()) {
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('q') => {
+                        app.replay = None;
+                        app.state = AppState::Normal;
+                    }
+                    KeyCode::Char(' ') => {
+                        //播放到最后再按空格从头开始
+                        if replay.pos + 1 >= len {
+                            replay.pos = 0;
+                        }
+                        replay.playing = !replay.playing;
+                        replay.last_step = Instant::now();
+                    }
+                    KeyCode::Char('+') | KeyCode::Char('=') => {
+                        replay.speed = (replay.speed * 2).min(8);
+                    }
+                    KeyCode::Char('-') => {
+                        replay.speed = (replay.speed / 2).max(1);
+                    }
+                    KeyCode::Left => {
+                        replay.playing = false;
+                        replay.pos = replay.pos.saturating_sub(1);
+                    }
+                    KeyCode::Right => {
+                        replay.playing = false;
+                        replay.pos = (replay.pos + 1).min(len.saturating_sub(1));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        AppState::Trading(side) => {
+            if let Event::Key(key) = event {
+                match key.code {
+                    KeyCode::Enter => {
+                        app.state = AppState::Normal;
+                        let result = match app.input.trim().parse::<u64>() {
+                            Ok(shares) if selsome => {
+                                let stock = app.stocks.lock().unwrap()[sel].clone();
+                                app.paper.order(&stock, side, shares)
+                            }
+                            Ok(_) => Err(String::from("没有选中的stock")),
+                            Err(_) => Err(String::from("数量格式错误")),
+                        };
+                        match result {
+                            Ok(trade) => {
+                                tracing::info!(
+                                    "模拟{} {} {}股 价格{:.3}",
+                                    side.title(),
+                                    trade.code,
+                                    trade.shares,
+                                    trade.price
+                                );
+                                app.tab = Tab::Paper;
+                                app.save_stocks().unwrap();
+                            }
+                            Err(err) => *app.error.lock().unwrap() = err,
+                        }
+                    }
+                    KeyCode::Esc => {
+                        app.state = AppState::Normal;
+                    }
+                    KeyCode::Char(c) if c.is_ascii_digit() => {
+                        app.input.push(c);
+                    }
+                    KeyCode::Backspace => {
+                        app.input.pop();
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        AppState::Comparing => {
+            if let Event::Key(key) = event {
+                if let KeyCode::Esc | KeyCode::Enter | KeyCode::Char('c') = key.code {
+                    app.state = AppState::Normal;
+                }
+            }
+        }
+
+        AppState::Searching => match event {
+            Event::Key(key) => match key.code {
+                KeyCode::Enter => {
+                    app.state = AppState::Normal;
+                }
+                KeyCode::Esc => {
+                    app.state = AppState::Normal;
+                    app.search = String::new();
+                }
+                KeyCode::Char(c) => {
+                    app.search.push(c);
+                    app.search_news();
+                }
+                KeyCode::Backspace => {
+                    app.search.pop();
+                    app.search_news();
                 }
                 _ => {}
             },
@@ -83,9 +507,63 @@ pub fn on_events(event: Event, app: &mut App) {
 //处理定时事件
 pub fn on_tick(app: &mut App) {
     app.tick_count += 1;
-    if app.tick_count % 60 == 0 {
+    //回放只用已经加载的数据,离线时也能继续
+    if let (AppState::Replaying, Some(replay)) = (&app.state, app.replay.as_mut()) {
+        let len = app
+            .stocks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|stock| stock.code == replay.code)
+            .map_or(0, |stock| stock.timeline.len());
+        if replay.playing
+            && replay.last_step.elapsed() >= Duration::from_millis(1000 / replay.speed as u64)
+        {
+            replay.last_step = Instant::now();
+            if replay.pos + 1 < len {
+                replay.pos += 1;
+            } else {
+                replay.playing = false;
+            }
+            *app.dirty.lock().unwrap() = true;
+        }
+    }
+    //离线模式下不加载任何数据,不自动刷新,也不按退避重试
+    if *app.offline.lock().unwrap() {
+        return;
+    }
+    app.load_dividends();
+    app.load_reports();
+    app.load_conditions();
+    app.load_boards();
+    app.check_keywords();
+    app.load_fx_rates();
+    app.send_eod_report();
+    if app.tab == Tab::List {
+        app.load_kline();
+        app.load_timeline();
+        app.load_ticks();
+        app.load_fundamentals();
+        app.load_profile();
+        app.load_block_trades();
+        app.load_margins();
+        app.load_news();
+        app.load_announcements();
+    }
+    //被限流时每秒更新标题栏的倒计时,结束后马上重试
+    if app.throttle.lock().unwrap().remaining().is_some() {
+        *app.dirty.lock().unwrap() = true;
+    } else if app.throttle.lock().unwrap().retry_due() {
+        app.refresh_stocks();
+    }
+    //tick频率会变化,自动刷新按实际经过的时间算
+    if app.last_auto_refresh.elapsed() >= app.refresh_interval() {
+        app.last_auto_refresh = Instant::now();
+        //休市或不在刷新时间窗口内时不再自动刷新,手动刷新不受影响
         if let AppState::Normal = app.state {
-            app.refresh_stocks();
+            if app.should_auto_refresh() {
+                app.auto_refresh();
+            }
         }
     }
 }
@@ -0,0 +1,272 @@
+//提醒条件用的简单表达式,支持数字,变量,函数调用,四则运算,比较和&& || !
+//比如: price > 1800 && percent > 2
+//比较和逻辑运算的结果用1.0/0.0表示
+
+//表达式里的变量和函数由调用方提供
+pub trait Env {
+    fn var(&self, name: &str) -> Option<f64>;
+    fn call(&self, name: &str, args: &[f64]) -> Option<f64>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(String),
+}
+
+pub fn eval(expr: &str, env: &dyn Env) -> Result<f64, String> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        env,
+    };
+    let value = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        Some(token) => Err(format!("多余的{:?}", token)),
+        None => Ok(value),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Num(
+                text.parse().map_err(|_| format!("数字格式错误:{}", text))?,
+            ));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            //先匹配两个字符的运算符
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["&&", "||", ">=", "<=", "==", "!="].contains(&two.as_str()) {
+                tokens.push(Token::Op(two));
+                i += 2;
+            } else if "+-*/()<>!,".contains(c) {
+                tokens.push(Token::Op(c.to_string()));
+                i += 1;
+            } else {
+                return Err(format!("不认识的字符:{}", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    env: &'a dyn Env,
+}
+
+impl<'a> Parser<'a> {
+    //如果下一个是给定的运算符就消耗掉
+    fn eat(&mut self, ops: &[&str]) -> Option<String> {
+        if let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            if ops.contains(&op.as_str()) {
+                self.pos += 1;
+                return Some(op.clone());
+            }
+        }
+        None
+    }
+
+    fn or(&mut self) -> Result<f64, String> {
+        let mut value = self.and()?;
+        while self.eat(&["||"]).is_some() {
+            let right = self.and()?;
+            value = bool_value(value != 0.0 || right != 0.0);
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<f64, String> {
+        let mut value = self.compare()?;
+        while self.eat(&["&&"]).is_some() {
+            let right = self.compare()?;
+            value = bool_value(value != 0.0 && right != 0.0);
+        }
+        Ok(value)
+    }
+
+    fn compare(&mut self) -> Result<f64, String> {
+        let left = self.add()?;
+        match self.eat(&[">", "<", ">=", "<=", "==", "!="]) {
+            Some(op) => {
+                let right = self.add()?;
+                Ok(bool_value(match op.as_str() {
+                    ">" => left > right,
+                    "<" => left < right,
+                    ">=" => left >= right,
+                    "<=" => left <= right,
+                    "==" => left == right,
+                    _ => left != right,
+                }))
+            }
+            None => Ok(left),
+        }
+    }
+
+    fn add(&mut self) -> Result<f64, String> {
+        let mut value = self.mul()?;
+        while let Some(op) = self.eat(&["+", "-"]) {
+            let right = self.mul()?;
+            value = if op == "+" {
+                value + right
+            } else {
+                value - right
+            };
+        }
+        Ok(value)
+    }
+
+    fn mul(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.eat(&["*", "/"]) {
+            let right = self.unary()?;
+            value = if op == "*" {
+                value * right
+            } else if right == 0.0 {
+                //除以0得到inf,和任何数比较都有结果,提醒会误触发
+                return Err(String::from("除数为0"));
+            } else {
+                value / right
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        match self.eat(&["!", "-"]) {
+            Some(op) if op == "!" => Ok(bool_value(self.unary()? == 0.0)),
+            Some(_) => Ok(-self.unary()?),
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Num(n)) => Ok(n),
+            Some(Token::Ident(name)) => {
+                if self.eat(&["("]).is_some() {
+                    let mut args = vec![];
+                    if self.eat(&[")"]).is_none() {
+                        loop {
+                            args.push(self.or()?);
+                            if self.eat(&[")"]).is_some() {
+                                break;
+                            }
+                            self.eat(&[","]).ok_or("缺少,或)")?;
+                        }
+                    }
+                    self.env
+                        .call(&name, &args)
+                        .ok_or(format!("不支持的函数:{}", name))
+                } else {
+                    self.env.var(&name).ok_or(format!("不认识的变量:{}", name))
+                }
+            }
+            Some(Token::Op(op)) if op == "(" => {
+                let value = self.or()?;
+                self.eat(&[")"]).ok_or("缺少)")?;
+                Ok(value)
+            }
+            Some(token) => Err(format!("多余的{:?}", token)),
+            None => Err(String::from("表达式不完整")),
+        }
+    }
+}
+
+fn bool_value(b: bool) -> f64 {
+    if b {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestEnv;
+
+    impl Env for TestEnv {
+        fn var(&self, name: &str) -> Option<f64> {
+            match name {
+                "price" => Some(10.0),
+                "zero" => Some(0.0),
+                _ => None,
+            }
+        }
+
+        fn call(&self, name: &str, args: &[f64]) -> Option<f64> {
+            match (name, args) {
+                ("max", [a, b]) => Some(a.max(*b)),
+                _ => None,
+            }
+        }
+    }
+
+    fn run(expr: &str) -> Result<f64, String> {
+        eval(expr, &TestEnv)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(run("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(run("10 - 4 / 2 - 1").unwrap(), 7.0);
+        assert_eq!(run("-2 * 3 + 1").unwrap(), -5.0);
+        assert_eq!(run("1 + 1 > 1 && 2 < 1 || 3 == 3").unwrap(), 1.0);
+        assert_eq!(run("!0 && !(price > 5)").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(run("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(run("((price))").unwrap(), 10.0);
+        assert_eq!(run("max(1, (2 + 3) * 2) / 2").unwrap(), 5.0);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(run("1 / 0").unwrap_err(), "除数为0");
+        assert_eq!(run("price / zero > 1").unwrap_err(), "除数为0");
+        assert_eq!(run("0 / price").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn unknown_identifiers() {
+        assert_eq!(run("volume > 1").unwrap_err(), "不认识的变量:volume");
+        assert_eq!(run("sum(1, 2)").unwrap_err(), "不支持的函数:sum");
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(run("").unwrap_err(), "表达式不完整");
+        assert_eq!(run("1 +").unwrap_err(), "表达式不完整");
+        assert_eq!(run("(1 + 2").unwrap_err(), "缺少)");
+        assert_eq!(run("max(1 2)").unwrap_err(), "缺少,或)");
+        assert_eq!(run("1.2.3").unwrap_err(), "数字格式错误:1.2.3");
+        assert_eq!(run("price $ 1").unwrap_err(), "不认识的字符:$");
+        assert!(run("1 2").unwrap_err().starts_with("多余的"));
+        assert!(run(") 1").unwrap_err().starts_with("多余的"));
+    }
+}
@@ -1,26 +1,77 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fs,
     io::Stdout,
+    process::{Command, Stdio},
     sync::{Arc, Mutex},
-    thread,
+    time::{Duration, Instant},
 };
 
-use chrono::{DateTime, Local};
-use http_req::request;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, NaiveTime, TimeZone};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use tui::{backend::CrosstermBackend, widgets::ListState};
 
+//日志宏要在其他模块之前声明才能用
 pub mod aio;
+pub mod alert;
+pub mod api;
+pub mod calendar;
 pub mod events;
+pub mod expr;
+pub mod logger;
+pub mod market;
+pub mod mqtt;
+pub mod notify;
+pub mod paper;
+pub mod proxy;
+pub mod update;
 pub mod widget;
 
+use aio::Executor;
+use alert::{Alert, AlertNotice};
+use api::{ApiKeys, DataProvider, Suggestion, Throttle};
+use logger::Level;
+use market::Market;
+use mqtt::MqttConfig;
+use notify::NotifyConfig;
+use paper::{PaperAccount, Side};
+use widget::{ListLayout, Palette};
+
 pub type DynResult = Result<(), Box<dyn std::error::Error>>;
 pub type CrossTerminal = tui::Terminal<CrosstermBackend<Stdout>>;
 pub type TerminalFrame<'a> = tui::Frame<'a, CrosstermBackend<Stdout>>;
 
 pub const DB_PATH: &str = ".stocks.json";
+//最后一次取到的行情,刷新成功后写入,启动时先显示
+pub const CACHE_PATH: &str = ".stocks_cache.json";
+//刷新时最多隔这么久写一次缓存,快速刷新时几秒就刷新一次,不需要每次都写,退出时总是写
+const CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+static CACHE_SAVED: Mutex<Option<Instant>> = Mutex::new(None);
+//缓存的行情超过这么多小时标记为过期
+pub const CACHE_TTL_HOURS: i64 = 24;
+//连续这么多次刷新失败后进入离线模式
+pub const OFFLINE_AFTER: u32 = 3;
+//每个stock保留的历史价格个数,一分钟刷新一次大约是4个小时
+pub const HISTORY_SIZE: usize = 240;
+//最多同时固定显示详情的stock个数,正好排成2x2
+pub const MAX_PINNED: usize = 4;
+//请求的逐笔成交笔数
+pub const TICK_COUNT: usize = 50;
+//请求的日K线根数,大约一年,够算年初至今的涨幅
+pub const KLINE_DAYS: usize = 250;
+//顶部指数条显示的指数:上证、深成、创业板、恒生、纳斯达克
+pub const TICKER_CODES: &[&str] = &["0000001", "1399001", "1399006", "hkHSI", "US_IXIC"];
+//检查公告关键词的间隔,公告接口没有推送,盘中隔一会儿请求一次
+const KEYWORD_INTERVAL: Duration = Duration::from_secs(30 * 60);
+//换算币种时汇率的更新间隔
+const FX_INTERVAL: Duration = Duration::from_secs(10 * 60);
+//涨跌家数要翻好几页,最多隔这么久请求一次
+const BREADTH_INTERVAL: Duration = Duration::from_secs(60);
+//列表布局最多缓存这么久,有没有已收盘的会随时间变化
+const LIST_LAYOUT_TTL: Duration = Duration::from_secs(60);
+//快速刷新的间隔,秒,接口都不支持推送,只能缩短轮询的间隔
+pub const FAST_INTERVAL: u64 = 3;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Stock {
@@ -32,7 +83,244 @@ pub struct Stock {
     pub yestclose: f64, //昨收
     pub high: f64,      //最高
     pub low: f64,       //最低
-                        //pub slice: Vec<f64>
+    pub turnover: f64,  //成交额,单位是所在市场的币种
+    pub time: String,   //最后成交时间,交易所当地时间
+    //单个stock的错误,比如代码不存在,为空表示正常
+    pub error: String,
+    pub alerts: Vec<Alert>,
+    //最近的价格,每次刷新追加一个,超过HISTORY_SIZE后丢掉最早的
+    pub history: VecDeque<f64>,
+    //行情是上次退出时保存的还没有刷新过,或者离线模式下不再刷新
+    pub stale: bool,
+    //缓存行情的写入时间,本地时间"%Y-%m-%d %H:%M:%S"格式,只在stale时有意义
+    pub cached_at: String,
+    //固定在详情区,和当前选中的一起显示
+    pub pinned: bool,
+    //通过自定义别名添加的,显示别名而不是接口返回的名称
+    pub alias: String,
+    //基金的单位净值日期,单位净值记在yestclose,盘中的估值记在price
+    pub nav_date: String,
+    //行情是哪个接口返回的,配置了备用链时看得出是哪个
+    pub source: String,
+    //日K线,每天第一次选中时请求一次,candles_date是请求的日期
+    pub candles: Vec<Candle>,
+    pub candles_date: String,
+    //当天的分时价格,时间是"09:30"格式,timeline_time是请求时的行情时间
+    pub timeline: Vec<(String, f64)>,
+    pub timeline_time: String,
+    //五档买卖盘,(价格,数量),买一和卖一在最前面,接口没有返回时为空
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+    //最近的逐笔成交,最新的在最后,ticks_time是请求时的行情时间
+    pub ticks: Vec<Tick>,
+    pub ticks_time: String,
+    pub fundamentals: Option<Fundamentals>,
+    pub fundamentals_date: String,
+    //公司资料不会变,每个stock只请求一次
+    pub profile: Option<Profile>,
+    pub profile_requested: bool,
+    //最近3个月的大宗交易,最近的在最前面,每天请求一次
+    pub block_trades: Vec<BlockTrade>,
+    pub block_trades_date: String,
+    //最近20个交易日的融资融券,最近的在最前面,每天请求一次
+    pub margins: Vec<Margin>,
+    pub margins_date: String,
+    //机构评级,和基本面一起每天请求一次
+    pub rating: Option<Rating>,
+    pub rating_date: String,
+    //分红送配,最近的在最前面
+    pub dividends: Vec<Dividend>,
+    pub dividends_date: String,
+    //下一次财报的预约披露日,已经披露或没有预约时为None
+    pub report: Option<Report>,
+    pub report_date: String,
+    //相关新闻,最新的在最前面,news_time是请求时的"%Y-%m-%d %H",每小时最多请求一次
+    pub news: Vec<News>,
+    pub news_time: String,
+    //公司公告,和新闻的格式一样,每天请求一次
+    pub announcements: Vec<News>,
+    pub announcements_date: String,
+    //已经提醒过的包含关键词的新闻和公告地址
+    pub keyword_alerted: Vec<String>,
+    //当天的资金流向,只有沪深的,打开money_flow配置后随行情一起刷新
+    pub money_flow: Option<MoneyFlow>,
+    //A+H两地上市时另一边的行情,打开ah_premium配置后随行情一起刷新
+    pub ah_pair: Option<AhPair>,
+}
+
+//资金净流入,单位是元,流出为负
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct MoneyFlow {
+    //主力,超大单加大单
+    pub main: f64,
+    pub super_large: f64,
+    pub large: f64,
+}
+
+//A+H两地上市的另一边,code是另一边的代码,price是另一边的现价,用它自己的币种
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AhPair {
+    pub code: String,
+    pub price: f64,
+}
+
+//统一币种显示时的汇率,rates的key是原来的币种,value是1单位原币种换成currency的数量
+#[derive(Clone, Debug, Default)]
+pub struct Conversion {
+    pub currency: String,
+    pub rates: BTreeMap<String, f64>,
+}
+
+impl Conversion {
+    //指数、逆回购和外汇本身不换算,还没取到汇率的也不换算,币种相同时是1
+    pub fn rate(&self, stock: &Stock) -> Option<f64> {
+        if stock.is_index() || stock.is_repo() || stock.market() == Market::Forex {
+            return None;
+        }
+        let currency = stock.currency();
+        if currency == self.currency {
+            return Some(1.0);
+        }
+        self.rates.get(currency).copied()
+    }
+
+    //需要换算并且和原来的币种不同时,返回"≈换算后的金额 币种"
+    pub fn approx(&self, stock: &Stock, amount: f64, show: impl Fn(f64) -> String) -> String {
+        match self.rate(stock) {
+            Some(rate) if stock.currency() != self.currency => {
+                format!(" ≈{} {}", show(amount * rate), self.currency)
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+//沪深两市的涨跌家数和涨停数,在指数条后面显示
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct Breadth {
+    pub up: u32,
+    pub down: u32,
+    pub flat: u32,
+    pub limit_up: u32,
+}
+
+//一个行业或概念板块,leader是领涨股
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Board {
+    pub name: String,
+    pub percent: f64,
+    pub leader: String,
+    pub leader_percent: f64,
+}
+
+//一条新闻或公告的标题,time是"2024-01-05 09:31:00"格式
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct News {
+    pub time: String,
+    pub title: String,
+    pub url: String,
+    //来源媒体
+    pub source: String,
+}
+
+//一次分红送配方案,ex_date是除权除息日,还没确定时为空
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Dividend {
+    pub ex_date: String,
+    //方案,比如10派4.1元(含税)
+    pub plan: String,
+    //进度,比如董事会预案、实施分配
+    pub progress: String,
+}
+
+//一笔大宗交易,premium是成交价相对收盘价的溢价率,折价时为负,buyer和seller是营业部
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockTrade {
+    pub date: String,
+    pub price: f64,
+    pub premium: f64,
+    pub volume: f64,
+    pub amount: f64,
+    pub buyer: String,
+    pub seller: String,
+}
+
+//一个交易日的融资融券,balance是融资余额,buy是当天融资买入额,short_balance是融券余额
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Margin {
+    pub date: String,
+    pub balance: f64,
+    pub buy: f64,
+    pub short_balance: f64,
+}
+
+//一次财报的预约披露,period是报告期比如2024年报,date是最新的预约日期
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Report {
+    pub period: String,
+    pub date: String,
+}
+
+//基本面,市值的单位是所在市场的币种,比例都是百分数
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Fundamentals {
+    pub eps: f64,
+    pub roe: f64,
+    pub pe: f64,
+    pub pb: f64,
+    pub revenue: f64,
+    pub revenue_growth: f64,
+    pub profit_growth: f64,
+    pub gross_margin: f64,
+    pub market_cap: f64,
+    pub float_cap: f64,
+    //归属净利润和资产负债率,和营收一样是最近一期财报的
+    #[serde(default)]
+    pub net_profit: f64,
+    #[serde(default)]
+    pub debt_ratio: f64,
+    //所属行业,只有沪深有
+    #[serde(default)]
+    pub industry: String,
+}
+
+//公司资料,股本的单位是股,上市日期是"2001-08-27"格式,没有的字段为空或0
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Profile {
+    pub industry: String,
+    pub listing_date: String,
+    pub total_shares: f64,
+    pub float_shares: f64,
+    pub main_business: String,
+}
+
+//最近半年研报的一致评级,rating是出现最多的评级,target是平均目标价,没有目标价时为0
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Rating {
+    pub rating: String,
+    pub target: f64,
+    //参与统计的研报数
+    pub reports: usize,
+}
+
+//一笔成交,direction是1主动买,-1主动卖,0中性
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Tick {
+    pub time: String,
+    pub price: f64,
+    pub volume: f64,
+    pub direction: i8,
+}
+
+//一根日K线,成交量的单位是接口返回的手或股
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Candle {
+    pub date: String,
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    pub volume: f64,
 }
 
 impl Stock {
@@ -46,25 +334,719 @@ impl Stock {
             yestclose: 0.0,
             high: 0.0,
             low: 0.0,
-            //slice:vec![],
+            turnover: 0.0,
+            time: String::new(),
+            error: String::new(),
+            alerts: vec![],
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+            stale: false,
+            cached_at: String::new(),
+            pinned: false,
+            alias: String::new(),
+            nav_date: String::new(),
+            source: String::new(),
+            candles: vec![],
+            candles_date: String::new(),
+            timeline: vec![],
+            timeline_time: String::new(),
+            bids: vec![],
+            asks: vec![],
+            ticks: vec![],
+            ticks_time: String::new(),
+            fundamentals: None,
+            profile: None,
+            profile_requested: false,
+            block_trades: vec![],
+            block_trades_date: String::new(),
+            margins: vec![],
+            margins_date: String::new(),
+            fundamentals_date: String::new(),
+            rating: None,
+            rating_date: String::new(),
+            dividends: vec![],
+            dividends_date: String::new(),
+            report: None,
+            report_date: String::new(),
+            news: vec![],
+            news_time: String::new(),
+            announcements: vec![],
+            announcements_date: String::new(),
+            keyword_alerted: vec![],
+            money_flow: None,
+            ah_pair: None,
         }
     }
+
+    //所在市场正在交易,收盘后多留5分钟取到收盘价
+    pub fn is_open(&self) -> bool {
+        let now = Local::now();
+        let grace = now - chrono::Duration::minutes(5);
+        self.market().is_open(&now) || self.market().is_open(&grace)
+    }
+
+    //缓存的行情是多久以前取到的,不知道时返回None
+    pub fn cached_age(&self) -> Option<chrono::Duration> {
+        NaiveDateTime::parse_from_str(&self.cached_at, "%Y-%m-%d %H:%M:%S")
+            .ok()
+            .map(|at| Local::now().naive_local() - at)
+    }
+
+    //缓存的行情超过CACHE_TTL_HOURS,或者不知道是什么时候的
+    pub fn expired(&self) -> bool {
+        self.stale
+            && self
+                .cached_age()
+                .map_or(true, |age| age > chrono::Duration::hours(CACHE_TTL_HOURS))
+    }
+
+    //今天或以后最近的一次除权除息
+    pub fn next_dividend(&self) -> Option<&Dividend> {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        self.dividends
+            .iter()
+            .filter(|dividend| !dividend.ex_date.is_empty() && dividend.ex_date >= today)
+            .min_by(|a, b| a.ex_date.cmp(&b.ex_date))
+    }
+
+    //除权除息日在days天以内
+    pub fn dividend_within(&self, days: i64) -> Option<&Dividend> {
+        let limit = (Local::now() + chrono::Duration::days(days))
+            .format("%Y-%m-%d")
+            .to_string();
+        self.next_dividend()
+            .filter(|dividend| days > 0 && dividend.ex_date <= limit)
+    }
+
+    //今天或以后的财报披露日,预约日已经过了还没披露的不算
+    pub fn next_report(&self) -> Option<&Report> {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        self.report.as_ref().filter(|report| report.date >= today)
+    }
+
+    //网易的沪深代码是7位数字,第一位0是上交所,1是深交所
+    pub fn is_cn(&self) -> bool {
+        self.code.len() == 7 && self.code.chars().all(|c| c.is_ascii_digit())
+    }
+
+    pub fn market(&self) -> Market {
+        if self.is_cn() {
+            match self.code.chars().next() {
+                Some('0') => Market::SH,
+                Some('1') => Market::SZ,
+                _ => Market::Other,
+            }
+        } else if self.code.to_lowercase().starts_with("hk") {
+            Market::HK
+        } else if self.code.to_uppercase().starts_with("US_") {
+            Market::US
+        } else if self.code.starts_with("CRYPTO_") {
+            Market::Crypto
+        } else if self.code.starts_with("FX_") {
+            Market::Forex
+        } else if self.code.starts_with("FUND_") {
+            Market::Fund
+        } else if self.code.starts_with("CMDTY_") {
+            Market::Commodity
+        } else if self.code.starts_with("BJ_") {
+            Market::BJ
+        } else {
+            Market::Other
+        }
+    }
+
+    //A+H两地上市时A股价格相对H股的溢价,百分数,rate是1港元换成人民币的数量
+    pub fn ah_premium(&self, rate: f64) -> Option<f64> {
+        let pair = self.ah_pair.as_ref()?;
+        let (a, h) = if self.market() == Market::HK {
+            (pair.price, self.price)
+        } else {
+            (self.price, pair.price)
+        };
+        if a <= 0.0 || h <= 0.0 || rate <= 0.0 {
+            return None;
+        }
+        Some((a / (h * rate) - 1.0) * 100.0)
+    }
+
+    //报价币种,B股虽然在沪深交易,但沪B用美元,深B用港币
+    pub fn currency(&self) -> &'static str {
+        match self.market() {
+            Market::SH if self.code[1..].starts_with("900") => "USD",
+            Market::SZ if self.code[1..].starts_with("200") => "HKD",
+            Market::SH | Market::SZ | Market::BJ | Market::Fund => "CNY",
+            Market::HK => "HKD",
+            Market::US => "USD",
+            //交易对的计价币种
+            Market::Crypto => market::CRYPTO_QUOTES
+                .iter()
+                .find(|quote| self.code.ends_with(*quote))
+                .copied()
+                .unwrap_or(""),
+            //汇率的计价币种是后一个
+            Market::Forex => market::CURRENCIES
+                .iter()
+                .find(|currency| self.code.ends_with(*currency))
+                .copied()
+                .unwrap_or(""),
+            Market::Commodity => market::commodity(&self.code)
+                .map(|(.., currency)| currency)
+                .unwrap_or(""),
+            Market::Other => "",
+        }
+    }
+
+    //走势图的价格,有当天的分时数据时用分时,没有时用这次运行以来记录的价格
+    pub fn chart_prices(&self) -> Vec<f64> {
+        if self.timeline.len() >= 2 {
+            self.timeline.iter().map(|(_, price)| *price).collect()
+        } else {
+            self.history.iter().copied().collect()
+        }
+    }
+
+    pub fn is_index(&self) -> bool {
+        market::is_index(&self.code)
+    }
+
+    pub fn is_repo(&self) -> bool {
+        market::is_repo(&self.code)
+    }
+
+    //网易的港股和美股行情有15分钟延迟,沪深是实时的
+    pub fn is_delayed(&self) -> bool {
+        matches!(self.market(), Market::HK | Market::US)
+    }
+
+    //接口返回的时间没有时区,按所在市场的时区解析
+    pub fn trade_time(&self) -> Option<DateTime<FixedOffset>> {
+        let naive = NaiveDateTime::parse_from_str(&self.time, "%Y/%m/%d %H:%M:%S").ok()?;
+        self.market()
+            .utc_offset(naive.date())
+            .from_local_datetime(&naive)
+            .single()
+    }
+
+    //按接口返回的格式保存行情,加载时可以直接用update解析
+    pub fn quote_json(&self) -> Value {
+        json!({
+            "name": self.title,
+            "price": self.price,
+            "percent": self.percent,
+            "open": self.open,
+            "yestclose": self.yestclose,
+            "high": self.high,
+            "low": self.low,
+            "turnover": self.turnover,
+            "time": self.time,
+            "nav_date": self.nav_date,
+            "source": self.source,
+        })
+    }
+
+    //用接口返回的行情更新,数据明显错误时返回错误并保留原来的数据
+    pub fn update(&mut self, quote: &api::Quote) -> Result<(), String> {
+        let api::Quote {
+            price,
+            percent,
+            open,
+            yestclose,
+            high,
+            low,
+            turnover,
+            ..
+        } = *quote;
+
+        //明显错误的数据直接丢弃,保留上次的行情
+        if price < 0.0 || open < 0.0 || yestclose < 0.0 || high < 0.0 || low < 0.0 {
+            return Err(String::from("数据异常:价格为负"));
+        }
+        if high > 0.0 && low > 0.0 && high < low {
+            return Err(String::from("数据异常:最高价低于最低价"));
+        }
+        //沪深有涨跌幅限制,超过50%肯定是错的,港股美股没有限制不做检查
+        //逆回购的价格是利率,季末经常翻倍,也不检查
+        if self.is_cn() && !self.is_repo() && percent.abs() > 0.5 {
+            return Err(String::from("数据异常:涨跌幅超过50%"));
+        }
+
+        self.title = if !self.alias.is_empty() {
+            self.alias.clone()
+        } else if quote.name.is_empty() {
+            self.code.clone()
+        } else {
+            quote.name.clone()
+        };
+        self.price = price;
+        self.percent = percent;
+        self.open = open;
+        self.yestclose = yestclose;
+        self.high = high;
+        self.low = low;
+        self.turnover = turnover;
+        self.time = quote.time.clone();
+        self.nav_date = quote.nav_date.clone();
+        self.source = quote.source.clone();
+        self.bids = quote.bids.clone();
+        self.asks = quote.asks.clone();
+        if price > 0.0 {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(price);
+        }
+        Ok(())
+    }
+}
+
+//用系统默认的浏览器打开链接,输出会破坏TUI界面,全部丢掉
+pub fn open_url(url: &str) {
+    //链接来自接口返回的数据,只打开网页,防止被当成本地程序或命令执行
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        tracing::error!("不是网页链接,不打开: {}", url);
+        return;
+    }
+    //cmd会解释&和^等字符,直接交给url.dll打开
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("rundll32");
+        command.args(["url.dll,FileProtocolHandler", url]);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg(url);
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(url);
+        command
+    };
+    let ret = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(err) = ret {
+        tracing::error!("打开链接失败: {}", err);
+    }
+}
+
+//离线后所有行情都不再更新,按缓存显示
+pub fn mark_stale(stocks: &mut [Stock]) {
+    for stock in stocks.iter_mut().filter(|stock| stock.price > 0.0) {
+        stock.stale = true;
+    }
+}
+
+//收盘报告的正文,每个有行情的stock一行,最后是涨跌家数和平均涨跌幅
+fn eod_report(stocks: &[Stock]) -> String {
+    let quoted: Vec<_> = stocks.iter().filter(|stock| stock.price > 0.0).collect();
+    let mut lines: Vec<String> = quoted
+        .iter()
+        .map(|stock| {
+            format!(
+                "{} {} {:+.2}%",
+                stock.title,
+                stock.price,
+                stock.percent * 100.0
+            )
+        })
+        .collect();
+    let up = quoted.iter().filter(|stock| stock.percent > 0.0).count();
+    let down = quoted.iter().filter(|stock| stock.percent < 0.0).count();
+    if !quoted.is_empty() {
+        lines.push(format!(
+            "涨{} 跌{} 平{} 平均{:+.2}%",
+            up,
+            down,
+            quoted.len() - up - down,
+            quoted.iter().map(|stock| stock.percent).sum::<f64>() / quoted.len() as f64 * 100.0
+        ));
+    }
+    lines.join("\n")
+}
+
+//代码到stocks下标的索引,批量更新时先建好,不用每个结果都遍历一遍stocks
+fn code_index(stocks: &[Stock]) -> HashMap<String, usize> {
+    stocks
+        .iter()
+        .enumerate()
+        .map(|(i, stock)| (stock.code.clone(), i))
+        .collect()
+}
+
+//刷新时是否需要写缓存,需要的话记下这次的时间
+fn cache_due() -> bool {
+    let mut saved = CACHE_SAVED.lock().unwrap();
+    if saved.map_or(false, |time| time.elapsed() < CACHE_SAVE_INTERVAL) {
+        return false;
+    }
+    *saved = Some(Instant::now());
+    true
+}
+
+//取到过行情的stock,cached_at是最后一次取到的时间
+fn cache_json(stocks: &[Stock]) -> Value {
+    let cache: Map<String, Value> = stocks
+        .iter()
+        .filter(|stock| stock.price > 0.0 && !stock.cached_at.is_empty())
+        .map(|stock| {
+            (
+                stock.code.clone(),
+                json!({"quote": stock.quote_json(), "cached_at": stock.cached_at}),
+            )
+        })
+        .collect();
+    Value::Object(cache)
+}
+
+//写文件比较慢,调用时不能拿着stocks的锁,写失败只记日志
+fn save_cache(cache: Value) {
+    let path = dirs_next::home_dir().unwrap().join(CACHE_PATH);
+    if let Err(err) = fs::write(path, cache.to_string()) {
+        tracing::warn!("保存行情缓存失败: {}", err);
+    }
+}
+
+fn aliases_json(aliases: &[(String, String)]) -> Value {
+    Value::Object(
+        aliases
+            .iter()
+            .map(|(name, code)| (name.clone(), json!(code)))
+            .collect(),
+    )
+}
+
+//把别名文件合并到配置里,同名的覆盖,不需要启动界面,返回导入的个数
+pub fn import_aliases(path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let imported = market::parse_aliases(&fs::read_to_string(path)?)?;
+    let db = dirs_next::home_dir().unwrap().join(DB_PATH);
+    let mut json: Map<String, Value> =
+        serde_json::from_str(&fs::read_to_string(&db).unwrap_or_default()).unwrap_or_default();
+    let mut aliases: Vec<(String, String)> = json
+        .get("aliases")
+        .and_then(|aliases| aliases.as_object())
+        .map(|aliases| {
+            aliases
+                .iter()
+                .filter_map(|(name, code)| Some((name.clone(), code.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+    for (name, code) in imported.iter() {
+        aliases.retain(|(n, _)| n != name);
+        aliases.push((name.clone(), code.clone()));
+    }
+    json.insert(String::from("aliases"), aliases_json(&aliases));
+    fs::write(&db, serde_json::to_string(&json)?)?;
+    Ok(imported.len())
+}
+
+//请求自选里沪深stock的分红和财报预约,导出成日历文件,不需要启动界面,返回事件个数
+pub fn export_calendar(path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let home = dirs_next::home_dir().unwrap();
+    let json: Map<String, Value> =
+        serde_json::from_str(&fs::read_to_string(home.join(DB_PATH)).unwrap_or_default())
+            .unwrap_or_default();
+    let cache: Map<String, Value> =
+        serde_json::from_str(&fs::read_to_string(home.join(CACHE_PATH)).unwrap_or_default())
+            .unwrap_or_default();
+    //名称先用别名,再用缓存的行情里的,都没有时用代码
+    let mut stocks: Vec<Stock> = json
+        .get("stocks")
+        .and_then(|stocks| stocks.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|obj| {
+            let mut stock = Stock::new(&obj.get("code")?.as_str()?.to_string());
+            let name = obj
+                .get("alias")
+                .and_then(|alias| alias.as_str())
+                .or_else(|| cache.get(&stock.code)?.pointer("/quote/name")?.as_str());
+            if let Some(name) = name {
+                stock.title = name.to_string();
+            }
+            Some(stock)
+        })
+        .filter(|stock| (stock.is_cn() || market::is_blind(&stock.code)) && !stock.is_index())
+        .collect();
+    let codes: Vec<String> = stocks.iter().map(|stock| stock.code.clone()).collect();
+    if !codes.is_empty() {
+        let index = code_index(&stocks);
+        for (code, dividends) in api::fetch_dividends(&codes)? {
+            if let Some(&i) = index.get(&code) {
+                stocks[i].dividends = dividends;
+            }
+        }
+        for (code, report) in api::fetch_reports(&codes)? {
+            if let Some(&i) = index.get(&code) {
+                stocks[i].report = report;
+            }
+        }
+    }
+    let (content, count) = calendar::ics(&stocks);
+    fs::write(path, content)?;
+    Ok(count)
 }
 
 pub enum AppState {
     Normal,
     Adding,
+    Searching,
+    //对比两个标记的stock
+    Comparing,
+    //回放选中stock今天的价格变化
+    Replaying,
+    //输入模拟交易的数量
+    Trading(Side),
+}
+
+//回放的进度,pos是当天分时里的位置,speed是每秒前进几步
+//分时从开盘开始,后面追加新的数据不会改变前面的位置
+pub struct Replay {
+    pub code: String,
+    pub pos: usize,
+    pub speed: u32,
+    pub playing: bool,
+    pub last_step: Instant,
+}
+
+impl Replay {
+    //按回放位置截取的stock,价格、涨跌和最高最低都按当时计算,用来复用详情和走势图
+    pub fn snapshot(&self, stock: &Stock) -> Stock {
+        let mut snapshot = stock.clone();
+        snapshot.timeline.truncate(self.pos + 1);
+        //走势图只画截取的分时
+        snapshot.history.clear();
+        let prices: Vec<f64> = snapshot.timeline.iter().map(|(_, price)| *price).collect();
+        snapshot.price = prices.last().copied().unwrap_or(stock.price);
+        if stock.yestclose > 0.0 {
+            snapshot.percent = (snapshot.price - stock.yestclose) / stock.yestclose;
+        }
+        snapshot.high = prices.iter().copied().fold(f64::MIN, f64::max);
+        snapshot.low = prices.iter().copied().fold(f64::MAX, f64::min);
+        snapshot.turnover = 0.0;
+        snapshot.time = String::new();
+        snapshot.bids.clear();
+        snapshot.asks.clear();
+        snapshot
+    }
+}
+//详情区走势图位置的几个页面
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetailTab {
+    Chart,
+    Kline,
+    Ticks,
+    Fundamentals,
+    Profile,
+    BlockTrades,
+    Margin,
+    News,
+    Announcements,
+}
+
+impl DetailTab {
+    pub fn title(&self) -> &'static str {
+        match self {
+            DetailTab::Chart => "走势",
+            DetailTab::Kline => "日K",
+            DetailTab::Ticks => "逐笔",
+            DetailTab::Fundamentals => "基本面",
+            DetailTab::Profile => "简介",
+            DetailTab::BlockTrades => "大宗",
+            DetailTab::Margin => "两融",
+            DetailTab::News => "新闻",
+            DetailTab::Announcements => "公告",
+        }
+    }
+
+    pub fn next(&self) -> DetailTab {
+        match self {
+            DetailTab::Chart => DetailTab::Kline,
+            DetailTab::Kline => DetailTab::Ticks,
+            DetailTab::Ticks => DetailTab::Fundamentals,
+            DetailTab::Fundamentals => DetailTab::Profile,
+            DetailTab::Profile => DetailTab::BlockTrades,
+            DetailTab::BlockTrades => DetailTab::Margin,
+            DetailTab::Margin => DetailTab::News,
+            DetailTab::News => DetailTab::Announcements,
+            DetailTab::Announcements => DetailTab::Chart,
+        }
+    }
+}
+
+//列表的一行,分组显示时会插入市场标题行
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListRow {
+    Header(Market),
+    Stock(usize),
+}
+
+//主界面的标签页,Tab键切换,也可以用数字键直接选择
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tab {
+    List,
+    Heatmap,
+    //大字显示价格和涨跌幅,适合放在单独的显示器上
+    Wall,
+    //模拟交易账户
+    Paper,
+    //行业和概念板块的涨跌
+    Boards,
+    //自选股接下来的财报披露日
+    Reports,
+    //今天的日志
+    Log,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 7] = [
+        Tab::List,
+        Tab::Heatmap,
+        Tab::Wall,
+        Tab::Paper,
+        Tab::Boards,
+        Tab::Reports,
+        Tab::Log,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Tab::List => "列表",
+            Tab::Heatmap => "热力图",
+            Tab::Wall => "大字",
+            Tab::Paper => "模拟",
+            Tab::Boards => "板块",
+            Tab::Reports => "财报",
+            Tab::Log => "日志",
+        }
+    }
+
+    pub fn next(&self) -> Tab {
+        let index = Tab::ALL.iter().position(|tab| tab == self).unwrap_or(0);
+        Tab::ALL[(index + 1) % Tab::ALL.len()]
+    }
 }
+
 pub struct App {
     pub should_exit: bool,
     pub state: AppState,
+    pub tab: Tab,
     pub error: Arc<Mutex<String>>,
     pub input: String,
+    //详情里的搜索关键字,确认后保留高亮,ESC清除
+    pub search: String,
     pub stocks: Arc<Mutex<Vec<Stock>>>,
+    //顶部指数条,和stocks一起刷新
+    pub ticker: Arc<Mutex<Vec<Stock>>>,
+    //市场宽度,和指数条一起刷新,还没取到时为None,breadth_time是上次请求的时间
+    pub breadth: Arc<Mutex<Option<Breadth>>>,
+    pub breadth_time: Option<Instant>,
     //TUI的List控件需要这个state记录当前选中和滚动位置两个状态
+    //列表只渲染可见部分,这里只用到选中状态,滚动位置记在list_offset
     pub stocks_state: ListState,
+    pub list_offset: usize,
+    //按市场分组显示,collapsed里是折叠起来的分组
+    pub grouped: bool,
+    pub collapsed: Vec<Market>,
+    pub replay: Option<Replay>,
+    //模拟交易账户,B/S下单
+    pub paper: PaperAccount,
+    //新建时按输入的名称或代码搜索到的候选,suggestion是用上下键选中的
+    //suggest_query是最后一次搜索的关键字,返回晚了的旧结果直接丢掉
+    pub suggestions: Arc<Mutex<Vec<Suggestion>>>,
+    pub suggestion: Option<usize>,
+    pub suggest_query: Arc<Mutex<String>>,
+    //除权除息日在这么多天以内时在列表里提示,配置里的dividend_days,0表示不提示
+    pub dividend_days: i64,
+    //详情区下半部分显示的内容,按T切换
+    pub detail_tab: DetailTab,
+    //新闻或公告列表里选中的是第几条,切换stock或页面时回到第一条
+    pub news_index: usize,
+    //板块页的数据,concept为true时是概念板块,否则是行业板块
+    //boards_time记下请求的是哪种和请求的时间,一分钟内不重复请求
+    pub boards: Arc<Mutex<Vec<Board>>>,
+    pub concept: bool,
+    pub boards_time: Option<(bool, Instant)>,
+    pub board_scroll: usize,
+    //财报日历页滚动到的行
+    pub report_scroll: usize,
+    //日志页的过滤级别和从最后往上滚动的行数
+    pub log_level: Level,
+    pub log_scroll: usize,
+    //用M标记的stock代码,最多两个,按C对比
+    pub marked: Vec<String>,
+    //列表横向滚动到的列,名称列固定不动
+    pub list_column: usize,
+    //列表占主区域宽度的百分比,其余是详情,保存在配置里
+    pub list_percent: u16,
+    //涨跌的配色,在配置里设置palette为deuteranopia或protanopia使用色盲配色
+    pub palette: Palette,
+    //走势图用盲文点阵画,字体不支持盲文字符时在配置里改成block
+    pub braille: bool,
+    //正在用鼠标拖动分隔线
+    pub dragging: bool,
+    //专注模式,只显示没有边框的列表,适合很小的tmux窗格
+    pub focus: bool,
+    //显示顶部的大盘指数条,按V切换,保存在配置里
+    pub show_ticker: bool,
+    //刷新时同时请求资金流向,在列表最后一列和详情里显示,配置里的money_flow打开
+    pub money_flow: bool,
+    //刷新时同时请求A+H两地上市的另一边,在列表最后一列和详情里显示A/H溢价,配置里的ah_premium打开
+    pub ah_premium: bool,
+    //纯文本模式,不用边框、颜色和画线字符,方便读屏软件使用,--plain参数或配置里打开
+    pub plain: bool,
     pub last_refresh: Arc<Mutex<DateTime<Local>>>,
     pub tick_count: u128,
+    //最近一次用户操作和自动刷新的时间,用来调整tick频率
+    pub last_input: Instant,
+    pub last_auto_refresh: Instant,
+    //已触发还没关闭的提醒,后台刷新线程写入,界面上逐条弹出
+    pub notices: Arc<Mutex<Vec<AlertNotice>>>,
+    //配置了时间窗口时只在窗口内自动刷新,本地时间
+    pub refresh_windows: Vec<(NaiveTime, NaiveTime)>,
+    //提醒触发时的推送渠道
+    pub notify: NotifyConfig,
+    //配置了eod_report时,沪深收盘后用推送渠道发一份自选的收盘报告,eod_date是上次发送的日期
+    pub eod_report: bool,
+    pub eod_date: String,
+    //新闻和公告的关键词提醒,keywords_time是上次检查的时间
+    pub keywords: Vec<String>,
+    pub keywords_time: Option<Instant>,
+    //自定义的别名,新建时输入别名等于输入对应的代码,用stock alias import导入
+    pub aliases: Vec<(String, String)>,
+    //刷新后发布行情和提醒的MQTT服务器
+    pub mqtt: Option<MqttConfig>,
+    //界面数据有变化需要重绘,后台线程更新数据后也会设置
+    pub dirty: Arc<Mutex<bool>>,
+    //列表的列宽和分组个数的缓存,行情或汇率更新后设置list_stale,下次画列表时重新计算
+    pub list_layout: Option<(Instant, ListLayout)>,
+    pub list_stale: Arc<Mutex<bool>>,
+    //后台刷新线程是否正在请求
+    pub refreshing: Arc<Mutex<bool>>,
+    //刷新限速和被限流后的退避,连续按R也不会频繁请求
+    pub throttle: Arc<Mutex<Throttle>>,
+    //离线模式不再自动刷新,只显示缓存的行情和数据的时间,按O切换,
+    //连续OFFLINE_AFTER次请求失败时自动进入,手动刷新成功后退出
+    pub offline: Arc<Mutex<bool>>,
+    //连续失败的刷新次数
+    pub failures: Arc<Mutex<u32>>,
+    //配置了update_check时启动后检查有没有新版本,有的话在状态栏提示
+    pub update_check: bool,
+    //快速刷新,交易时间内每隔FAST_INTERVAL秒刷新一次,按I切换,保存在配置里
+    pub fast_refresh: bool,
+    //按X切换是否把价格和市值换算成display_currency显示,汇率来自外汇行情,fx_time是上次请求的时间
+    pub convert: bool,
+    pub display_currency: String,
+    pub fx_rates: Arc<Mutex<BTreeMap<String, f64>>>,
+    pub fx_time: Option<Instant>,
+    pub new_version: Arc<Mutex<String>>,
+    //后台请求都交给工作线程池执行
+    pub executor: Executor,
+    //行情来源,配置里的provider指定,默认是网易,指定多个时按顺序作为备用
+    pub provider: Arc<dyn DataProvider>,
+    pub api_keys: ApiKeys,
+    //行情请求走的代理,配置里没有时读HTTP_PROXY/ALL_PROXY等环境变量
+    pub proxy: String,
 }
 
 impl App {
@@ -72,16 +1054,87 @@ impl App {
         let mut app = Self {
             should_exit: false,
             state: AppState::Normal,
+            tab: Tab::List,
             input: String::new(),
+            search: String::new(),
             error: Arc::new(Mutex::new(String::new())),
             stocks: Arc::new(Mutex::new([].to_vec())),
+            ticker: Arc::new(Mutex::new(
+                TICKER_CODES
+                    .iter()
+                    .map(|code| Stock::new(&code.to_string()))
+                    .collect(),
+            )),
+            breadth: Arc::new(Mutex::new(None)),
+            breadth_time: None,
             //ListState:default为未选择，因为可能stocks为空，所以不能自动选第一个
             stocks_state: ListState::default(),
+            list_offset: 0,
+            grouped: false,
+            collapsed: vec![],
+            replay: None,
+            paper: PaperAccount::default(),
+            detail_tab: DetailTab::Chart,
+            news_index: 0,
+            boards: Arc::new(Mutex::new(vec![])),
+            concept: false,
+            boards_time: None,
+            report_scroll: 0,
+            board_scroll: 0,
+            dividend_days: 0,
+            suggestions: Arc::new(Mutex::new(vec![])),
+            suggestion: None,
+            suggest_query: Arc::new(Mutex::new(String::new())),
+            log_level: Level::Info,
+            log_scroll: 0,
+            marked: vec![],
+            list_column: 0,
+            list_percent: 30,
+            palette: Palette::default(),
+            braille: true,
+            dragging: false,
+            focus: false,
+            show_ticker: true,
+            money_flow: false,
+            ah_premium: false,
+            plain: false,
             last_refresh: Arc::new(Mutex::new(Local::now())),
             tick_count: 0,
+            last_input: Instant::now(),
+            last_auto_refresh: Instant::now(),
+            notices: Arc::new(Mutex::new(vec![])),
+            refresh_windows: vec![],
+            notify: NotifyConfig::default(),
+            eod_report: false,
+            eod_date: String::new(),
+            keywords: alert::default_keywords(),
+            keywords_time: None,
+            mqtt: None,
+            aliases: vec![],
+            dirty: Arc::new(Mutex::new(true)),
+            list_layout: None,
+            list_stale: Arc::new(Mutex::new(true)),
+            refreshing: Arc::new(Mutex::new(false)),
+            throttle: Arc::new(Mutex::new(Throttle::default())),
+            offline: Arc::new(Mutex::new(false)),
+            failures: Arc::new(Mutex::new(0)),
+            update_check: false,
+            fast_refresh: false,
+            convert: false,
+            display_currency: String::from("CNY"),
+            fx_rates: Arc::new(Mutex::new(BTreeMap::new())),
+            fx_time: None,
+            new_version: Arc::new(Mutex::new(String::new())),
+            executor: Executor::new(4),
+            provider: Arc::from(api::provider(&Value::Null, &ApiKeys::default())),
+            api_keys: ApiKeys::default(),
+            proxy: String::new(),
         };
         app.load_stocks().unwrap_or_default();
         app.refresh_stocks();
+        if app.update_check {
+            app.check_update();
+        }
         return app;
     }
 
@@ -91,12 +1144,110 @@ impl App {
         let stocks = self.stocks.lock().unwrap();
         let lists: Vec<_> = stocks
             .iter()
-            .map(|s| HashMap::from([("code", &s.code)]))
+            .map(|s| {
+                let mut obj = Map::new();
+                obj.insert(String::from("code"), json!(s.code));
+                if !s.alerts.is_empty() {
+                    obj.insert(String::from("alerts"), json!(s.alerts));
+                }
+                if s.pinned {
+                    obj.insert(String::from("pinned"), json!(true));
+                }
+                if !s.alias.is_empty() {
+                    obj.insert(String::from("alias"), json!(s.alias));
+                }
+                if let Some(secid) = api::resolved_secid(&s.code) {
+                    obj.insert(String::from("secid"), json!(secid));
+                }
+                obj
+            })
             .collect();
-        fs::write(
-            &db,
-            serde_json::to_string(&HashMap::from([("stocks", lists)]))?,
-        )?;
+        let cache = cache_json(&stocks);
+        drop(stocks);
+        let mut db_json = Map::new();
+        db_json.insert(String::from("stocks"), json!(lists));
+        if !self.refresh_windows.is_empty() {
+            let windows: Vec<_> = self
+                .refresh_windows
+                .iter()
+                .map(|(start, end)| format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")))
+                .collect();
+            db_json.insert(String::from("refresh_windows"), json!(windows));
+        }
+        if !self.notify.is_empty() {
+            db_json.insert(String::from("notify"), json!(self.notify));
+        }
+        if self.eod_report {
+            db_json.insert(String::from("eod_report"), json!(true));
+            db_json.insert(String::from("eod_date"), json!(self.eod_date));
+        }
+        if self.keywords != alert::default_keywords() {
+            db_json.insert(String::from("keywords"), json!(self.keywords));
+        }
+        db_json.insert(String::from("list_percent"), json!(self.list_percent));
+        if !self.show_ticker {
+            db_json.insert(String::from("ticker"), json!(false));
+        }
+        if self.money_flow {
+            db_json.insert(String::from("money_flow"), json!(true));
+        }
+        if self.ah_premium {
+            db_json.insert(String::from("ah_premium"), json!(true));
+        }
+        if self.focus {
+            db_json.insert(String::from("focus"), json!(true));
+        }
+        if self.grouped {
+            db_json.insert(String::from("grouped"), json!(true));
+        }
+        if self.plain {
+            db_json.insert(String::from("plain"), json!(true));
+        }
+        if !self.aliases.is_empty() {
+            db_json.insert(String::from("aliases"), aliases_json(&self.aliases));
+        }
+        db_json.insert(String::from("paper"), json!(self.paper));
+        if let Some(mqtt) = &self.mqtt {
+            db_json.insert(String::from("mqtt"), json!(mqtt));
+        }
+        if self.update_check {
+            db_json.insert(String::from("update_check"), json!(true));
+        }
+        if self.fast_refresh {
+            db_json.insert(String::from("fast_refresh"), json!(true));
+        }
+        if self.convert {
+            db_json.insert(String::from("convert"), json!(true));
+        }
+        if self.display_currency != "CNY" {
+            db_json.insert(
+                String::from("display_currency"),
+                json!(self.display_currency),
+            );
+        }
+        if self.palette != Palette::default() {
+            db_json.insert(String::from("palette"), json!(self.palette));
+        }
+        if !self.braille {
+            db_json.insert(String::from("chart_marker"), json!("block"));
+        }
+        let names = self.provider.names();
+        if names.len() > 1 {
+            db_json.insert(String::from("provider"), json!(names));
+        } else if self.provider.name() != "netease" {
+            db_json.insert(String::from("provider"), json!(self.provider.name()));
+        }
+        if self.dividend_days > 0 {
+            db_json.insert(String::from("dividend_days"), json!(self.dividend_days));
+        }
+        if !self.api_keys.is_empty() {
+            db_json.insert(String::from("api_keys"), json!(self.api_keys));
+        }
+        if !self.proxy.is_empty() {
+            db_json.insert(String::from("proxy"), json!(self.proxy));
+        }
+        fs::write(&db, serde_json::to_string(&db_json)?)?;
+        save_cache(cache);
         Ok(())
     }
 
@@ -109,6 +1260,11 @@ impl App {
 
         //先读成Map再转换，可以增加兼容性，
         let json: Map<String, Value> = serde_json::from_str(&content).unwrap_or_default();
+        let cache: Map<String, Value> = serde_json::from_str(
+            &fs::read_to_string(dirs_next::home_dir().unwrap().join(CACHE_PATH))
+                .unwrap_or_default(),
+        )
+        .unwrap_or_default();
         let mut data = self.stocks.lock().unwrap();
         data.clear();
         data.append(
@@ -119,105 +1275,1236 @@ impl App {
                 .unwrap()
                 .iter()
                 .map(|s| {
-                    Stock::new(
-                        &s.as_object()
-                            .unwrap()
-                            .get("code")
-                            .unwrap()
-                            .as_str()
-                            .unwrap()
-                            .to_string(),
-                    )
+                    let obj = s.as_object().unwrap();
+                    let mut stock =
+                        Stock::new(&obj.get("code").unwrap().as_str().unwrap().to_string());
+                    stock.alerts = obj
+                        .get("alerts")
+                        .and_then(|alerts| serde_json::from_value(alerts.clone()).ok())
+                        .unwrap_or_default();
+                    stock.pinned = obj.get("pinned").and_then(|p| p.as_bool()).unwrap_or(false);
+                    if let Some(secid) = obj.get("secid").and_then(|s| s.as_str()) {
+                        api::remember_secid(&stock.code, secid);
+                    }
+                    if let Some(alias) = obj.get("alias").and_then(|a| a.as_str()) {
+                        stock.alias = alias.to_string();
+                        stock.title = stock.alias.clone();
+                    }
+                    //先用缓存的行情,旧版本的行情保存在配置里
+                    let cached = cache.get(&stock.code);
+                    if let Some(quote) = cached.and_then(|c| c.get("quote")).or(obj.get("quote")) {
+                        stock.stale = api::Quote::from_json(quote)
+                            .and_then(|quote| stock.update(&quote))
+                            .is_ok();
+                        stock.cached_at = cached
+                            .and_then(|c| c.get("cached_at"))
+                            .and_then(|at| at.as_str())
+                            .unwrap_or_default()
+                            .to_string();
+                    }
+                    stock
                 })
                 .collect(),
         );
+        drop(data);
+        self.refresh_windows = json
+            .get("refresh_windows")
+            .and_then(|windows| windows.as_array())
+            .map(|windows| {
+                windows
+                    .iter()
+                    .filter_map(|w| w.as_str().and_then(market::parse_window))
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.notify = json
+            .get("notify")
+            .and_then(|notify| serde_json::from_value(notify.clone()).ok())
+            .unwrap_or_default();
+        self.eod_report = json
+            .get("eod_report")
+            .and_then(|e| e.as_bool())
+            .unwrap_or(false);
+        self.eod_date = json
+            .get("eod_date")
+            .and_then(|e| e.as_str())
+            .unwrap_or_default()
+            .to_string();
+        self.keywords = json
+            .get("keywords")
+            .and_then(|keywords| serde_json::from_value(keywords.clone()).ok())
+            .unwrap_or_else(alert::default_keywords);
+        self.focus = json.get("focus").and_then(|f| f.as_bool()).unwrap_or(false);
+        self.show_ticker = json.get("ticker").and_then(|t| t.as_bool()).unwrap_or(true);
+        self.money_flow = json
+            .get("money_flow")
+            .and_then(|m| m.as_bool())
+            .unwrap_or(false);
+        self.ah_premium = json
+            .get("ah_premium")
+            .and_then(|a| a.as_bool())
+            .unwrap_or(false);
+        self.plain = json.get("plain").and_then(|p| p.as_bool()).unwrap_or(false);
+        self.grouped = json
+            .get("grouped")
+            .and_then(|g| g.as_bool())
+            .unwrap_or(false);
+        self.aliases = json
+            .get("aliases")
+            .and_then(|aliases| aliases.as_object())
+            .map(|aliases| {
+                aliases
+                    .iter()
+                    .filter_map(|(name, code)| Some((name.clone(), code.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.paper = json
+            .get("paper")
+            .and_then(|paper| serde_json::from_value(paper.clone()).ok())
+            .unwrap_or_default();
+        self.mqtt = json
+            .get("mqtt")
+            .and_then(|mqtt| serde_json::from_value(mqtt.clone()).ok());
+        self.update_check = json
+            .get("update_check")
+            .and_then(|u| u.as_bool())
+            .unwrap_or(false);
+        //以前的版本叫stream
+        self.fast_refresh = json
+            .get("fast_refresh")
+            .or_else(|| json.get("stream"))
+            .and_then(|f| f.as_bool())
+            .unwrap_or(false);
+        self.convert = json
+            .get("convert")
+            .and_then(|c| c.as_bool())
+            .unwrap_or(false);
+        self.display_currency = json
+            .get("display_currency")
+            .and_then(|c| c.as_str())
+            .map(str::to_uppercase)
+            .filter(|c| market::CURRENCIES.contains(&c.as_str()))
+            .unwrap_or_else(|| String::from("CNY"));
+        self.palette = json
+            .get("palette")
+            .and_then(|palette| serde_json::from_value(palette.clone()).ok())
+            .unwrap_or_default();
+        self.braille = json.get("chart_marker").and_then(|m| m.as_str()) != Some("block");
+        self.dividend_days = json
+            .get("dividend_days")
+            .and_then(|d| d.as_i64())
+            .unwrap_or(0);
+        self.api_keys = json
+            .get("api_keys")
+            .and_then(|keys| serde_json::from_value(keys.clone()).ok())
+            .unwrap_or_default();
+        self.proxy = json
+            .get("proxy")
+            .and_then(|p| p.as_str())
+            .unwrap_or_default()
+            .to_string();
+        proxy::set(&self.proxy);
+        self.provider = Arc::from(api::provider(
+            json.get("provider").unwrap_or(&Value::Null),
+            &self.api_keys,
+        ));
+        if let Some(percent) = json.get("list_percent").and_then(|p| p.as_i64()) {
+            self.set_list_percent(percent as i32);
+        }
 
         Ok(())
     }
 
     pub fn refresh_stocks(&mut self) {
+        self.refresh(false);
+    }
+
+    //自动刷新只请求正在交易的市场,配置了刷新时间窗口时按窗口全部刷新
+    pub fn auto_refresh(&mut self) {
+        self.refresh(self.refresh_windows.is_empty());
+    }
+
+    fn refresh(&mut self, open_only: bool) {
         let stock_clone = self.stocks.clone();
+        let ticker_clone = self.ticker.clone();
+        let breadth_clone = self.breadth.clone();
         let err_clone = self.error.clone();
         let last_refresh_clone = self.last_refresh.clone();
-        let codes = self.get_codes();
-        if codes.len() > 0 {
-            thread::spawn(move || {
-                let mut writer = Vec::new();
-                let ret = request::get(
-                    format!("{}{}", "http://api.money.126.net/data/feed/", codes),
-                    &mut writer,
-                );
-                let mut locked_err = err_clone.lock().unwrap();
-                if let Err(err) = ret {
-                    *locked_err = format!("{:?}", err);
+        let notices_clone = self.notices.clone();
+        let notify = self.notify.clone();
+        let mqtt = self.mqtt.clone();
+        let dirty_clone = self.dirty.clone();
+        let list_stale = self.list_stale.clone();
+        let refreshing_clone = self.refreshing.clone();
+        let provider = self.provider.clone();
+        let throttle = self.throttle.clone();
+        let failures_clone = self.failures.clone();
+        let offline_clone = self.offline.clone();
+        let money_flow = self.money_flow;
+        let ah_premium = self.ah_premium;
+        let codes: Vec<String> = self
+            .stocks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|stock| !open_only || stock.is_open())
+            .map(|stock| stock.code.clone())
+            .collect();
+        //隐藏了指数条时不请求指数
+        let ticker_codes: Vec<String> = if self.show_ticker {
+            TICKER_CODES
+                .iter()
+                .filter(|code| !open_only || Stock::new(&code.to_string()).is_open())
+                .map(|code| code.to_string())
+                .collect()
+        } else {
+            vec![]
+        };
+        if !codes.is_empty() || !ticker_codes.is_empty() {
+            //上一次刷新还没结束时直接忽略,避免连续按R时同时发出多个请求
+            let mut refreshing = self.refreshing.lock().unwrap();
+            if *refreshing || !self.throttle.lock().unwrap().acquire() {
+                return;
+            }
+            *refreshing = true;
+            //指数条里有沪深的指数时才请求市场宽度,快速刷新时也最多隔BREADTH_INTERVAL请求一次
+            let breadth_due = ticker_codes.iter().any(|code| Stock::new(code).is_cn())
+                && self
+                    .breadth_time
+                    .map_or(true, |time| time.elapsed() >= BREADTH_INTERVAL);
+            if breadth_due {
+                self.breadth_time = Some(Instant::now());
+            }
+            self.executor.spawn(move |cancel| {
+                let ret = api::fetch(provider.as_ref(), &codes);
+                //指数条固定用网易,其他行情来源不一定支持这几个指数
+                let ticker_json = if ticker_codes.is_empty() {
+                    Default::default()
                 } else {
-                    let content = String::from_utf8_lossy(&writer);
-                    if content.starts_with("_ntes_quote_callback") {
+                    api::fetch(&api::Netease, &ticker_codes).unwrap_or_else(|err| {
+                        tracing::warn!("请求指数条失败: {}", err);
+                        Default::default()
+                    })
+                };
+                let breadth = if breadth_due {
+                    api::fetch_breadth()
+                        .map_err(|err| tracing::warn!("请求涨跌家数失败: {}", err))
+                        .ok()
+                } else {
+                    None
+                };
+                //资金流向取不到不影响行情
+                let flows = if ret.is_ok() && money_flow {
+                    api::fetch_money_flow(&codes).unwrap_or_else(|err| {
+                        tracing::warn!("请求资金流向失败: {}", err);
+                        Default::default()
+                    })
+                } else {
+                    Default::default()
+                };
+                let pairs = if ret.is_ok() && ah_premium {
+                    api::fetch_ah_pairs(&codes).unwrap_or_else(|err| {
+                        tracing::warn!("请求A+H行情失败: {}", err);
+                        Default::default()
+                    })
+                } else {
+                    Default::default()
+                };
+                //退出时请求可能还没返回,这时直接丢掉结果
+                if cancel.is_cancelled() {
+                    return;
+                }
+                //要写的缓存先复制出来,放开锁之后再写文件
+                let mut cache = None;
+                let mut locked_err = err_clone.lock().unwrap();
+                match ret {
+                    //被限流时退避,标题栏显示倒计时,到时间后on_tick自动重试
+                    Err(err) if err.starts_with(api::THROTTLED) => {
+                        let wait = throttle.lock().unwrap().throttled();
+                        tracing::warn!("{}, {}秒后重试", err, wait.as_secs());
+                        locked_err.clear();
+                    }
+                    //整体失败时保留上次的数据,只报告错误,连续失败几次后进入离线模式
+                    Err(err) => {
+                        tracing::error!("请求行情失败: {}", err);
+                        let mut failures = failures_clone.lock().unwrap();
+                        *failures += 1;
+                        if *failures >= OFFLINE_AFTER {
+                            tracing::warn!("连续{}次请求失败,进入离线模式", failures);
+                            *offline_clone.lock().unwrap() = true;
+                            mark_stale(&mut stock_clone.lock().unwrap());
+                            locked_err.clear();
+                        } else {
+                            *locked_err = err;
+                        }
+                    }
+                    Ok(json) => {
+                        throttle.lock().unwrap().succeeded();
+                        *failures_clone.lock().unwrap() = 0;
+                        *offline_clone.lock().unwrap() = false;
+                        let mut messages = vec![];
                         let mut stocks = stock_clone.lock().unwrap();
-                        //网易的返回包了一个js call，用skip,take,collect实现一个substring剥掉它
-                        let json: Map<String, Value> = serde_json::from_str(
-                            &content
-                                .chars()
-                                .skip(21)
-                                .take(content.len() - 23)
-                                .collect::<String>(),
-                        )
-                        .unwrap();
-                        for stock in stocks.iter_mut() {
-                            //如果code不对,返回的json里不包括这个对象, 用unwrap_or生成一个空对象,防止异常
-                            let obj = json
-                                .get(&stock.code)
-                                .unwrap_or(&json!({}))
-                                .as_object()
-                                .unwrap()
-                                .to_owned();
-                            stock.title = obj
-                                .get("name")
-                                .unwrap_or(&json!(stock.code.clone()))
-                                .as_str()
-                                .unwrap()
-                                .to_owned();
-                            stock.price = obj.get("price").unwrap_or(&json!(0.0)).as_f64().unwrap();
-                            stock.percent =
-                                obj.get("percent").unwrap_or(&json!(0.0)).as_f64().unwrap();
-                            stock.open = obj.get("open").unwrap_or(&json!(0.0)).as_f64().unwrap();
-                            stock.yestclose = obj
-                                .get("yestclose")
-                                .unwrap_or(&json!(0.0))
-                                .as_f64()
-                                .unwrap();
-                            stock.high = obj.get("high").unwrap_or(&json!(0.0)).as_f64().unwrap();
-                            stock.low = obj.get("low").unwrap_or(&json!(0.0)).as_f64().unwrap();
-
-                            // if json.contains_key(&stock.code) {
-                            //     let mut writer2 = Vec::new();
-                            //     request::get(format!("http://img1.money.126.net/data/hs/time/today/{}.json",stock.code), &mut writer2)?;
-                            //     println!("{:?}", format!("http://img1.money.126.net/data/hs/time/today/{}.json",stock.code));
-                            //     let json2: Map<String, Value> = serde_json::from_str(&String::from_utf8_lossy(&writer2).to_string())?;
-                            //     stock.slice = json2.get("data").unwrap().as_array().unwrap()
-                            //         .iter().map(|item| item.as_array().unwrap().get(2).unwrap().as_f64().unwrap())
-                            //         .collect();
-                            // }
+                        //已收盘没有请求的保持原样
+                        let requested: HashSet<&str> = codes.iter().map(String::as_str).collect();
+                        for stock in stocks
+                            .iter_mut()
+                            .filter(|s| requested.contains(s.code.as_str()))
+                        {
+                            //如果code不对,返回的结果里不包括这个对象,错误记在这个stock上
+                            //单个stock解析失败不影响其他stock
+                            stock.error = match json.get(&stock.code) {
+                                Some(Err(err)) => {
+                                    stock.stale = true;
+                                    err.to_string()
+                                }
+                                Some(Ok(quote)) => match stock.update(quote) {
+                                    Ok(()) => {
+                                        stock.stale = false;
+                                        stock.cached_at =
+                                            Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                                        if let Some(flow) = flows.get(&stock.code) {
+                                            stock.money_flow = Some(*flow);
+                                        }
+                                        if let Some(pair) = pairs.get(&stock.code) {
+                                            stock.ah_pair = Some(pair.clone());
+                                        }
+                                        if let Some(mqtt) = &mqtt {
+                                            messages.push(mqtt.quote(
+                                                &stock.code,
+                                                stock.quote_json().to_string(),
+                                            ));
+                                        }
+                                        for notice in alert::check_alerts(stock) {
+                                            tracing::info!("触发提醒 {}", notice.message);
+                                            if let Some(mqtt) = &mqtt {
+                                                messages.push(mqtt.alert(
+                                                    &stock.code,
+                                                    json!({"message": notice.message}).to_string(),
+                                                ));
+                                            }
+                                            notify.send(
+                                                notice.title,
+                                                &notice.message,
+                                                &err_clone,
+                                                &dirty_clone,
+                                            );
+                                            notices_clone.lock().unwrap().push(notice);
+                                        }
+                                        alert::condition_errors(stock)
+                                    }
+                                    Err(err) => {
+                                        tracing::warn!("{} {}", stock.code, err);
+                                        err
+                                    }
+                                },
+                                None => {
+                                    tracing::warn!("{} 代码不存在", stock.code);
+                                    String::from("代码不存在")
+                                }
+                            };
+                        }
+                        if let Some(mqtt) = &mqtt {
+                            mqtt.publish(messages, &err_clone, &dirty_clone);
+                        }
+                        tracing::info!("刷新完成 {}个", stocks.len());
+                        if cache_due() {
+                            cache = Some(cache_json(&stocks));
                         }
                         let mut last_refresh = last_refresh_clone.lock().unwrap();
                         *last_refresh = Local::now();
                         *locked_err = String::new();
-                    } else {
-                        *locked_err = String::from("服务器返回错误");
                     }
                 }
+                drop(locked_err);
+                //指数条和自选的请求互不影响,取不到时保留上次的数据,不报错
+                for stock in ticker_clone.lock().unwrap().iter_mut() {
+                    if let Some(Ok(quote)) = ticker_json.get(&stock.code) {
+                        let _ = stock.update(quote);
+                    }
+                }
+                if breadth.is_some() {
+                    *breadth_clone.lock().unwrap() = breadth;
+                }
+                if let Some(cache) = cache {
+                    save_cache(cache);
+                }
+                *refreshing_clone.lock().unwrap() = false;
+                *list_stale.lock().unwrap() = true;
+                *dirty_clone.lock().unwrap() = true;
+            });
+        }
+    }
+
+    //选中的stock需要额外的数据时在后台请求,mark判断要不要请求并记下这次请求,
+    //先记下再请求,失败了也不会反复请求,成功后用store保存到stock上
+    fn load_selected<T: Send + 'static>(
+        &self,
+        name: &'static str,
+        mark: impl FnOnce(&mut Stock) -> bool,
+        fetch: fn(&str) -> Result<T, String>,
+        store: fn(&mut Stock, T),
+    ) {
+        let code = {
+            let mut stocks = self.stocks.lock().unwrap();
+            let stock = match self.stocks_state.selected().and_then(|i| stocks.get_mut(i)) {
+                Some(stock) => stock,
+                None => return,
+            };
+            if !mark(stock) {
+                return;
+            }
+            stock.code.clone()
+        };
+        self.spawn_load(code, name, fetch, store);
+    }
+
+    //在后台请求code的数据,成功后用store保存到对应的stock上
+    fn spawn_load<T: Send + 'static>(
+        &self,
+        code: String,
+        name: &'static str,
+        fetch: fn(&str) -> Result<T, String>,
+        store: fn(&mut Stock, T),
+    ) {
+        let stocks = self.stocks.clone();
+        let dirty = self.dirty.clone();
+        self.executor.spawn(move |cancel| match fetch(&code) {
+            Ok(data) if !cancel.is_cancelled() => {
+                if let Some(stock) = stocks
+                    .lock()
+                    .unwrap()
+                    .iter_mut()
+                    .find(|stock| stock.code == code)
+                {
+                    store(stock, data);
+                }
+                *dirty.lock().unwrap() = true;
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!("{} 请求{}失败: {}", code, name, err),
+        });
+    }
+
+    //在后台一次请求多个code的数据,成功后用store保存到各自的stock上
+    fn spawn_batch_load<T: Send + 'static>(
+        &self,
+        codes: Vec<String>,
+        name: &'static str,
+        fetch: fn(&[String]) -> Result<Vec<(String, T)>, String>,
+        store: fn(&mut Stock, T),
+    ) {
+        if codes.is_empty() {
+            return;
+        }
+        let stocks = self.stocks.clone();
+        let dirty = self.dirty.clone();
+        self.executor.spawn(move |cancel| match fetch(&codes) {
+            Ok(_) if cancel.is_cancelled() => {}
+            Ok(list) => {
+                let mut stocks = stocks.lock().unwrap();
+                let index = code_index(&stocks);
+                for (code, data) in list {
+                    if let Some(&i) = index.get(&code) {
+                        store(&mut stocks[i], data);
+                    }
+                }
+                *dirty.lock().unwrap() = true;
+            }
+            Err(err) => tracing::warn!("请求{}失败: {}", name, err),
+        });
+    }
+
+    //分红送配每天请求一次,列表要显示除权提醒,所以沪深的stock都要请求,不只是选中的
+    pub fn load_dividends(&self) {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        let codes: Vec<String> = self
+            .stocks
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|stock| {
+                (stock.is_cn() || market::is_blind(&stock.code))
+                    && !stock.is_index()
+                    && stock.dividends_date != today
+            })
+            .map(|stock| {
+                stock.dividends_date = today.clone();
+                stock.code.clone()
+            })
+            .collect();
+        self.spawn_batch_load(
+            codes,
+            "分红送配",
+            api::fetch_dividends,
+            |stock, dividends| stock.dividends = dividends,
+        );
+    }
+
+    //财报预约披露日每天请求一次,财报日历要列出所有stock,所以沪深的stock都要请求
+    pub fn load_reports(&self) {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        let codes: Vec<String> = self
+            .stocks
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|stock| {
+                (stock.is_cn() || market::is_blind(&stock.code))
+                    && !stock.is_index()
+                    && stock.report_date != today
+            })
+            .map(|stock| {
+                stock.report_date = today.clone();
+                stock.code.clone()
+            })
+            .collect();
+        self.spawn_batch_load(
+            codes,
+            "财报日期",
+            api::fetch_reports,
+            |stock, report| stock.report = report,
+        );
+    }
+
+    //日K线每天请求一次
+    pub fn load_kline(&self) {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        self.load_selected(
+            "K线",
+            |stock| {
+                if stock.candles_date == today {
+                    return false;
+                }
+                stock.candles_date = today;
+                true
+            },
+            |code| api::fetch_kline(code, KLINE_DAYS),
+            |stock, candles| stock.candles = candles,
+        );
+    }
+
+    //分时数据在每次行情更新后请求一次
+    pub fn load_timeline(&self) {
+        self.load_selected(
+            "分时",
+            |stock| {
+                if stock.time.is_empty() || stock.timeline_time == stock.time {
+                    return false;
+                }
+                stock.timeline_time = stock.time.clone();
+                true
+            },
+            api::fetch_timeline,
+            |stock, timeline| stock.timeline = timeline,
+        );
+    }
+
+    //显示逐笔成交时,每次行情更新后请求一次
+    pub fn load_ticks(&self) {
+        if self.detail_tab != DetailTab::Ticks {
+            return;
+        }
+        self.load_selected(
+            "逐笔成交",
+            |stock| {
+                if stock.time.is_empty() || stock.ticks_time == stock.time {
+                    return false;
+                }
+                stock.ticks_time = stock.time.clone();
+                true
+            },
+            |code| api::fetch_ticks(code, TICK_COUNT),
+            |stock, ticks| stock.ticks = ticks,
+        );
+    }
+
+    //基本面和机构评级每天请求一次,只有切换到基本面时才请求,评级只有沪深的
+    pub fn load_fundamentals(&self) {
+        if self.detail_tab != DetailTab::Fundamentals {
+            return;
+        }
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        self.load_selected(
+            "基本面",
+            |stock| {
+                if stock.fundamentals_date == today {
+                    return false;
+                }
+                stock.fundamentals_date = today.clone();
+                true
+            },
+            api::fetch_fundamentals,
+            |stock, fundamentals| stock.fundamentals = Some(fundamentals),
+        );
+        self.load_selected(
+            "机构评级",
+            |stock| {
+                if stock.rating_date == today
+                    || stock.is_index()
+                    || !(stock.is_cn() || market::is_blind(&stock.code))
+                {
+                    return false;
+                }
+                stock.rating_date = today;
+                true
+            },
+            api::fetch_rating,
+            |stock, rating| stock.rating = rating,
+        );
+    }
+
+    //公司资料每个stock只请求一次,只有切换到简介时才请求
+    pub fn load_profile(&self) {
+        if self.detail_tab != DetailTab::Profile {
+            return;
+        }
+        self.load_selected(
+            "公司资料",
+            |stock| {
+                if stock.profile_requested || stock.is_index() {
+                    return false;
+                }
+                stock.profile_requested = true;
+                true
+            },
+            api::fetch_profile,
+            |stock, profile| stock.profile = Some(profile),
+        );
+    }
+
+    //大宗交易每天请求一次,只有沪深京的个股有
+    pub fn load_block_trades(&self) {
+        if self.detail_tab != DetailTab::BlockTrades {
+            return;
+        }
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        self.load_selected(
+            "大宗交易",
+            |stock| {
+                if stock.block_trades_date == today
+                    || stock.is_index()
+                    || !(stock.is_cn() || market::is_blind(&stock.code))
+                {
+                    return false;
+                }
+                stock.block_trades_date = today;
+                true
+            },
+            api::fetch_block_trades,
+            |stock, trades| stock.block_trades = trades,
+        );
+    }
+
+    //融资融券每天请求一次,只有沪深京的个股有,不是两融标的时返回空的
+    pub fn load_margins(&self) {
+        if self.detail_tab != DetailTab::Margin {
+            return;
+        }
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        self.load_selected(
+            "融资融券",
+            |stock| {
+                if stock.margins_date == today
+                    || stock.is_index()
+                    || !(stock.is_cn() || market::is_blind(&stock.code))
+                {
+                    return false;
+                }
+                stock.margins_date = today;
+                true
+            },
+            api::fetch_margins,
+            |stock, margins| stock.margins = margins,
+        );
+    }
+
+    //模拟持仓的风险指标要用行业和日K线,在模拟页时请求
+    pub fn load_positions(&self) {
+        if self.tab != Tab::Paper {
+            return;
+        }
+        let codes: Vec<_> = self
+            .paper
+            .positions
+            .iter()
+            .map(|p| p.code.clone())
+            .collect();
+        self.load_fundamentals_and_kline(&codes);
+    }
+
+    //提醒条件里可以用均线和基本面,有条件的stock都要请求日K线和基本面
+    pub fn load_conditions(&self) {
+        let codes: Vec<_> = self
+            .stocks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|stock| stock.alerts.iter().any(|alert| !alert.condition.is_empty()))
+            .map(|stock| stock.code.clone())
+            .collect();
+        if !codes.is_empty() {
+            self.load_fundamentals_and_kline(&codes);
+        }
+    }
+
+    //请求codes的基本面和日K线,每天一次,不是选中的stock也请求
+    pub fn load_fundamentals_and_kline(&self, codes: &[String]) {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        let (mut fundamentals, mut klines) = (vec![], vec![]);
+        let codes: HashSet<&String> = codes.iter().collect();
+        for stock in self.stocks.lock().unwrap().iter_mut() {
+            if !codes.contains(&stock.code) {
+                continue;
+            }
+            if stock.fundamentals_date != today {
+                stock.fundamentals_date = today.clone();
+                fundamentals.push(stock.code.clone());
+            }
+            if stock.candles_date != today {
+                stock.candles_date = today.clone();
+                klines.push(stock.code.clone());
+            }
+        }
+        for code in fundamentals {
+            self.spawn_load(code, "基本面", api::fetch_fundamentals, |stock, f| {
+                stock.fundamentals = Some(f)
             });
         }
+        for code in klines {
+            self.spawn_load(
+                code,
+                "K线",
+                |code| api::fetch_kline(code, KLINE_DAYS),
+                |stock, candles| stock.candles = candles,
+            );
+        }
+    }
+
+    //新闻每小时请求一次,只有切换到新闻时才请求
+    pub fn load_news(&self) {
+        if self.detail_tab != DetailTab::News {
+            return;
+        }
+        let hour = Local::now().format("%Y-%m-%d %H").to_string();
+        self.load_selected(
+            "新闻",
+            |stock| {
+                if stock.news_time == hour {
+                    return false;
+                }
+                stock.news_time = hour;
+                true
+            },
+            api::fetch_news,
+            |stock, news| stock.news = news,
+        );
     }
 
-    pub fn get_codes(&self) -> String {
+    //公告每天请求一次,只有切换到公告时才请求
+    pub fn load_announcements(&self) {
+        if self.detail_tab != DetailTab::Announcements {
+            return;
+        }
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        self.load_selected(
+            "公告",
+            |stock| {
+                if stock.announcements_date == today {
+                    return false;
+                }
+                stock.announcements_date = today;
+                true
+            },
+            api::fetch_announcements,
+            |stock, announcements| stock.announcements = announcements,
+        );
+    }
+
+    //板块页每分钟请求一次,切换行业和概念时马上请求
+    pub fn load_boards(&mut self) {
+        if self.tab != Tab::Boards {
+            return;
+        }
+        if let Some((concept, time)) = self.boards_time {
+            if concept == self.concept && time.elapsed() < Duration::from_secs(60) {
+                return;
+            }
+        }
+        self.boards_time = Some((self.concept, Instant::now()));
+        let concept = self.concept;
+        let boards = self.boards.clone();
+        let dirty = self.dirty.clone();
+        self.executor
+            .spawn(move |cancel| match api::fetch_boards(concept) {
+                Ok(data) if !cancel.is_cancelled() => {
+                    *boards.lock().unwrap() = data;
+                    *dirty.lock().unwrap() = true;
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("请求板块失败: {}", err),
+            });
+    }
+
+    //每隔KEYWORD_INTERVAL请求一次沪深stock的最新公告,和已经加载的新闻一起检查关键词
+    pub fn check_keywords(&mut self) {
+        if self.keywords.is_empty()
+            || self
+                .keywords_time
+                .map_or(false, |time| time.elapsed() < KEYWORD_INTERVAL)
+        {
+            return;
+        }
+        self.keywords_time = Some(Instant::now());
         let codes: Vec<String> = self
             .stocks
             .lock()
             .unwrap()
             .iter()
+            .filter(|stock| (stock.is_cn() || market::is_blind(&stock.code)) && !stock.is_index())
             .map(|stock| stock.code.clone())
             .collect();
-        codes.join(",")
+        let keywords = self.keywords.clone();
+        let stocks = self.stocks.clone();
+        let notices = self.notices.clone();
+        let notify = self.notify.clone();
+        let mqtt = self.mqtt.clone();
+        let error = self.error.clone();
+        let dirty = self.dirty.clone();
+        self.executor.spawn(move |cancel| {
+            //公告取不到时照样检查已经加载的新闻
+            let announcements = if codes.is_empty() {
+                vec![]
+            } else {
+                api::fetch_recent_announcements(&codes).unwrap_or_else(|err| {
+                    tracing::warn!("请求公告失败: {}", err);
+                    vec![]
+                })
+            };
+            if cancel.is_cancelled() {
+                return;
+            }
+            let mut messages = vec![];
+            let mut found = vec![];
+            for stock in stocks.lock().unwrap().iter_mut() {
+                let mut items: Vec<News> = announcements
+                    .iter()
+                    .filter(|(code, _)| *code == stock.code)
+                    .map(|(_, news)| news.clone())
+                    .collect();
+                items.extend(stock.news.iter().cloned());
+                items.extend(stock.announcements.iter().cloned());
+                found.extend(alert::check_keywords(stock, &items, &keywords));
+            }
+            for notice in found {
+                tracing::info!("触发提醒 {}", notice.message);
+                if let Some(mqtt) = &mqtt {
+                    messages.push(
+                        mqtt.alert(&notice.code, json!({"message": notice.message}).to_string()),
+                    );
+                }
+                notify.send(notice.title, &notice.message, &error, &dirty);
+                notices.lock().unwrap().push(notice);
+                *dirty.lock().unwrap() = true;
+            }
+            if let Some(mqtt) = &mqtt {
+                mqtt.publish(messages, &error, &dirty);
+            }
+        });
+    }
+
+    //当前页显示的新闻或公告,其他页没有
+    pub fn news_items<'a>(&self, stock: &'a Stock) -> &'a [News] {
+        match self.detail_tab {
+            DetailTab::News => &stock.news,
+            DetailTab::Announcements => &stock.announcements,
+            _ => &[],
+        }
+    }
+
+    //当前选中的新闻或公告,用[]移动
+    pub fn selected_news(&self) -> Option<News> {
+        let stocks = self.stocks.lock().unwrap();
+        let stock = stocks.get(self.stocks_state.selected()?)?;
+        self.news_items(stock).get(self.news_index).cloned()
+    }
+
+    //在新闻或公告页搜索时,选中第一条标题包含关键字的,忽略大小写
+    pub fn search_news(&mut self) {
+        let search = self.search.to_lowercase();
+        if search.is_empty() {
+            return;
+        }
+        let found = {
+            let stocks = self.stocks.lock().unwrap();
+            self.stocks_state
+                .selected()
+                .and_then(|i| stocks.get(i))
+                .and_then(|stock| {
+                    self.news_items(stock)
+                        .iter()
+                        .position(|news| news.title.to_lowercase().contains(&search))
+                })
+        };
+        if let Some(i) = found {
+            self.news_index = i;
+        }
+    }
+
+    //输入变化后在后台搜索候选
+    pub fn suggest(&mut self) {
+        let query = self.input.trim().to_string();
+        if query.is_empty() {
+            self.clear_suggestions();
+            return;
+        }
+        self.suggestion = None;
+        *self.suggest_query.lock().unwrap() = query.clone();
+        let suggestions = self.suggestions.clone();
+        let suggest_query = self.suggest_query.clone();
+        let dirty = self.dirty.clone();
+        self.executor
+            .spawn(move |cancel| match api::search(&query) {
+                Ok(result) if !cancel.is_cancelled() && *suggest_query.lock().unwrap() == query => {
+                    *suggestions.lock().unwrap() = result;
+                    *dirty.lock().unwrap() = true;
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("搜索{}失败: {}", query, err),
+            });
+    }
+
+    //退出新建时清掉候选,还没返回的搜索结果也不要了
+    pub fn clear_suggestions(&mut self) {
+        self.suggestion = None;
+        self.suggest_query.lock().unwrap().clear();
+        self.suggestions.lock().unwrap().clear();
+    }
+
+    //后台检查新版本,失败了只记日志,不打扰用户
+    pub fn check_update(&self) {
+        let new_version = self.new_version.clone();
+        let dirty = self.dirty.clone();
+        self.executor
+            .spawn(move |cancel| match update::latest_release() {
+                Ok(release) if release.is_newer() && !cancel.is_cancelled() => {
+                    tracing::info!("发现新版本 {}", release.version);
+                    *new_version.lock().unwrap() = release.version;
+                    *dirty.lock().unwrap() = true;
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("{}", err),
+            });
+    }
+
+    //列表和详情都至少保留10%
+    pub fn set_list_percent(&mut self, percent: i32) {
+        self.list_percent = percent.clamp(10, 90) as u16;
+    }
+
+    //列表要显示的行,不分组时每个stock一行,分组时折叠的分组只显示标题
+    pub fn list_rows(&self, stocks: &[Stock]) -> Vec<ListRow> {
+        if !self.grouped {
+            return (0..stocks.len()).map(ListRow::Stock).collect();
+        }
+        let mut rows = vec![];
+        for section in Market::SECTIONS {
+            let members: Vec<_> = (0..stocks.len())
+                .filter(|i| stocks[*i].market().section() == section)
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+            rows.push(ListRow::Header(section));
+            if !self.collapsed.contains(&section) {
+                rows.extend(members.into_iter().map(ListRow::Stock));
+            }
+        }
+        rows
+    }
+
+    //选中的stock在第几行,所在分组折叠时就是分组的标题行
+    pub fn selected_row(&self, stocks: &[Stock], rows: &[ListRow]) -> Option<usize> {
+        let sel = self
+            .stocks_state
+            .selected()
+            .filter(|sel| *sel < stocks.len())?;
+        let section = stocks[sel].market().section();
+        rows.iter().position(|row| match row {
+            ListRow::Stock(i) => *i == sel,
+            ListRow::Header(header) => *header == section && self.collapsed.contains(header),
+        })
+    }
+
+    //上下移动选中的stock,折叠的分组当作一行,选中其中第一个
+    pub fn move_selection(&mut self, step: i32) {
+        let stocks = self.stocks.lock().unwrap();
+        let rows = self.list_rows(&stocks);
+        //每个可以停留的行和对应的stock
+        let stops: Vec<_> = rows
+            .iter()
+            .enumerate()
+            .filter_map(|(row, item)| match item {
+                ListRow::Stock(i) => Some((row, *i)),
+                ListRow::Header(section) if self.collapsed.contains(section) => stocks
+                    .iter()
+                    .position(|s| s.market().section() == *section)
+                    .map(|i| (row, i)),
+                ListRow::Header(_) => None,
+            })
+            .collect();
+        let selected = self.selected_row(&stocks, &rows);
+        drop(stocks);
+        if stops.is_empty() {
+            return;
+        }
+        let next = match stops.iter().position(|(row, _)| Some(*row) == selected) {
+            Some(pos) => (pos as i32 + step).clamp(0, stops.len() as i32 - 1) as usize,
+            None => 0,
+        };
+        self.stocks_state.select(Some(stops[next].1));
+    }
+
+    //折叠或展开分组,选中的stock移到分组的第一个,保证折叠后标题行是选中的
+    pub fn toggle_section(&mut self, section: Market) {
+        if let Some(pos) = self.collapsed.iter().position(|s| *s == section) {
+            self.collapsed.remove(pos);
+        } else {
+            self.collapsed.push(section);
+        }
+        let first = self
+            .stocks
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|s| s.market().section() == section);
+        if first.is_some() {
+            self.stocks_state.select(first);
+        }
+    }
+
+    //根据可见行数调整滚动位置,保证选中的行可见
+    pub fn scroll_list(&mut self, height: usize) {
+        let stocks = self.stocks.lock().unwrap();
+        let rows = self.list_rows(&stocks);
+        let selected = self.selected_row(&stocks, &rows);
+        let count = stocks.len();
+        drop(stocks);
+        let total = rows.len();
+        if let Some(sel) = selected {
+            if sel < self.list_offset {
+                self.list_offset = sel;
+            } else if height > 0 && sel >= self.list_offset + height {
+                self.list_offset = sel + 1 - height;
+            }
+        }
+        self.list_offset = self.list_offset.min(total.saturating_sub(height));
+        //选中的行被删除后可能超出范围
+        if self
+            .stocks_state
+            .selected()
+            .map_or(false, |sel| sel >= count)
+        {
+            self.stocks_state.select(None);
+        }
+    }
+
+    //取出并清除重绘标志
+    //沪深交易日收盘后发一次收盘报告,要等取到了收盘后的行情,每天只发一次
+    pub fn send_eod_report(&mut self) {
+        if !self.eod_report || self.notify.is_empty() {
+            return;
+        }
+        let now = Local::now().with_timezone(&Market::SH.utc_offset(Local::now().date_naive()));
+        let today = now.format("%Y-%m-%d").to_string();
+        let close = now.date_naive().and_hms_opt(15, 0, 0).unwrap();
+        if self.eod_date == today
+            || !Market::SH.is_trading_day(now.date_naive())
+            || now.naive_local() < close
+        {
+            return;
+        }
+        let body = {
+            let stocks = self.stocks.lock().unwrap();
+            let closed = stocks.iter().any(|stock| {
+                stock.market().section() == Market::SH
+                    && stock
+                        .trade_time()
+                        .map_or(false, |time| time.naive_local() >= close)
+            });
+            if !closed {
+                return;
+            }
+            eod_report(&stocks)
+        };
+        tracing::info!("发送收盘报告 {}", today);
+        self.notify.send(
+            &format!("收盘报告 {}", today),
+            &body,
+            &self.error,
+            &self.dirty,
+        );
+        self.eod_date = today;
+        if let Err(err) = self.save_stocks() {
+            tracing::error!("保存配置失败: {}", err);
+        }
+    }
+
+    //画列表时用缓存的布局,行情变了、stock增删了或者缓存太旧时重新计算
+    pub fn list_layout(&mut self, stocks: &[Stock]) -> ListLayout {
+        let stale = std::mem::replace(&mut *self.list_stale.lock().unwrap(), false);
+        let expired = self.list_layout.as_ref().map_or(true, |(time, layout)| {
+            time.elapsed() >= LIST_LAYOUT_TTL || layout.total != stocks.len()
+        });
+        if stale || expired {
+            let layout = widget::list_layout(stocks, self.conversion().as_ref(), self.hkd_cny());
+            self.list_layout = Some((Instant::now(), layout));
+        }
+        self.list_layout.as_ref().unwrap().1.clone()
+    }
+
+    pub fn take_dirty(&self) -> bool {
+        let mut dirty = self.dirty.lock().unwrap();
+        std::mem::replace(&mut *dirty, false)
+    }
+
+    //用户正在操作时提高tick频率,休市时降低,减少唤醒次数
+    pub fn tick_rate(&self) -> Duration {
+        //回放时需要按速度前进,最快每秒8步
+        if let AppState::Replaying = self.state {
+            Duration::from_millis(100)
+        } else if self.last_input.elapsed() < Duration::from_secs(10) {
+            Duration::from_millis(250)
+        } else if self.should_auto_refresh() {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_secs(5)
+        }
+    }
+
+    //打开了换算时返回当前的汇率
+    pub fn conversion(&self) -> Option<Conversion> {
+        self.convert.then(|| Conversion {
+            currency: self.display_currency.clone(),
+            rates: self.fx_rates.lock().unwrap().clone(),
+        })
+    }
+
+    //港元兑人民币的汇率,用换算的汇率算,rates都是对display_currency的,还没取到时是None
+    pub fn hkd_cny(&self) -> Option<f64> {
+        let rates = self.fx_rates.lock().unwrap();
+        let rate = |currency: &str| {
+            if currency == self.display_currency {
+                Some(1.0)
+            } else {
+                rates.get(currency).copied()
+            }
+        };
+        Some(rate("HKD")? / rate("CNY")?)
+    }
+
+    //打开换算后每隔FX_INTERVAL请求一次自选里各币种对display_currency的汇率,有新币种时马上请求,
+    //打开A/H溢价时还要港元和人民币的汇率
+    pub fn load_fx_rates(&mut self) {
+        if !self.convert && !self.ah_premium {
+            return;
+        }
+        let target = self.display_currency.clone();
+        let mut currencies: Vec<&'static str> = self
+            .stocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|stock| stock.currency())
+            .chain(if self.ah_premium {
+                vec!["HKD", "CNY"]
+            } else {
+                vec![]
+            })
+            .filter(|currency| *currency != target && market::CURRENCIES.contains(currency))
+            .collect();
+        currencies.sort();
+        currencies.dedup();
+        let missing = {
+            let rates = self.fx_rates.lock().unwrap();
+            currencies
+                .iter()
+                .any(|currency| !rates.contains_key(*currency))
+        };
+        if currencies.is_empty()
+            || (!missing
+                && self
+                    .fx_time
+                    .map_or(false, |time| time.elapsed() < FX_INTERVAL))
+        {
+            return;
+        }
+        self.fx_time = Some(Instant::now());
+        let codes: Vec<String> = currencies
+            .iter()
+            .map(|currency| format!("FX_{}{}", currency, target))
+            .collect();
+        let rates = self.fx_rates.clone();
+        let dirty = self.dirty.clone();
+        let list_stale = self.list_stale.clone();
+        self.executor
+            .spawn(move |cancel| match api::fetch(&api::Forex, &codes) {
+                Ok(_) if cancel.is_cancelled() => {}
+                Ok(quotes) => {
+                    let mut rates = rates.lock().unwrap();
+                    for (code, quote) in quotes {
+                        match quote {
+                            Ok(quote) if quote.price > 0.0 => {
+                                rates.insert(code[3..6].to_string(), quote.price);
+                            }
+                            Ok(_) => {}
+                            Err(err) => tracing::warn!("{} 请求汇率失败: {}", code, err),
+                        }
+                    }
+                    *list_stale.lock().unwrap() = true;
+                    *dirty.lock().unwrap() = true;
+                }
+                Err(err) => tracing::warn!("请求汇率失败: {}", err),
+            });
+    }
+
+    //打开了快速刷新并且行情来源支持时才缩短刷新间隔
+    pub fn fast_refreshing(&self) -> bool {
+        self.fast_refresh && self.provider.fast_refresh()
+    }
+
+    //自动刷新的间隔,快速刷新时缩短
+    pub fn refresh_interval(&self) -> Duration {
+        if self.fast_refreshing() {
+            Duration::from_secs(FAST_INTERVAL)
+        } else {
+            Duration::from_secs(60)
+        }
+    }
+
+    //配置了刷新时间窗口时按窗口判断,否则按市场交易时间判断
+    pub fn should_auto_refresh(&self) -> bool {
+        if self.refresh_windows.is_empty() {
+            self.is_market_open()
+        } else {
+            let now = Local::now().time();
+            self.refresh_windows
+                .iter()
+                .any(|window| market::in_window(now, *window))
+        }
+    }
+
+    //只要有一个stock所在的市场在交易就需要自动刷新
+    pub fn is_market_open(&self) -> bool {
+        self.stocks.lock().unwrap().iter().any(Stock::is_open)
+    }
+
+    pub fn get_codes(&self) -> Vec<String> {
+        self.stocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|stock| stock.code.clone())
+            .collect()
     }
 }
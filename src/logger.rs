@@ -0,0 +1,157 @@
+use std::{backtrace::Backtrace, fmt, fs, path::PathBuf};
+
+use chrono::Local;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::{format::Writer, time::FormatTime};
+
+//TUI占用了终端,日志只能写文件,每天一个文件,只保留最近几天
+const KEEP_DAYS: usize = 7;
+
+//越往后越详细,查看日志时按级别过滤
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        })
+    }
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::Error, Level::Warn, Level::Info, Level::Debug];
+
+    pub fn parse(text: &str) -> Option<Level> {
+        Level::ALL
+            .iter()
+            .copied()
+            .find(|level| level.to_string() == text)
+    }
+
+    //查看日志时按L切换过滤级别
+    pub fn next(&self) -> Level {
+        let index = Level::ALL
+            .iter()
+            .position(|level| level == self)
+            .unwrap_or(0);
+        Level::ALL[(index + 1) % Level::ALL.len()]
+    }
+}
+
+//日志和崩溃报告都放在这个目录
+pub fn data_dir() -> PathBuf {
+    dirs_next::home_dir().unwrap().join(".stock")
+}
+
+pub fn log_dir() -> PathBuf {
+    data_dir().join("logs")
+}
+
+//每行开头只写本地的时分秒,日期在文件名里
+struct LocalTime;
+
+impl FormatTime for LocalTime {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        write!(w, "{}", Local::now().format("%H:%M:%S%.3f"))
+    }
+}
+
+//tracing-appender按UTC日期滚动,文件名是stock.2024-01-05.log,过期的文件自动删掉
+//--verbose时才记录Debug级别,比如每次请求的地址
+pub fn init(verbose: bool) {
+    let _ = fs::create_dir_all(log_dir());
+    let appender = match RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("stock")
+        .filename_suffix("log")
+        .max_log_files(KEEP_DAYS)
+        .build(log_dir())
+    {
+        Ok(appender) => appender,
+        //写日志失败时什么也不做,不能影响正常功能
+        Err(_) => return,
+    };
+    let _ = tracing_subscriber::fmt()
+        .with_writer(appender)
+        .with_ansi(false)
+        .with_target(false)
+        .with_timer(LocalTime)
+        .with_max_level(if verbose {
+            LevelFilter::DEBUG
+        } else {
+            LevelFilter::INFO
+        })
+        .try_init();
+}
+
+//最新的日志文件,跨过UTC的午夜后就是新的一个
+fn latest_file() -> Option<PathBuf> {
+    fs::read_dir(log_dir())
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name.starts_with("stock.") && name.ends_with(".log")
+                })
+        })
+        .max()
+}
+
+fn read_latest_file() -> String {
+    latest_file()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default()
+}
+
+//读取最新的日志,只保留不比level详细的行
+pub fn read_latest(level: Level) -> Vec<String> {
+    read_latest_file()
+        .lines()
+        .filter(|line| {
+            line.split_whitespace()
+                .nth(1)
+                .and_then(Level::parse)
+                .map_or(false, |l| l <= level)
+        })
+        .map(String::from)
+        .collect()
+}
+
+//崩溃报告里附带的最近日志行数
+const CRASH_LOG_LINES: usize = 50;
+
+//panic时把错误信息、调用栈、最近的日志和版本号写到一个文件里,返回文件路径
+pub fn write_crash_report(info: &dyn fmt::Display) -> Option<PathBuf> {
+    let now = Local::now();
+    tracing::error!("{}", info);
+    let log = read_latest_file();
+    let lines: Vec<_> = log.lines().collect();
+    let recent = lines[lines.len().saturating_sub(CRASH_LOG_LINES)..].join("\n");
+    let report = format!(
+        "版本: {}\n时间: {}\n系统: {} {}\n\n{}\n\n调用栈:\n{}\n\n最近的日志:\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        now.format("%Y-%m-%d %H:%M:%S"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        info,
+        Backtrace::force_capture(),
+        recent
+    );
+    let path = data_dir().join(format!("crash-{}.txt", now.format("%Y%m%d-%H%M%S")));
+    fs::create_dir_all(data_dir()).ok()?;
+    fs::write(&path, report).ok()?;
+    Some(path)
+}
@@ -1,21 +1,98 @@
-use std::{
-    error::Error,
-    time::{Duration, Instant},
-};
+use std::{error::Error, time::Instant};
 
-use stock::{events, widget, App, AppState, CrossTerminal, DynResult, TerminalFrame};
-use tui::{backend::CrosstermBackend, widgets, Terminal};
+use stock::{
+    events, logger, update, widget, App, AppState, CrossTerminal, DetailTab, DynResult, Tab,
+    TerminalFrame, MAX_PINNED,
+};
+use tui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout, Rect},
+    widgets::{self, ListState},
+    Terminal,
+};
 use unicode_width::UnicodeWidthStr;
 
 fn main() -> DynResult {
+    //--verbose时日志里记录更详细的调试信息
+    logger::init(std::env::args().any(|arg| arg == "--verbose" || arg == "-v"));
+    tracing::info!("启动 v{}", env!("CARGO_PKG_VERSION"));
+    //主线程panic时先恢复终端,不然错误信息看不到,终端也没法用了
+    //后台线程panic不影响界面,只写崩溃报告,恢复终端会把还在运行的界面弄乱
+    std::panic::set_hook(Box::new(|info| {
+        if std::thread::current().name() != Some("main") {
+            logger::write_crash_report(info);
+            return;
+        }
+        let _ = crossterm::terminal::disable_raw_mode();
+        let _ = crossterm::execute!(
+            std::io::stdout(),
+            crossterm::event::DisableMouseCapture,
+            crossterm::terminal::LeaveAlternateScreen
+        );
+        eprintln!("{}", info);
+        if let Some(path) = logger::write_crash_report(info) {
+            eprintln!("崩溃报告已保存到 {}", path.display());
+        }
+    }));
+    //stock update --check只检查新版本并打印更新说明,不进入界面
+    let args: Vec<_> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("update") {
+        return check_update(args.iter().any(|arg| arg == "--check"));
+    }
+    //stock alias import aliases.csv导入自定义别名
+    if args.get(1).map(String::as_str) == Some("alias") {
+        return match (args.get(2).map(String::as_str), args.get(3)) {
+            (Some("import"), Some(path)) => {
+                println!("导入了{}个别名", stock::import_aliases(path)?);
+                Ok(())
+            }
+            _ => Err("用法: stock alias import <名称,代码格式的csv文件>".into()),
+        };
+    }
+    //stock calendar export stock.ics导出除权除息日和财报披露日
+    if args.get(1).map(String::as_str) == Some("calendar") {
+        return match (args.get(2).map(String::as_str), args.get(3)) {
+            (Some("export"), Some(path)) => {
+                println!("导出了{}个日程", stock::export_calendar(path)?);
+                Ok(())
+            }
+            _ => Err("用法: stock calendar export <ics文件>".into()),
+        };
+    }
     let mut app = App::new();
+    if args.iter().any(|arg| arg == "--plain") {
+        app.plain = true;
+    }
     let mut terminal = init_terminal()?;
     main_loop(&mut terminal, &mut app)?;
+    //不等待还没返回的请求,直接退出
+    app.executor.shutdown();
     close_terminal(terminal)?;
+    //保存最后的行情,下次启动时先显示,放在恢复终端之后,出错时能看到提示
+    app.save_stocks()?;
+    tracing::info!("退出");
 
     Ok(())
 }
 
+fn check_update(check: bool) -> DynResult {
+    if !check {
+        println!("暂不支持自动更新,请使用stock update --check检查新版本后手动下载");
+        return Ok(());
+    }
+    println!("当前版本 v{}", update::VERSION);
+    let release = update::latest_release()?;
+    if release.is_newer() {
+        println!(
+            "发现新版本 v{}\n{}\n\n{}",
+            release.version, release.url, release.notes
+        );
+    } else {
+        println!("已经是最新版本");
+    }
+    Ok(())
+}
+
 fn init_terminal() -> Result<CrossTerminal, Box<dyn Error>> {
     let mut stdout = std::io::stdout();
     crossterm::terminal::enable_raw_mode()?;
@@ -45,16 +122,24 @@ fn close_terminal(mut terminal: CrossTerminal) -> DynResult {
 fn main_loop(terminal: &mut CrossTerminal, app: &mut App) -> DynResult {
     let mut last_tick = Instant::now();
     while !app.should_exit {
-        terminal.draw(|f| {
-            on_draw(f, app);
-        })?;
+        //没有变化时不重绘,减少空闲时的CPU占用
+        if app.take_dirty() {
+            terminal.draw(|f| {
+                on_draw(f, app);
+            })?;
+        }
 
         if crossterm::event::poll(
-            Duration::from_secs(1)
+            app.tick_rate()
                 .checked_sub(last_tick.elapsed())
                 .unwrap_or_default(),
         )? {
-            events::on_events(crossterm::event::read()?, app);
+            let event = crossterm::event::read()?;
+            //窗口大小变化时立即调整缓冲区,避免下次绘制前按旧尺寸计算
+            if let crossterm::event::Event::Resize(_, _) = event {
+                terminal.autoresize()?;
+            }
+            events::on_events(event, app);
         } else {
             events::on_tick(app);
             last_tick = Instant::now();
@@ -65,25 +150,274 @@ fn main_loop(terminal: &mut CrossTerminal, app: &mut App) -> DynResult {
 }
 
 fn on_draw(frame: &mut TerminalFrame, app: &mut App) {
-    let chunks = widget::main_chunks(frame.size());
+    //纯文本模式:标题、列表、说明行、状态栏,没有边框和弹框
+    if app.plain {
+        let chunks = Layout::default()
+            .constraints(
+                [
+                    Constraint::Length(1),
+                    Constraint::Min(1),
+                    Constraint::Length(1),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(frame.size());
+        frame.render_widget(widget::title_bar(app, frame.size()), chunks[0]);
+        render_list(frame, app, chunks[1], false);
+        frame.render_widget(widget::announcement(app), chunks[2]);
+        frame.render_widget(widget::status_bar(app), chunks[3]);
+        return;
+    }
+    //专注模式不管窗口多小都只画列表
+    if app.focus {
+        let area = frame.size();
+        render_list(frame, app, area, false);
+        draw_popups(
+            frame,
+            app,
+            widget::main_chunks(area, app.list_percent, app.show_ticker)[4],
+        );
+        return;
+    }
+    //窗口太小时布局计算没有意义,只显示提示
+    if frame.size().width < widget::MIN_WIDTH || frame.size().height < widget::MIN_HEIGHT {
+        frame.render_widget(widget::too_small(frame.size()), frame.size());
+        return;
+    }
+    let chunks = widget::main_chunks(frame.size(), app.list_percent, app.show_ticker);
 
-    //list的render需要调render_stateful_widget,否则滚动状态不对,这里第一个参数不能是app,否则会和后面的mut stock_state冲突
-    frame.render_stateful_widget(
-        widget::stock_list(&app.stocks.lock().unwrap()),
-        chunks[1],
-        &mut app.stocks_state,
-    );
-    //因为render stock_list时会修改滚动状态，后面如果要用到这个值，就需要先做list的render
+    match app.tab {
+        Tab::List => {
+            //列表最下面留一行显示统计
+            let list = Rect {
+                height: chunks[1].height.saturating_sub(1),
+                ..chunks[1]
+            };
+            render_list(frame, app, list, true);
+            frame.render_widget(
+                widget::watchlist_stats(&app.stocks.lock().unwrap(), app.palette, app.conversion()),
+                Rect::new(list.x, list.y + list.height, list.width, 1),
+            );
+            render_details(frame, app, chunks[2]);
+        }
+        Tab::Log => {
+            let height = chunks[5].height as usize;
+            frame.render_widget(widget::log_view(app, height), chunks[5]);
+        }
+        Tab::Boards => {
+            let height = chunks[5].height as usize;
+            frame.render_widget(widget::board_view(app, height), chunks[5]);
+        }
+        Tab::Reports => {
+            let height = chunks[5].height as usize;
+            frame.render_widget(widget::report_view(app, height), chunks[5]);
+        }
+        Tab::Paper => {
+            frame.render_widget(widget::paper_view(app), chunks[5]);
+        }
+        Tab::Heatmap => {
+            let stocks = app.stocks.lock().unwrap();
+            for (rect, tile) in
+                widget::heatmap(&stocks, app.stocks_state.selected(), chunks[5], app.palette)
+            {
+                frame.render_widget(tile, rect);
+            }
+        }
+        Tab::Wall => {
+            let stocks = app.stocks.lock().unwrap();
+            for (rect, tile) in
+                widget::big_tiles(&stocks, app.stocks_state.selected(), chunks[5], app.palette)
+            {
+                frame.render_widget(tile, rect);
+            }
+        }
+    }
     frame.render_widget(widget::title_bar(app, frame.size()), chunks[0]);
-    frame.render_widget(widget::stock_detail(app), chunks[2]);
+    frame.render_widget(
+        widget::ticker_bar(
+            &app.ticker.lock().unwrap(),
+            *app.breadth.lock().unwrap(),
+            app.palette,
+        ),
+        chunks[6],
+    );
     frame.render_widget(widget::status_bar(app), chunks[3]);
+    draw_popups(frame, app, chunks[4]);
+}
+
+//详情区,选中的排第一个,后面是其他固定的stock
+fn render_details(frame: &mut TerminalFrame, app: &App, area: Rect) {
+    let conversion = app.conversion();
+    let stocks = app.stocks.lock().unwrap();
+    //回放时详情区只显示回放的stock
+    if let Some(replay) = &app.replay {
+        if let Some(stock) = stocks.iter().find(|stock| stock.code == replay.code) {
+            let snapshot = replay.snapshot(stock);
+            let title = format!(
+                "回放 {}/{} x{}{}",
+                replay.pos + 1,
+                stock.timeline.len(),
+                replay.speed,
+                if replay.playing { "" } else { " 暂停" }
+            );
+            frame.render_widget(
+                widget::stock_detail(
+                    Some(&snapshot),
+                    "",
+                    &title,
+                    conversion.as_ref(),
+                    app.hkd_cny(),
+                ),
+                area,
+            );
+            if let Some(chart) = widget::detail_chart_area(area) {
+                frame.render_widget(
+                    widget::price_chart(&snapshot, app.braille, app.palette),
+                    chart,
+                );
+            }
+            return;
+        }
+    }
+    let sel = app
+        .stocks_state
+        .selected()
+        .filter(|sel| *sel < stocks.len());
+    let mut panes: Vec<_> = sel.map(|sel| (&stocks[sel], "详情")).into_iter().collect();
+    panes.extend(
+        stocks
+            .iter()
+            .enumerate()
+            .filter(|(i, stock)| stock.pinned && Some(*i) != sel)
+            .map(|(_, stock)| (stock, "固定")),
+    );
+    panes.truncate(MAX_PINNED);
+    if panes.is_empty() {
+        frame.render_widget(
+            widget::stock_detail(None, &app.search, "详情", None, None),
+            area,
+        );
+    }
+    for (rect, (stock, title)) in widget::detail_grid(area, panes.len())
+        .into_iter()
+        .zip(panes)
+    {
+        frame.render_widget(
+            widget::stock_detail(
+                Some(stock),
+                &app.search,
+                title,
+                conversion.as_ref(),
+                app.hkd_cny(),
+            ),
+            rect,
+        );
+        if let Some(chart) = widget::detail_chart_area(rect) {
+            //选中的stock在走势图旁边显示买卖盘
+            let (chart, book) =
+                if (stock.bids.is_empty() && stock.asks.is_empty()) || title != "详情" {
+                    (chart, None)
+                } else {
+                    widget::split_order_book(chart)
+                };
+            if let Some(book) = book {
+                frame.render_widget(widget::order_book(stock, book.height, app.palette), book);
+            }
+            if title == "详情" && app.detail_tab == DetailTab::Kline {
+                if !stock.candles.is_empty() {
+                    frame
+                        .render_widget(widget::kline_chart(stock, app.braille, app.palette), chart);
+                }
+            } else if title == "详情" && app.detail_tab == DetailTab::Ticks {
+                frame.render_widget(widget::tick_list(stock, chart.height, app.palette), chart);
+            } else if title == "详情" && app.detail_tab == DetailTab::Fundamentals {
+                frame.render_widget(widget::fundamentals(stock, conversion.as_ref()), chart);
+            } else if title == "详情" && app.detail_tab == DetailTab::Profile {
+                frame.render_widget(widget::profile(stock), chart);
+            } else if title == "详情" && app.detail_tab == DetailTab::BlockTrades {
+                frame.render_widget(
+                    widget::block_trades(stock, chart.height, app.palette),
+                    chart,
+                );
+            } else if title == "详情" && app.detail_tab == DetailTab::Margin {
+                frame.render_widget(widget::margins(stock, chart.height, app.palette), chart);
+            } else if title == "详情"
+                && matches!(app.detail_tab, DetailTab::News | DetailTab::Announcements)
+            {
+                frame.render_widget(
+                    widget::news_list(
+                        app.news_items(stock),
+                        app.news_index,
+                        chart.height,
+                        app.detail_tab.title(),
+                        &app.search,
+                    ),
+                    chart,
+                );
+            } else if stock.chart_prices().len() >= 2 {
+                frame.render_widget(widget::price_chart(stock, app.braille, app.palette), chart);
+            }
+        }
+    }
+}
+
+//列表只生成可见部分的行,滚动位置自己维护,传给List的state是相对可见部分的
+fn render_list(frame: &mut TerminalFrame, app: &mut App, area: Rect, borders: bool) {
+    let height = if borders {
+        area.height.saturating_sub(2)
+    } else {
+        area.height
+    } as usize;
+    app.scroll_list(height);
+    let stocks = app.stocks.clone();
+    let stocks = stocks.lock().unwrap();
+    let layout = app.list_layout(&stocks);
+    let rows = app.list_rows(&stocks);
+    let mut visible_state = ListState::default();
+    visible_state.select(
+        app.selected_row(&stocks, &rows)
+            .map(|row| row - app.list_offset),
+    );
+    frame.render_stateful_widget(
+        widget::stock_list(app, &stocks, &rows, &layout, height, borders),
+        area,
+        &mut visible_state,
+    );
+}
 
-    if let AppState::Adding = app.state {
+//提醒弹框和输入框画在最上层
+fn draw_popups(frame: &mut TerminalFrame, app: &mut App, popup: Rect) {
+    if !app.notices.lock().unwrap().is_empty() {
+        frame.render_widget(widgets::Clear, popup);
+        frame.render_widget(widget::alert_toast(app), popup);
+    }
+
+    if let AppState::Comparing = app.state {
+        let area = widget::centered(frame.size(), 50, 16);
+        frame.render_widget(widgets::Clear, area);
+        frame.render_widget(widget::compare_popup(app), area);
+    }
+
+    if let AppState::Adding | AppState::Trading(_) = app.state {
         //popup需要先clear一下,否则下面的背景色会透上来
-        frame.render_widget(widgets::Clear, chunks[4]);
-        frame.render_widget(widget::stock_input(app), chunks[4]);
+        frame.render_widget(widgets::Clear, popup);
+        frame.render_widget(widget::stock_input(app), popup);
 
         //显示光标, width()接口依赖一个外部包,可以正确处理中文宽度
-        frame.set_cursor(chunks[4].x + app.input.width() as u16 + 1, chunks[4].y + 1);
+        frame.set_cursor(popup.x + app.input.width() as u16 + 1, popup.y + 1);
+
+        //输入框下面显示搜索到的候选
+        let count = app.suggestions.lock().unwrap().len() as u16;
+        if matches!(app.state, AppState::Adding) && count > 0 {
+            let below = frame.size().height.saturating_sub(popup.y + popup.height);
+            let area = Rect {
+                y: popup.y + popup.height,
+                height: (count + 2).min(below),
+                ..popup
+            };
+            frame.render_widget(widgets::Clear, area);
+            frame.render_widget(widget::suggestion_list(app), area);
+        }
     }
 }
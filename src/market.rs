@@ -0,0 +1,466 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Weekday};
+
+//沪深休市日(不含周末),每年底交易所公布次年安排后需要更新
+const HOLIDAYS_CN: &[&str] = &[
+    "2025-01-01",
+    "2025-01-28",
+    "2025-01-29",
+    "2025-01-30",
+    "2025-01-31",
+    "2025-02-03",
+    "2025-02-04",
+    "2025-04-04",
+    "2025-05-01",
+    "2025-05-02",
+    "2025-05-05",
+    "2025-06-02",
+    "2025-10-01",
+    "2025-10-02",
+    "2025-10-03",
+    "2025-10-06",
+    "2025-10-07",
+    "2025-10-08",
+    "2026-01-01",
+    "2026-01-02",
+    "2026-02-16",
+    "2026-02-17",
+    "2026-02-18",
+    "2026-02-19",
+    "2026-02-20",
+    "2026-02-23",
+    "2026-04-06",
+    "2026-05-01",
+    "2026-05-04",
+    "2026-05-05",
+    "2026-06-19",
+    "2026-09-25",
+    "2026-10-01",
+    "2026-10-02",
+    "2026-10-05",
+    "2026-10-06",
+    "2026-10-07",
+];
+
+//美股休市日(不含周末)
+const HOLIDAYS_US: &[&str] = &[
+    "2025-01-01",
+    "2025-01-09",
+    "2025-01-20",
+    "2025-02-17",
+    "2025-04-18",
+    "2025-05-26",
+    "2025-06-19",
+    "2025-07-04",
+    "2025-09-01",
+    "2025-11-27",
+    "2025-12-25",
+    "2026-01-01",
+    "2026-01-19",
+    "2026-02-16",
+    "2026-04-03",
+    "2026-05-25",
+    "2026-06-19",
+    "2026-07-03",
+    "2026-09-07",
+    "2026-11-26",
+    "2026-12-25",
+];
+
+//证券所在的市场,由网易的代码格式推断
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Market {
+    SH,
+    SZ,
+    HK,
+    US,
+    //币安的现货交易对,代码是CRYPTO_加交易对,比如CRYPTO_BTCUSDT
+    Crypto,
+    //外汇,代码是FX_加货币对,比如FX_USDCNY
+    Forex,
+    //场外基金,代码是FUND_加6位数字,行情是天天基金的盘中估值
+    Fund,
+    //黄金、原油等商品,代码是CMDTY_加COMMODITIES里的品种,比如CMDTY_AU9999
+    Commodity,
+    //北交所和新三板,代码是BJ_加6位数字,比如BJ_830799,网易没有这些行情
+    BJ,
+    Other,
+}
+
+impl Market {
+    //列表分组显示时的顺序,沪深合成一组,用SH代表
+    pub const SECTIONS: [Market; 8] = [
+        Market::SH,
+        Market::HK,
+        Market::US,
+        Market::Crypto,
+        Market::Forex,
+        Market::Fund,
+        Market::Commodity,
+        Market::Other,
+    ];
+
+    pub fn section(&self) -> Market {
+        match self {
+            Market::SZ | Market::BJ => Market::SH,
+            market => *market,
+        }
+    }
+
+    pub fn section_title(&self) -> &'static str {
+        match self.section() {
+            Market::SH | Market::SZ | Market::BJ => "沪深",
+            Market::HK => "港股",
+            Market::US => "美股",
+            Market::Crypto => "加密货币",
+            Market::Forex => "外汇",
+            Market::Fund => "基金",
+            Market::Commodity => "商品",
+            Market::Other => "其他",
+        }
+    }
+
+    //市场所在时区,美股要考虑夏令时
+    pub fn utc_offset(&self, date: NaiveDate) -> FixedOffset {
+        let hours = match self {
+            Market::US => {
+                if is_us_dst(date) {
+                    -4
+                } else {
+                    -5
+                }
+            }
+            Market::Crypto | Market::Forex => 0,
+            _ => 8,
+        };
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    //交易时段,都是交易所当地时间
+    fn sessions(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Market::SH | Market::SZ | Market::BJ | Market::Fund => {
+                &[("09:30", "11:30"), ("13:00", "15:00")]
+            }
+            Market::HK => &[("09:30", "12:00"), ("13:00", "16:00")],
+            Market::US => &[("09:30", "16:00")],
+            //上海金有夜盘,国际期货几乎全天交易,只按周末判断
+            Market::Crypto | Market::Forex | Market::Commodity | Market::Other => {
+                &[("00:00", "23:59")]
+            }
+        }
+    }
+
+    //港股的假期表还没有收录,只按周末判断
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        if let Weekday::Sat | Weekday::Sun = date.weekday() {
+            return false;
+        }
+        let holidays = match self {
+            Market::SH | Market::SZ | Market::BJ | Market::Fund => HOLIDAYS_CN,
+            Market::US => HOLIDAYS_US,
+            _ => &[],
+        };
+        !holidays.contains(&date.format("%Y-%m-%d").to_string().as_str())
+    }
+
+    //判断某个时刻是否在交易中,加密货币全天交易,不认识的市场总是认为在交易
+    pub fn is_open<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> bool {
+        if let Market::Crypto | Market::Other = self {
+            return true;
+        }
+        let utc = now.naive_utc();
+        let local = utc + Duration::seconds(self.utc_offset(utc.date()).local_minus_utc() as i64);
+        if !self.is_trading_day(local.date()) {
+            return false;
+        }
+        let time = local.time();
+        self.sessions().iter().any(|(start, end)| {
+            let start = NaiveTime::parse_from_str(start, "%H:%M").unwrap();
+            let end = NaiveTime::parse_from_str(end, "%H:%M").unwrap();
+            time >= start && time <= end
+        })
+    }
+
+    pub fn tz_name(&self, date: NaiveDate) -> &'static str {
+        match self {
+            Market::US => {
+                if is_us_dst(date) {
+                    "EDT"
+                } else {
+                    "EST"
+                }
+            }
+            Market::HK => "HKT",
+            Market::Crypto | Market::Forex => "UTC",
+            _ => "CST",
+        }
+    }
+}
+
+//美国夏令时从3月第二个周日开始,到11月第一个周日结束,这里只精确到日期
+fn is_us_dst(date: NaiveDate) -> bool {
+    let start = nth_weekday(date.year(), 3, Weekday::Sun, 2);
+    let end = nth_weekday(date.year(), 11, Weekday::Sun, 1);
+    date >= start && date < end
+}
+
+//某年某月的第n个星期几
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u32) -> NaiveDate {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let offset = (7 + weekday.num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+    first + Duration::days((offset + (n - 1) * 7) as i64)
+}
+
+//6位代码按号段对应的交易所,网易的格式沪市前面加0,深市加1
+const PREFIX_SH: &[&str] = &["6", "5", "900", "11", "204"];
+const PREFIX_SZ: &[&str] = &["00", "30", "12", "13", "15", "16", "18", "200", "399"];
+//北交所和新三板,东方财富的secid市场编号是0
+const PREFIX_BJ: &[&str] = &["8", "43", "92"];
+
+//常用指数可以直接输入名称添加
+const INDEX_ALIASES: &[(&str, &str)] = &[
+    ("上证指数", "0000001"),
+    ("上证", "0000001"),
+    ("沪深300", "0000300"),
+    ("深证成指", "1399001"),
+    ("深成", "1399001"),
+    ("创业板指", "1399006"),
+    ("恒生指数", "hkHSI"),
+    ("恒生", "hkHSI"),
+    ("国企指数", "hkHSCEI"),
+    ("道指", "US_DJI"),
+    ("纳指", "US_IXIC"),
+    ("NASDAQ", "US_IXIC"),
+    ("标普500", "US_SPX"),
+    ("GC001", "0204001"),
+    ("R-001", "1131810"),
+];
+
+//美股指数和股票代码格式一样,只能列出来
+const US_INDEXES: &[&str] = &["US_DJI", "US_IXIC", "US_SPX"];
+
+//沪市000开头,深市399开头,港股代码不是数字的都是指数
+pub fn is_index(code: &str) -> bool {
+    if US_INDEXES.contains(&code) {
+        return true;
+    }
+    if code.len() == 7 && code.chars().all(|c| c.is_ascii_digit()) {
+        return code.starts_with("0000") || code.starts_with("1399");
+    }
+    code.to_lowercase().starts_with("hk") && code[2..].chars().any(|c| c.is_ascii_alphabetic())
+}
+
+//沪市204开头和深市1318开头的是国债逆回购,价格就是年化利率
+pub fn is_repo(code: &str) -> bool {
+    code.len() == 7 && (code.starts_with("0204") || code.starts_with("11318"))
+}
+
+//把用户输入的6位代码转成网易的格式,号段无法确定交易所的保持6位,刷新时沪深两边都查
+//指数名称换成对应的代码,其他格式(7位代码,hk,US_)原样返回
+pub fn normalize_code(input: &str) -> Result<String, String> {
+    let code = input.trim();
+    if let Some((_, index)) = INDEX_ALIASES.iter().find(|(name, _)| *name == code) {
+        return Ok(index.to_string());
+    }
+    //基金代码和股票的号段重叠,要加F前缀输入,比如F161725
+    if let Some(fund) = code.strip_prefix(['F', 'f']).filter(|fund| is_blind(fund)) {
+        return Ok(format!("FUND_{}", fund));
+    }
+    if let Some((symbol, ..)) = commodity(code) {
+        return Ok(format!("CMDTY_{}", symbol));
+    }
+    if is_forex_pair(code) {
+        return Ok(format!("FX_{}", code.to_uppercase()));
+    }
+    if is_crypto_pair(code) {
+        return Ok(format!("CRYPTO_{}", code.to_uppercase()));
+    }
+    if !is_blind(code) {
+        return Ok(code.to_string());
+    }
+    let matches = |prefixes: &[&str]| prefixes.iter().any(|p| code.starts_with(p));
+    if matches(PREFIX_SH) {
+        Ok(format!("0{}", code))
+    } else if matches(PREFIX_SZ) {
+        Ok(format!("1{}", code))
+    } else if matches(PREFIX_BJ) {
+        Ok(format!("BJ_{}", code))
+    } else {
+        Ok(code.to_string())
+    }
+}
+
+//解析"名称,代码"格式的别名文件,一行一个,#开头的是注释,第一行可以是表头
+//代码和手动输入时一样先转换格式,出错时返回行号
+pub fn parse_aliases(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut aliases = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = line
+            .split(',')
+            .map(|field| field.trim().trim_matches('"').trim())
+            .collect();
+        if i == 0 && matches!(fields[0].to_lowercase().as_str(), "name" | "名称") {
+            continue;
+        }
+        match fields.as_slice() {
+            [name, code] if !name.is_empty() && !code.is_empty() => {
+                let code = normalize_code(code).map_err(|err| format!("第{}行: {}", i + 1, err))?;
+                aliases.push((name.to_string(), code));
+            }
+            _ => return Err(format!("第{}行格式错误,应该是\"名称,代码\"", i + 1)),
+        }
+    }
+    Ok(aliases)
+}
+
+//计价币种结尾的字母代码当作币安的交易对,比如BTCUSDT、ETHBTC
+pub const CRYPTO_QUOTES: &[&str] = &["USDT", "USDC", "FDUSD", "BTC"];
+
+fn is_crypto_pair(code: &str) -> bool {
+    let upper = code.to_uppercase();
+    upper.chars().all(|c| c.is_ascii_alphanumeric())
+        && CRYPTO_QUOTES
+            .iter()
+            .any(|quote| upper.len() > quote.len() && upper.ends_with(quote))
+}
+
+//支持的商品:输入的品种,东方财富的secid,报价币种
+pub const COMMODITIES: &[(&str, &str, &str)] = &[
+    //上海黄金交易所
+    ("AU9999", "118.AU9999", "CNY"),
+    ("AG9999", "118.AG9999", "CNY"),
+    //伦敦现货
+    ("XAUUSD", "122.XAU", "USD"),
+    ("XAGUSD", "122.XAG", "USD"),
+    //COMEX黄金、白银和NYMEX原油的主力连续合约
+    ("GC", "101.GC00Y", "USD"),
+    ("SI", "101.SI00Y", "USD"),
+    ("CL", "102.CL00Y", "USD"),
+];
+
+//按品种查找,不区分大小写,CMDTY_前缀可有可无
+pub fn commodity(code: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    let upper = code.to_uppercase();
+    let symbol = upper.strip_prefix("CMDTY_").unwrap_or(&upper);
+    COMMODITIES
+        .iter()
+        .find(|(name, ..)| *name == symbol)
+        .copied()
+}
+
+//两个币种代码连在一起的当作外汇,比如USDCNY、EURUSD
+pub const CURRENCIES: &[&str] = &[
+    "USD", "CNY", "CNH", "EUR", "JPY", "GBP", "HKD", "AUD", "CAD", "CHF", "NZD", "SGD", "KRW",
+];
+
+fn is_forex_pair(code: &str) -> bool {
+    let upper = code.to_uppercase();
+    upper.len() == 6
+        && upper.is_char_boundary(3)
+        && CURRENCIES.contains(&&upper[..3])
+        && CURRENCIES.contains(&&upper[3..])
+}
+
+//没有交易所前缀的6位数字代码
+pub fn is_blind(code: &str) -> bool {
+    code.len() == 6 && code.chars().all(|c| c.is_ascii_digit())
+}
+
+//请求时使用的代码,无法确定交易所的代码两个市场都查
+pub fn query_codes(code: &str) -> Vec<String> {
+    if is_blind(code) {
+        vec![format!("0{}", code), format!("1{}", code)]
+    } else {
+        vec![code.to_string()]
+    }
+}
+
+//解析"09:25-15:05"格式的时间窗口,结束时间比开始早表示跨过午夜
+pub fn parse_window(window: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = window.split_once('-')?;
+    Some((
+        NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+        NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+    ))
+}
+
+pub fn in_window(time: NaiveTime, (start, end): (NaiveTime, NaiveTime)) -> bool {
+    if start <= end {
+        time >= start && time <= end
+    } else {
+        time >= start || time <= end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_sh() {
+        assert_eq!(normalize_code("600519").unwrap(), "0600519");
+        assert_eq!(normalize_code(" 510300 ").unwrap(), "0510300");
+        assert_eq!(normalize_code("900901").unwrap(), "0900901");
+        assert_eq!(normalize_code("上证指数").unwrap(), "0000001");
+    }
+
+    #[test]
+    fn normalize_sz() {
+        assert_eq!(normalize_code("000001").unwrap(), "1000001");
+        assert_eq!(normalize_code("300750").unwrap(), "1300750");
+        assert_eq!(normalize_code("159915").unwrap(), "1159915");
+        assert_eq!(normalize_code("399006").unwrap(), "1399006");
+        assert_eq!(normalize_code("创业板指").unwrap(), "1399006");
+    }
+
+    #[test]
+    fn normalize_hk_us() {
+        assert_eq!(normalize_code("hk00700").unwrap(), "hk00700");
+        assert_eq!(normalize_code("US_AAPL").unwrap(), "US_AAPL");
+        assert_eq!(normalize_code("0600519").unwrap(), "0600519");
+    }
+
+    #[test]
+    fn normalize_blind() {
+        //号段无法确定交易所的保持6位
+        assert_eq!(normalize_code("700001").unwrap(), "700001");
+        assert!(is_blind(&normalize_code("700001").unwrap()));
+    }
+
+    #[test]
+    fn normalize_prefixed() {
+        assert_eq!(normalize_code("F161725").unwrap(), "FUND_161725");
+        assert_eq!(normalize_code("usdcny").unwrap(), "FX_USDCNY");
+        assert_eq!(normalize_code("btcusdt").unwrap(), "CRYPTO_BTCUSDT");
+        assert_eq!(normalize_code("au9999").unwrap(), "CMDTY_AU9999");
+        assert_eq!(normalize_code("XAUUSD").unwrap(), "CMDTY_XAUUSD");
+        assert_eq!(normalize_code("CL").unwrap(), "CMDTY_CL");
+    }
+
+    #[test]
+    fn normalize_bj() {
+        assert_eq!(normalize_code("830799").unwrap(), "BJ_830799");
+        assert_eq!(normalize_code("430047").unwrap(), "BJ_430047");
+        assert_eq!(normalize_code("920002").unwrap(), "BJ_920002");
+        assert_eq!(normalize_code("BJ_830799").unwrap(), "BJ_830799");
+    }
+
+    #[test]
+    fn aliases_errors() {
+        let aliases = parse_aliases("名称,代码\n茅台,600519\n# 注释\n").unwrap();
+        assert_eq!(aliases, vec![("茅台".to_string(), "0600519".to_string())]);
+        assert_eq!(
+            parse_aliases("茅台").unwrap_err(),
+            "第1行格式错误,应该是\"名称,代码\""
+        );
+        assert_eq!(
+            parse_aliases("北交所,830799").unwrap(),
+            vec![("北交所".to_string(), "BJ_830799".to_string())]
+        );
+        assert!(parse_aliases("北交所,").unwrap_err().starts_with("第1行"));
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+//刷新后把行情和提醒发布到MQTT服务器,在.stocks.json的mqtt里配置
+//行情的主题是{prefix}/{code},带retain标志,新订阅的客户端马上能拿到最新行情
+//提醒的主题是{prefix}/{code}/alert
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub password: String,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_prefix() -> String {
+    String::from("stock")
+}
+
+fn default_client_id() -> String {
+    String::from("rust-stock")
+}
+
+//一条要发布的消息
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+impl MqttConfig {
+    pub fn quote(&self, code: &str, payload: String) -> Message {
+        Message {
+            topic: format!("{}/{}", self.prefix, code),
+            payload,
+            retain: true,
+        }
+    }
+
+    pub fn alert(&self, code: &str, payload: String) -> Message {
+        Message {
+            topic: format!("{}/{}/alert", self.prefix, code),
+            payload,
+            retain: false,
+        }
+    }
+
+    //每次刷新连一次服务器,发完就断开,单独开线程不阻塞刷新
+    pub fn publish(
+        &self,
+        messages: Vec<Message>,
+        error: &Arc<Mutex<String>>,
+        dirty: &Arc<Mutex<bool>>,
+    ) {
+        if messages.is_empty() {
+            return;
+        }
+        let config = self.clone();
+        let (error, dirty) = (error.clone(), dirty.clone());
+        thread::spawn(move || {
+            if let Err(err) = send(&config, &messages) {
+                tracing::error!("MQTT发布失败: {}", err);
+                *error.lock().unwrap() = format!("MQTT发布失败: {}", err);
+                *dirty.lock().unwrap() = true;
+            }
+        });
+    }
+}
+
+//只用到QoS 0的发布,协议很简单,没必要引入MQTT库
+fn send(config: &MqttConfig, messages: &[Message]) -> Result<(), Box<dyn Error>> {
+    let mut stream = TcpStream::connect((config.host.as_str(), config.port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    //CONNECT: 协议名MQTT,版本3.1.1,clean session,keep alive 60秒
+    let mut flags = 0x02;
+    let mut payload = encode_str(&config.client_id);
+    if !config.username.is_empty() {
+        flags |= 0x80;
+        payload.extend(encode_str(&config.username));
+    }
+    if !config.password.is_empty() {
+        flags |= 0x40;
+        payload.extend(encode_str(&config.password));
+    }
+    let mut body = encode_str("MQTT");
+    body.extend([0x04, flags, 0x00, 60]);
+    body.extend(payload);
+    stream.write_all(&packet(0x10, &body))?;
+
+    //CONNACK的第4个字节是返回码,0表示成功
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack)?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        return Err(format!("连接被拒绝,返回码{}", connack[3]).into());
+    }
+
+    for message in messages {
+        let mut body = encode_str(&message.topic);
+        body.extend(message.payload.as_bytes());
+        let header = if message.retain { 0x31 } else { 0x30 };
+        stream.write_all(&packet(header, &body))?;
+    }
+    stream.write_all(&[0xE0, 0x00])?;
+    Ok(())
+}
+
+//字符串前面是两个字节的长度
+fn encode_str(text: &str) -> Vec<u8> {
+    let mut out = (text.len() as u16).to_be_bytes().to_vec();
+    out.extend(text.as_bytes());
+    out
+}
+
+//固定头加上剩余长度,长度每个字节7位,最高位表示后面还有
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend(body);
+    out
+}
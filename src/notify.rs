@@ -0,0 +1,219 @@
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use http_req::request;
+use serde::{Deserialize, Serialize};
+
+//提醒的推送渠道,在.stocks.json的notify里配置
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NotifyConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp: Option<SmtpConfig>,
+    //Server酱的SendKey
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub serverchan: String,
+    //Bark的推送地址,带上key,比如https://api.day.app/xxxxxx
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub bark: String,
+}
+
+//465端口直接用SSL连接,587等其他端口先明文连接再用STARTTLS升级,qq/163等邮箱的password填授权码
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SmtpConfig {
+    pub server: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    pub to: String,
+}
+
+fn default_smtp_port() -> u16 {
+    465
+}
+
+impl NotifyConfig {
+    pub fn is_empty(&self) -> bool {
+        self.smtp.is_none() && self.serverchan.is_empty() && self.bark.is_empty()
+    }
+
+    //每个渠道单独开线程发送,不阻塞刷新,发送失败的错误写到error里,并通知界面重绘
+    pub fn send(
+        &self,
+        title: &str,
+        body: &str,
+        error: &Arc<Mutex<String>>,
+        dirty: &Arc<Mutex<bool>>,
+    ) {
+        if let Some(smtp) = self.smtp.clone() {
+            let (title, body) = (title.to_string(), body.to_string());
+            let (error, dirty) = (error.clone(), dirty.clone());
+            thread::spawn(move || {
+                if let Err(err) = send_mail(&smtp, &title, &body) {
+                    tracing::error!("邮件发送失败: {}", err);
+                    *error.lock().unwrap() = format!("邮件发送失败: {}", err);
+                    *dirty.lock().unwrap() = true;
+                }
+            });
+        }
+        if !self.serverchan.is_empty() {
+            let url = format!(
+                "https://sctapi.ftqq.com/{}.send?title={}&desp={}",
+                self.serverchan,
+                url_encode(title),
+                url_encode(body)
+            );
+            send_get(url, "Server酱", error, dirty);
+        }
+        if !self.bark.is_empty() {
+            let url = format!(
+                "{}/{}/{}",
+                self.bark.trim_end_matches('/'),
+                url_encode(title),
+                url_encode(body)
+            );
+            send_get(url, "Bark", error, dirty);
+        }
+    }
+}
+
+fn send_get(url: String, name: &'static str, error: &Arc<Mutex<String>>, dirty: &Arc<Mutex<bool>>) {
+    let (error, dirty) = (error.clone(), dirty.clone());
+    thread::spawn(move || {
+        let mut writer = Vec::new();
+        let message = match request::get(&url, &mut writer) {
+            Ok(res) if res.status_code().is_success() => return,
+            Ok(res) => format!("{}推送失败: {}", name, u16::from(res.status_code())),
+            Err(err) => format!("{}推送失败: {}", name, err),
+        };
+        tracing::error!("{}", message);
+        *error.lock().unwrap() = message;
+        *dirty.lock().unwrap() = true;
+    });
+}
+
+pub fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+//连接邮件服务器,服务器地址可能解析出多个IP,依次尝试,连不上时不会一直卡住
+fn connect(server: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last = io::Error::new(io::ErrorKind::NotFound, "地址解析不到IP");
+    for addr in (server, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, Duration::from_secs(10)) {
+            Ok(tcp) => {
+                tcp.set_read_timeout(Some(Duration::from_secs(10)))?;
+                tcp.set_write_timeout(Some(Duration::from_secs(10)))?;
+                return Ok(tcp);
+            }
+            Err(err) => last = err,
+        }
+    }
+    Err(last)
+}
+
+fn send_mail(config: &SmtpConfig, subject: &str, body: &str) -> Result<(), Box<dyn Error>> {
+    let mut tcp = connect(&config.server, config.port)?;
+    //http_req已经依赖了native-tls,这里直接复用,不再引入邮件库
+    let connector = native_tls::TlsConnector::new()?;
+    let mut stream = if config.port == 465 {
+        let mut stream = connector.connect(&config.server, tcp)?;
+        smtp_reply(&mut stream, "220")?;
+        stream
+    } else {
+        smtp_reply(&mut tcp, "220")?;
+        smtp_command(&mut tcp, "EHLO stock", "250")?;
+        smtp_command(&mut tcp, "STARTTLS", "220")?;
+        connector.connect(&config.server, tcp)?
+    };
+    smtp_command(&mut stream, "EHLO stock", "250")?;
+    smtp_command(&mut stream, "AUTH LOGIN", "334")?;
+    smtp_command(&mut stream, &base64(config.user.as_bytes()), "334")?;
+    smtp_command(&mut stream, &base64(config.password.as_bytes()), "235")?;
+    smtp_command(&mut stream, &format!("MAIL FROM:<{}>", config.user), "250")?;
+    smtp_command(&mut stream, &format!("RCPT TO:<{}>", config.to), "250")?;
+    smtp_command(&mut stream, "DATA", "354")?;
+    //标题和正文都有中文,统一用base64编码,正文每行不能超过76个字符
+    let message = format!(
+        "From: <{}>\r\nTo: <{}>\r\nSubject: =?UTF-8?B?{}?=\r\nContent-Type: text/plain; charset=UTF-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n.",
+        config.user,
+        config.to,
+        base64(subject.as_bytes()),
+        wrap(&base64(body.as_bytes()), 76)
+    );
+    smtp_command(&mut stream, &message, "250")?;
+    smtp_command(&mut stream, "QUIT", "221")?;
+    Ok(())
+}
+
+fn smtp_command<S: Read + Write>(
+    stream: &mut S,
+    command: &str,
+    expect: &str,
+) -> Result<(), Box<dyn Error>> {
+    stream.write_all(format!("{}\r\n", command).as_bytes())?;
+    smtp_reply(stream, expect)
+}
+
+//读取一个完整的应答,多行应答除最后一行外第4个字符都是'-'
+fn smtp_reply<S: Read>(stream: &mut S, expect: &str) -> Result<(), Box<dyn Error>> {
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+        if line.ends_with(b"\r\n") {
+            let text = String::from_utf8_lossy(&line).to_string();
+            if !text.starts_with(expect) {
+                return Err(text.trim().into());
+            }
+            if text.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+            line.clear();
+        }
+    }
+}
+
+//base64的结果只有ASCII字符,按字节切就行
+fn wrap(text: &str, width: usize) -> String {
+    text.as_bytes()
+        .chunks(width)
+        .map(|line| String::from_utf8_lossy(line).to_string())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+pub fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - i * 6) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
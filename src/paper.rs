@@ -0,0 +1,527 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::{market::Market, Stock};
+
+//模拟交易账户,按最新行情成交,和真实持仓无关,保存在.stocks.json的paper里
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PaperAccount {
+    #[serde(default = "default_cash")]
+    pub initial: f64,
+    #[serde(default = "default_cash")]
+    pub cash: f64,
+    //佣金费率和最低佣金
+    #[serde(default = "default_fee_rate")]
+    pub fee_rate: f64,
+    #[serde(default = "default_min_fee")]
+    pub min_fee: f64,
+    //滑点,买入按最新价上浮,卖出下浮
+    #[serde(default = "default_slippage")]
+    pub slippage: f64,
+    #[serde(default)]
+    pub positions: Vec<Position>,
+    #[serde(default)]
+    pub trades: Vec<Trade>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Position {
+    pub code: String,
+    pub title: String,
+    pub shares: u64,
+    //持仓成本,包含手续费
+    pub cost: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Side::Buy => "买入",
+            Side::Sell => "卖出",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Trade {
+    pub time: String,
+    pub code: String,
+    pub side: Side,
+    pub price: f64,
+    pub shares: u64,
+    pub fee: f64,
+}
+
+fn default_cash() -> f64 {
+    1_000_000.0
+}
+
+fn default_fee_rate() -> f64 {
+    0.0003
+}
+
+fn default_min_fee() -> f64 {
+    5.0
+}
+
+fn default_slippage() -> f64 {
+    0.001
+}
+
+//只保留最近的成交记录
+const MAX_TRADES: usize = 100;
+
+impl Default for PaperAccount {
+    fn default() -> Self {
+        Self {
+            initial: default_cash(),
+            cash: default_cash(),
+            fee_rate: default_fee_rate(),
+            min_fee: default_min_fee(),
+            slippage: default_slippage(),
+            positions: vec![],
+            trades: vec![],
+        }
+    }
+}
+
+impl PaperAccount {
+    //按最新价加上滑点成交,沪深股票买入必须是100股的整数倍
+    pub fn order(&mut self, stock: &Stock, side: Side, shares: u64) -> Result<Trade, String> {
+        if stock.is_index() {
+            return Err(String::from("指数不能交易"));
+        }
+        if stock.price <= 0.0 || stock.stale {
+            return Err(String::from("没有最新行情,不能成交"));
+        }
+        if shares == 0 {
+            return Err(String::from("数量必须大于0"));
+        }
+        if side == Side::Buy && stock.is_cn() && shares % 100 != 0 {
+            return Err(String::from("沪深股票买入数量必须是100的整数倍"));
+        }
+        let price = match side {
+            Side::Buy => stock.price * (1.0 + self.slippage),
+            Side::Sell => stock.price * (1.0 - self.slippage),
+        };
+        let amount = price * shares as f64;
+        let fee = (amount * self.fee_rate).max(self.min_fee);
+        let index = self.positions.iter().position(|p| p.code == stock.code);
+        match side {
+            Side::Buy => {
+                if amount + fee > self.cash {
+                    return Err(format!("资金不足,可用{:.2}", self.cash));
+                }
+                self.cash -= amount + fee;
+                match index {
+                    Some(i) => {
+                        self.positions[i].shares += shares;
+                        self.positions[i].cost += amount + fee;
+                    }
+                    None => self.positions.push(Position {
+                        code: stock.code.clone(),
+                        title: stock.title.clone(),
+                        shares,
+                        cost: amount + fee,
+                    }),
+                }
+            }
+            Side::Sell => {
+                let i = index.ok_or("没有持仓")?;
+                let position = &mut self.positions[i];
+                if shares > position.shares {
+                    return Err(format!("持仓不足,可卖{}", position.shares));
+                }
+                //按比例扣掉成本,剩下的成本就是剩余持仓的成本
+                position.cost -= position.cost * shares as f64 / position.shares as f64;
+                position.shares -= shares;
+                if position.shares == 0 {
+                    self.positions.remove(i);
+                }
+                self.cash += amount - fee;
+            }
+        }
+        let trade = Trade {
+            time: Local::now().format("%m-%d %H:%M:%S").to_string(),
+            code: stock.code.clone(),
+            side,
+            price,
+            shares,
+            fee,
+        };
+        self.trades.push(trade.clone());
+        if self.trades.len() > MAX_TRADES {
+            self.trades.remove(0);
+        }
+        Ok(trade)
+    }
+
+    //持仓按最新价计算的市值,找不到行情时按成本算
+    pub fn market_value(&self, position: &Position, stocks: &[Stock]) -> f64 {
+        stocks
+            .iter()
+            .find(|stock| stock.code == position.code && stock.price > 0.0)
+            .map_or(position.cost, |stock| stock.price * position.shares as f64)
+    }
+
+    //按市场分的持仓占总资产的比例,最大的排前面
+    pub fn exposure(&self, stocks: &[Stock]) -> Vec<(Market, f64)> {
+        self.group_by(stocks, |position| {
+            Stock::new(&position.code).market().section()
+        })
+    }
+
+    //按行业分的持仓占总资产的比例,行业来自基本面,还没加载或没有行业的算作其他
+    pub fn sector_exposure(&self, stocks: &[Stock]) -> Vec<(String, f64)> {
+        self.group_by(stocks, |position| {
+            stocks
+                .iter()
+                .find(|stock| stock.code == position.code)
+                .and_then(|stock| stock.fundamentals.as_ref())
+                .map(|f| f.industry.clone())
+                .filter(|industry| !industry.is_empty())
+                .unwrap_or_else(|| String::from("其他"))
+        })
+    }
+
+    //按key分组汇总市值占总资产的比例,总资产不是正数时没有意义,返回空
+    fn group_by<K: PartialEq>(
+        &self,
+        stocks: &[Stock],
+        key: impl Fn(&Position) -> K,
+    ) -> Vec<(K, f64)> {
+        let equity = self.equity(stocks);
+        if equity <= 0.0 {
+            return vec![];
+        }
+        let mut groups: Vec<(K, f64)> = vec![];
+        for position in self.positions.iter() {
+            let group = key(position);
+            let value = self.market_value(position, stocks);
+            match groups.iter_mut().find(|(k, _)| *k == group) {
+                Some((_, sum)) => *sum += value,
+                None => groups.push((group, value)),
+            }
+        }
+        for (_, value) in groups.iter_mut() {
+            *value /= equity;
+        }
+        groups.sort_by(|a, b| b.1.total_cmp(&a.1));
+        groups
+    }
+
+    //最大的一个持仓和它占总资产的比例
+    pub fn concentration(&self, stocks: &[Stock]) -> Option<(&Position, f64)> {
+        let equity = self.equity(stocks);
+        if equity <= 0.0 {
+            return None;
+        }
+        self.positions
+            .iter()
+            .map(|position| (position, self.market_value(position, stocks) / equity))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    //用日K线的收盘价算持仓组合每天的收益,取5%分位数作为95%置信度的1日VaR
+    //有持仓的K线还没加载或者数据太少时没有意义,返回原因
+    pub fn value_at_risk(&self, stocks: &[Stock]) -> Result<f64, String> {
+        let candles: Vec<_> = self
+            .positions
+            .iter()
+            .map(
+                |position| match stocks.iter().find(|stock| stock.code == position.code) {
+                    Some(stock) if !stock.candles.is_empty() => {
+                        Ok((position.shares as f64, &stock.candles))
+                    }
+                    _ => Err(format!("{}的K线不足", position.title)),
+                },
+            )
+            .collect::<Result<_, _>>()?;
+        //各个市场的交易日不同,只取所有持仓都有K线的日期
+        let (_, first) = candles.first().ok_or("空仓")?;
+        let dates: Vec<&String> = first
+            .iter()
+            .map(|candle| &candle.date)
+            .filter(|date| {
+                candles
+                    .iter()
+                    .all(|(_, list)| list.iter().any(|candle| candle.date == **date))
+            })
+            .collect();
+        if dates.len() < 21 {
+            return Err(String::from("K线不足20天"));
+        }
+        let values: Vec<f64> = dates
+            .iter()
+            .map(|date| {
+                candles
+                    .iter()
+                    .filter_map(|(shares, list)| {
+                        let candle = list.iter().find(|candle| candle.date == **date)?;
+                        Some(shares * candle.close)
+                    })
+                    .sum()
+            })
+            .collect();
+        let len = values.len();
+        let mut returns: Vec<f64> = values.windows(2).map(|v| v[1] / v[0] - 1.0).collect();
+        returns.sort_by(|a, b| a.total_cmp(b));
+        let quantile = returns[returns.len() * 5 / 100];
+        Ok((-quantile).max(0.0) * values[len - 1])
+    }
+
+    pub fn equity(&self, stocks: &[Stock]) -> f64 {
+        self.cash
+            + self
+                .positions
+                .iter()
+                .map(|position| self.market_value(position, stocks))
+                .sum::<f64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Candle;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    fn stock(code: &str, price: f64) -> Stock {
+        let mut stock = Stock::new(&code.to_string());
+        stock.price = price;
+        stock
+    }
+
+    fn account() -> PaperAccount {
+        PaperAccount {
+            slippage: 0.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn buy() {
+        let mut paper = PaperAccount::default();
+        let trade = paper
+            .order(&stock("0600000", 10.0), Side::Buy, 1000)
+            .unwrap();
+        //买入按最新价上浮滑点
+        assert!(close(trade.price, 10.01));
+        assert_eq!(trade.fee, 5.0);
+        assert!(close(paper.cash, 1_000_000.0 - 10010.0 - 5.0));
+        assert_eq!(paper.positions.len(), 1);
+        assert_eq!(paper.positions[0].shares, 1000);
+        assert!(close(paper.positions[0].cost, 10015.0));
+        //加仓合并到同一个持仓
+        paper
+            .order(&stock("0600000", 10.0), Side::Buy, 1000)
+            .unwrap();
+        assert_eq!(paper.positions.len(), 1);
+        assert_eq!(paper.positions[0].shares, 2000);
+        assert!(close(paper.positions[0].cost, 20030.0));
+        assert_eq!(paper.trades.len(), 2);
+    }
+
+    #[test]
+    fn partial_sell() {
+        let mut paper = account();
+        paper
+            .order(&stock("0600000", 10.0), Side::Buy, 1000)
+            .unwrap();
+        let cash = paper.cash;
+        let trade = paper
+            .order(&stock("0600000", 12.0), Side::Sell, 400)
+            .unwrap();
+        assert_eq!(trade.fee, 5.0);
+        assert!(close(paper.cash, cash + 4800.0 - 5.0));
+        //按比例扣掉成本
+        assert_eq!(paper.positions[0].shares, 600);
+        assert!(close(paper.positions[0].cost, 10005.0 * 0.6));
+        //卖出的数量不用是100的整数倍,全部卖完后删掉持仓
+        paper
+            .order(&stock("0600000", 12.0), Side::Sell, 50)
+            .unwrap();
+        paper
+            .order(&stock("0600000", 12.0), Side::Sell, 550)
+            .unwrap();
+        assert!(paper.positions.is_empty());
+    }
+
+    #[test]
+    fn oversell() {
+        let mut paper = account();
+        assert_eq!(
+            paper
+                .order(&stock("0600000", 10.0), Side::Sell, 100)
+                .unwrap_err(),
+            String::from("没有持仓")
+        );
+        paper
+            .order(&stock("0600000", 10.0), Side::Buy, 100)
+            .unwrap();
+        let cash = paper.cash;
+        assert_eq!(
+            paper
+                .order(&stock("0600000", 10.0), Side::Sell, 200)
+                .unwrap_err(),
+            String::from("持仓不足,可卖100")
+        );
+        assert_eq!(paper.cash, cash);
+        assert_eq!(paper.positions[0].shares, 100);
+    }
+
+    #[test]
+    fn insufficient_cash() {
+        let mut paper = PaperAccount {
+            cash: 1000.0,
+            ..account()
+        };
+        //加上手续费超过可用资金
+        assert!(paper
+            .order(&stock("0600000", 10.0), Side::Buy, 100)
+            .unwrap_err()
+            .starts_with("资金不足"));
+        assert_eq!(paper.cash, 1000.0);
+        assert!(paper.positions.is_empty());
+        assert!(paper.trades.is_empty());
+    }
+
+    #[test]
+    fn min_fee() {
+        let mut paper = account();
+        let small = paper
+            .order(&stock("0600000", 10.0), Side::Buy, 100)
+            .unwrap();
+        assert_eq!(small.fee, 5.0);
+        let large = paper
+            .order(&stock("0600000", 10.0), Side::Buy, 10000)
+            .unwrap();
+        assert!(close(large.fee, 30.0));
+    }
+
+    #[test]
+    fn lot_size() {
+        let mut paper = account();
+        assert_eq!(
+            paper
+                .order(&stock("0600000", 10.0), Side::Buy, 150)
+                .unwrap_err(),
+            String::from("沪深股票买入数量必须是100的整数倍")
+        );
+        assert_eq!(
+            paper
+                .order(&stock("0600000", 10.0), Side::Buy, 0)
+                .unwrap_err(),
+            String::from("数量必须大于0")
+        );
+        //其他市场没有整手的限制
+        assert!(paper.order(&stock("US_AAPL", 180.0), Side::Buy, 3).is_ok());
+        assert!(paper
+            .order(&stock("0000001", 3000.0), Side::Buy, 100)
+            .is_err());
+        assert!(paper.order(&stock("0600000", 0.0), Side::Buy, 100).is_err());
+    }
+
+    fn candles(closes: &[(u32, f64)]) -> Vec<Candle> {
+        closes
+            .iter()
+            .map(|&(day, close)| Candle {
+                date: format!("2024-03-{:02}", day),
+                open: close,
+                close,
+                high: close,
+                low: close,
+                volume: 0.0,
+            })
+            .collect()
+    }
+
+    fn holding(paper: &mut PaperAccount, stock: &Stock, shares: u64) {
+        paper.positions.push(Position {
+            code: stock.code.clone(),
+            title: stock.title.clone(),
+            shares,
+            cost: 0.0,
+        });
+    }
+
+    #[test]
+    fn var_quantile() {
+        //25个收益里第二差的是5%分位数
+        let mut closes = vec![(1, 100.0)];
+        for day in 2..=26 {
+            let change = match day {
+                5 => -0.10,
+                12 => -0.05,
+                _ => 0.01,
+            };
+            let last = closes.last().unwrap().1;
+            closes.push((day, last * (1.0 + change)));
+        }
+        let mut spdb = stock("0600000", 10.0);
+        spdb.candles = candles(&closes);
+        let mut paper = account();
+        holding(&mut paper, &spdb, 100);
+        let value = closes.last().unwrap().1 * 100.0;
+        let var = paper.value_at_risk(&[spdb]).unwrap();
+        assert!(close(var, 0.05 * value));
+    }
+
+    #[test]
+    fn var_common_dates() {
+        //a在第5天暴跌,b没有第5天,b多出来的第30天a没有,都不算
+        let a_closes: Vec<_> = (1..=22)
+            .map(|day| (day, if day == 5 { 1.0 } else { 10.0 }))
+            .collect();
+        let b_closes: Vec<_> = (1..=22)
+            .filter(|&day| day != 5)
+            .map(|day| (day, 20.0))
+            .chain([(30, 1.0)])
+            .collect();
+        let mut a = stock("0600000", 10.0);
+        a.candles = candles(&a_closes);
+        let mut b = stock("1000001", 20.0);
+        b.candles = candles(&b_closes);
+        let mut paper = account();
+        holding(&mut paper, &a, 100);
+        holding(&mut paper, &b, 100);
+        assert_eq!(paper.value_at_risk(&[a.clone(), b.clone()]), Ok(0.0));
+        //共同的日期不够21天
+        b.candles.remove(0);
+        assert_eq!(
+            paper.value_at_risk(&[a, b]),
+            Err(String::from("K线不足20天"))
+        );
+    }
+
+    #[test]
+    fn var_missing_candles() {
+        let mut a = stock("0600000", 10.0);
+        a.candles = candles(&(1..=30).map(|day| (day, 10.0)).collect::<Vec<_>>());
+        let mut b = stock("1000001", 20.0);
+        b.title = String::from("平安银行");
+        let mut paper = account();
+        assert_eq!(paper.value_at_risk(&[a.clone()]), Err(String::from("空仓")));
+        holding(&mut paper, &a, 100);
+        holding(&mut paper, &b, 100);
+        assert_eq!(
+            paper.value_at_risk(&[a.clone(), b]),
+            Err(String::from("平安银行的K线不足"))
+        );
+        //持仓已经不在列表里
+        assert_eq!(
+            paper.value_at_risk(&[a]),
+            Err(String::from("平安银行的K线不足"))
+        );
+    }
+}
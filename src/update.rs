@@ -0,0 +1,53 @@
+use std::convert::TryFrom;
+
+use http_req::{request::Request, uri::Uri};
+use serde_json::Value;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+const RELEASES_API: &str = "https://api.github.com/repos/fjqz177/rust-stock/releases/latest";
+
+//GitHub上最新发布的版本
+pub struct Release {
+    pub version: String,
+    pub notes: String,
+    pub url: String,
+}
+
+impl Release {
+    pub fn is_newer(&self) -> bool {
+        parse_version(&self.version) > parse_version(VERSION)
+    }
+}
+
+//"v1.4.0"转成(1,4,0)比较,不认识的部分按0处理
+fn parse_version(version: &str) -> Vec<u32> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+//GitHub的接口必须带User-Agent,不能直接用request::get
+pub fn latest_release() -> Result<Release, String> {
+    let uri = Uri::try_from(RELEASES_API).map_err(|err| err.to_string())?;
+    let mut writer = Vec::new();
+    let res = Request::new(&uri)
+        .header("User-Agent", "rust-stock")
+        .header("Accept", "application/vnd.github+json")
+        .send(&mut writer)
+        .map_err(|err| err.to_string())?;
+    if !res.status_code().is_success() {
+        return Err(format!("检查更新失败: {}", u16::from(res.status_code())));
+    }
+    let json: Value = serde_json::from_slice(&writer).map_err(|err| err.to_string())?;
+    let field = |key: &str| json.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    if field("tag_name").is_empty() {
+        return Err(String::from("检查更新失败: 返回数据格式错误"));
+    }
+    Ok(Release {
+        version: field("tag_name").trim_start_matches('v').to_string(),
+        notes: field("body").to_string(),
+        url: field("html_url").to_string(),
+    })
+}
@@ -1,22 +1,36 @@
 use tui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols::Marker,
     text::{Span, Spans},
-    widgets::{Block, BorderType, Borders, List, ListItem, Paragraph},
+    widgets::{
+        canvas::{Canvas, Context, Line},
+        Block, BorderType, Borders, List, ListItem, Paragraph, Wrap,
+    },
 };
 
-use crate::{App, AppState, Stock};
-use unicode_width::UnicodeWidthStr;
+use crate::{
+    logger::{self, Level},
+    market::{self, Market},
+    update::VERSION,
+    App, AppState, Breadth, Conversion, Fundamentals, ListRow, Margin, News, Profile, Stock, Tab,
+};
+use chrono::{Local, NaiveDate, Offset};
+use serde::{Deserialize, Serialize};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+//正常显示需要的最小终端尺寸
+pub const MIN_WIDTH: u16 = 40;
+pub const MIN_HEIGHT: u16 = 8;
 
 //计算所有的屏幕窗口区域,供后续render使用
-pub fn main_chunks(area: Rect) -> Vec<Rect> {
+pub fn main_chunks(area: Rect, list_percent: u16, ticker: bool) -> Vec<Rect> {
     let parent = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [
                 Constraint::Length(1),
+                Constraint::Length(if ticker { 1 } else { 0 }),
                 Constraint::Min(1),
                 Constraint::Length(1),
             ]
@@ -27,8 +41,14 @@ pub fn main_chunks(area: Rect) -> Vec<Rect> {
     let center = Layout::default()
         .direction(Direction::Horizontal)
         .margin(0)
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
-        .split(parent[1]);
+        .constraints(
+            [
+                Constraint::Percentage(list_percent),
+                Constraint::Percentage(100 - list_percent),
+            ]
+            .as_ref(),
+        )
+        .split(parent[2]);
 
     //计算新建stock时的弹框位置
     let popup = Layout::default()
@@ -56,35 +76,645 @@ pub fn main_chunks(area: Rect) -> Vec<Rect> {
         )
         .split(popup[1]);
 
-    vec![parent[0], center[0], center[1], parent[2], popline[1]]
+    //标题栏,列表,详情,状态栏,弹框,标签页的主区域,指数条
+    vec![
+        parent[0], center[0], center[1], parent[3], popline[1], parent[2], parent[1],
+    ]
+}
+
+//涨跌的配色,默认红涨绿跌,色盲配色用Okabe-Ito色板里红绿色盲也能区分的颜色
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    #[default]
+    Default,
+    //绿色弱,涨用橙色,跌用蓝色
+    Deuteranopia,
+    //红色弱,涨用黄色,跌用天蓝色
+    Protanopia,
+}
+
+impl Palette {
+    pub fn up(&self) -> Color {
+        match self {
+            Palette::Default => Color::Red,
+            Palette::Deuteranopia => Color::Rgb(230, 159, 0),
+            Palette::Protanopia => Color::Rgb(240, 228, 66),
+        }
+    }
+
+    pub fn down(&self) -> Color {
+        match self {
+            Palette::Default => Color::Green,
+            Palette::Deuteranopia => Color::Rgb(0, 114, 178),
+            Palette::Protanopia => Color::Rgb(86, 180, 233),
+        }
+    }
+
+    //热力图里涨跌幅较小的用浅一点的颜色
+    fn up_light(&self) -> Color {
+        match self {
+            Palette::Default => Color::LightRed,
+            Palette::Deuteranopia => Color::Rgb(240, 200, 120),
+            Palette::Protanopia => Color::Rgb(245, 238, 150),
+        }
+    }
+
+    fn down_light(&self) -> Color {
+        match self {
+            Palette::Default => Color::LightGreen,
+            Palette::Deuteranopia => Color::Rgb(110, 170, 215),
+            Palette::Protanopia => Color::Rgb(160, 210, 240),
+        }
+    }
+
+    pub fn trend(&self, percent: f64) -> Color {
+        if percent < 0.0 {
+            self.down()
+        } else {
+            self.up()
+        }
+    }
+}
+
+//标题栏下面的指数条,每个指数显示名称、点位和涨跌幅
+pub fn ticker_bar(
+    ticker: &[Stock],
+    breadth: Option<Breadth>,
+    palette: Palette,
+) -> Paragraph<'static> {
+    let mut spans = vec![];
+    for stock in ticker {
+        if stock.price <= 0.0 {
+            continue;
+        }
+        spans.push(Span::raw(format!(" {} {:.2} ", stock.title, stock.price)));
+        spans.push(Span::styled(
+            format!(
+                "{}{:.2}% ",
+                arrow(stock.percent),
+                stock.percent.abs() * 100.0
+            ),
+            Style::default().fg(palette.trend(stock.percent)),
+        ));
+        //成交额看得出市场活跃程度,有的指数接口不返回
+        if stock.turnover > 0.0 {
+            spans.push(Span::styled(
+                format!("{} ", format_amount(stock.turnover)),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        spans.push(Span::raw("│"));
+    }
+    if let Some(breadth) = breadth {
+        spans.extend(breadth_bar(breadth, palette));
+    } else {
+        spans.pop();
+    }
+    Paragraph::new(Spans::from(spans))
+}
+
+//涨跌家数条的格子数
+const BREADTH_WIDTH: usize = 10;
+
+//上涨、平盘、下跌家数按比例画成一条,后面是涨停数
+fn breadth_bar(breadth: Breadth, palette: Palette) -> Vec<Span<'static>> {
+    let total = (breadth.up + breadth.down + breadth.flat).max(1) as f64;
+    let up = (breadth.up as f64 / total * BREADTH_WIDTH as f64).round() as usize;
+    let down = ((breadth.down as f64 / total * BREADTH_WIDTH as f64).round() as usize)
+        .min(BREADTH_WIDTH - up);
+    let flat = BREADTH_WIDTH - up - down;
+    vec![
+        Span::styled(
+            format!(" 涨{} ", breadth.up),
+            Style::default().fg(palette.up()),
+        ),
+        Span::styled("█".repeat(up), Style::default().fg(palette.up())),
+        Span::styled("█".repeat(flat), Style::default().fg(Color::DarkGray)),
+        Span::styled("█".repeat(down), Style::default().fg(palette.down())),
+        Span::styled(
+            format!(" 跌{}", breadth.down),
+            Style::default().fg(palette.down()),
+        ),
+        Span::raw(format!(" 涨停{} ", breadth.limit_up)),
+    ]
+}
+
+//除了颜色再用箭头表示涨跌,分不清颜色时也能看出方向
+fn arrow(percent: f64) -> &'static str {
+    if percent > 0.0 {
+        "▲"
+    } else if percent < 0.0 {
+        "▼"
+    } else {
+        " "
+    }
 }
 
-pub fn stock_list(stocks: &Vec<Stock>) -> List {
-    let items: Vec<_> = stocks
+//热力图每个方块的大小
+const TILE_WIDTH: u16 = 18;
+const TILE_HEIGHT: u16 = 3;
+//大字模式每个方块的大小,边框加上两行大字和中间的空行
+const BIG_TILE_WIDTH: u16 = 34;
+const BIG_TILE_HEIGHT: u16 = BIG_FONT_HEIGHT * 2 + 3;
+
+//把区域按方块大小排成网格,返回每个方块对应的stock下标和位置,超出区域的不显示,滚动到选中的那一行
+fn tile_grid(
+    count: usize,
+    selected: Option<usize>,
+    area: Rect,
+    tile_width: u16,
+    tile_height: u16,
+) -> Vec<(usize, Rect)> {
+    let cols = (area.width / tile_width).max(1) as usize;
+    let rows = (area.height / tile_height) as usize;
+    let width = area.width / cols as u16;
+    let first_row = selected.map_or(0, |sel| (sel / cols + 1).saturating_sub(rows));
+    (first_row * cols..count.min((first_row + rows) * cols))
+        .map(|i| {
+            let row = (i / cols - first_row) as u16;
+            let col = (i % cols) as u16;
+            //方块之间留一列空白
+            let rect = Rect::new(
+                area.x + col * width,
+                area.y + row * tile_height,
+                width.saturating_sub(1),
+                tile_height,
+            );
+            (i, rect)
+        })
+        .collect()
+}
+
+//按涨跌幅着色的方块
+pub fn heatmap(
+    stocks: &[Stock],
+    selected: Option<usize>,
+    area: Rect,
+    palette: Palette,
+) -> Vec<(Rect, Paragraph)> {
+    tile_grid(stocks.len(), selected, area, TILE_WIDTH, TILE_HEIGHT)
+        .into_iter()
+        .map(|(i, rect)| {
+            let stock = &stocks[i];
+            let percent = stock.percent * 100.0;
+            let bg = if percent >= 3.0 {
+                palette.up()
+            } else if percent > 0.0 {
+                palette.up_light()
+            } else if percent <= -3.0 {
+                palette.down()
+            } else if percent < 0.0 {
+                palette.down_light()
+            } else {
+                Color::DarkGray
+            };
+            let mut style = Style::default().bg(bg).fg(Color::Black);
+            if selected == Some(i) {
+                style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+            }
+            let tile = Paragraph::new(vec![
+                Spans::from(fit_width(&stock.title, rect.width as usize)),
+                Spans::from(format!("{}{:+.2}%", arrow(percent), percent)),
+                Spans::from(format!("{}", stock.price)),
+            ])
+            .alignment(Alignment::Center)
+            .style(style);
+            (rect, tile)
+        })
+        .collect()
+}
+
+//大字的高度,每个字符3列宽,字符之间空一列
+const BIG_FONT_HEIGHT: u16 = 5;
+
+fn big_glyph(c: char) -> [&'static str; 5] {
+    match c {
+        '0' => ["███", "█ █", "█ █", "█ █", "███"],
+        '1' => [" █ ", "██ ", " █ ", " █ ", "███"],
+        '2' => ["███", "  █", "███", "█  ", "███"],
+        '3' => ["███", "  █", "███", "  █", "███"],
+        '4' => ["█ █", "█ █", "███", "  █", "  █"],
+        '5' => ["███", "█  ", "███", "  █", "███"],
+        '6' => ["███", "█  ", "███", "█ █", "███"],
+        '7' => ["███", "  █", "  █", "  █", "  █"],
+        '8' => ["███", "█ █", "███", "█ █", "███"],
+        '9' => ["███", "█ █", "███", "  █", "███"],
+        '.' => [" ", " ", " ", " ", "█"],
+        '-' => ["   ", "   ", "███", "   ", "   "],
+        '+' => ["   ", " █ ", "███", " █ ", "   "],
+        '%' => ["█ █", "  █", " █ ", "█  ", "█ █"],
+        _ => ["   ", "   ", "   ", "   ", "   "],
+    }
+}
+
+//把数字转成多行的大字
+pub fn big_text(text: &str) -> Vec<String> {
+    (0..BIG_FONT_HEIGHT as usize)
+        .map(|row| {
+            text.chars()
+                .map(|c| big_glyph(c)[row])
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+//大字模式,每个stock一个带边框的方块,标题是名称,里面是大字的价格和涨跌幅
+pub fn big_tiles(
+    stocks: &[Stock],
+    selected: Option<usize>,
+    area: Rect,
+    palette: Palette,
+) -> Vec<(Rect, Paragraph)> {
+    tile_grid(
+        stocks.len(),
+        selected,
+        area,
+        BIG_TILE_WIDTH,
+        BIG_TILE_HEIGHT,
+    )
+    .into_iter()
+    .map(|(i, rect)| {
+        let stock = &stocks[i];
+        let percent = stock.percent * 100.0;
+        let color = if stock.stale {
+            Color::DarkGray
+        } else {
+            palette.trend(percent)
+        };
+        let mut lines: Vec<_> = big_text(&format!("{}", stock.price))
+            .into_iter()
+            .map(Spans::from)
+            .collect();
+        lines.push(Spans::default());
+        lines.extend(
+            big_text(&format!("{:+.2}%", percent))
+                .into_iter()
+                .map(|line| Spans::from(Span::styled(line, Style::default().fg(color)))),
+        );
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} {}", arrow(percent), stock.title))
+            .border_type(BorderType::Plain);
+        if selected == Some(i) {
+            block = block.border_style(Style::default().fg(Color::Yellow));
+        }
+        let tile = Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .block(block);
+        (rect, tile)
+    })
+    .collect()
+}
+
+//数据的时间按分钟、小时、天显示
+fn age_text(age: chrono::Duration) -> String {
+    if age.num_minutes() < 1 {
+        String::from("刚刚")
+    } else if age.num_hours() < 1 {
+        format!("{}分钟前", age.num_minutes())
+    } else if age.num_days() < 1 {
+        format!("{}小时前", age.num_hours())
+    } else {
+        format!("{}天前", age.num_days())
+    }
+}
+
+//净流入这种有正负的金额
+fn signed_amount(amount: f64) -> String {
+    format!(
+        "{}{}",
+        if amount < 0.0 { "-" } else { "+" },
+        format_amount(amount.abs())
+    )
+}
+
+//金额按万、亿显示
+pub fn format_amount(amount: f64) -> String {
+    if amount >= 1e8 {
+        format!("{:.2}亿", amount / 1e8)
+    } else if amount >= 1e4 {
+        format!("{:.2}万", amount / 1e4)
+    } else {
+        format!("{:.0}", amount)
+    }
+}
+
+//列表下面的统计行:涨跌家数、平均涨跌幅、成交额,成交额按币种分开加,指数的成交额是整个市场的不算在内
+//打开换算时能换算的成交额都加到统一的币种里
+pub fn watchlist_stats(
+    stocks: &[Stock],
+    palette: Palette,
+    conversion: Option<Conversion>,
+) -> Paragraph<'static> {
+    let quoted: Vec<_> = stocks.iter().filter(|stock| stock.price > 0.0).collect();
+    let up = quoted.iter().filter(|stock| stock.percent > 0.0).count();
+    let down = quoted.iter().filter(|stock| stock.percent < 0.0).count();
+    let average = if quoted.is_empty() {
+        0.0
+    } else {
+        quoted.iter().map(|stock| stock.percent).sum::<f64>() / quoted.len() as f64 * 100.0
+    };
+    let mut turnover: Vec<(String, f64)> = vec![];
+    for stock in quoted.iter().filter(|stock| !stock.is_index()) {
+        let (currency, amount) = match conversion.as_ref().and_then(|c| Some((c, c.rate(stock)?))) {
+            Some((conversion, rate)) => (conversion.currency.clone(), stock.turnover * rate),
+            None => (stock.currency().to_string(), stock.turnover),
+        };
+        match turnover.iter_mut().find(|(c, _)| *c == currency) {
+            Some((_, sum)) => *sum += amount,
+            None => turnover.push((currency, amount)),
+        }
+    }
+    let mut spans = vec![
+        Span::styled(format!("▲涨{} ", up), Style::default().fg(palette.up())),
+        Span::styled(format!("▼跌{} ", down), Style::default().fg(palette.down())),
+        Span::raw(format!(
+            "平{} 平均{:+.2}%",
+            quoted.len() - up - down,
+            average
+        )),
+    ];
+    for (currency, sum) in turnover.into_iter().filter(|(_, sum)| *sum > 0.0) {
+        spans.push(Span::raw(format!(
+            " 成交{}{}",
+            format_amount(sum),
+            currency
+        )));
+    }
+    Paragraph::new(Spans::from(spans))
+}
+
+pub fn too_small(area: Rect) -> Paragraph<'static> {
+    Paragraph::new(format!(
+        "终端窗口太小\n当前{}x{},至少需要{}x{}",
+        area.width, area.height, MIN_WIDTH, MIN_HEIGHT
+    ))
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true })
+}
+
+//按显示宽度截断或补齐,中文字符占两列,不能直接用len或chars().count()
+pub fn fit_width(text: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = UnicodeWidthChar::width(c).unwrap_or(0);
+        if used + w > width {
+            //截断时最后留一列给省略号,宽度为0时什么也不显示
+            if width == 0 {
+                return String::new();
+            }
+            while used + 1 > width {
+                used -= result.pop().and_then(UnicodeWidthChar::width).unwrap_or(0);
+            }
+            result.push('…');
+            used += 1;
+            break;
+        }
+        result.push(c);
+        used += w;
+    }
+    result + &" ".repeat(width - used)
+}
+
+//列表里名称后面可以左右滚动的列数
+pub const LIST_COLUMNS: usize = 8;
+
+//名称后面的各列:涨跌、当前、今开、昨收、最高、最低、主力净流入、A/H溢价,
+//没有资金流向或不是A+H时那一列为空,所有stock都为空时不占宽度
+//打开换算时价格换成统一的币种,保留两位小数,ah_rate是港元兑人民币的汇率
+fn list_columns(
+    stock: &Stock,
+    conversion: Option<&Conversion>,
+    ah_rate: Option<f64>,
+) -> [String; LIST_COLUMNS] {
+    let rate = conversion.and_then(|conversion| conversion.rate(stock));
+    let price = |value: f64| match rate {
+        Some(rate) => format!("{:.2}", value * rate),
+        None => format!("{}", value),
+    };
+    [
+        format!("{}{:+.2}%", arrow(stock.percent), stock.percent * 100.0),
+        price(stock.price),
+        price(stock.open),
+        price(stock.yestclose),
+        price(stock.high),
+        price(stock.low),
+        stock
+            .money_flow
+            .map(|flow| signed_amount(flow.main))
+            .unwrap_or_default(),
+        ah_rate
+            .and_then(|rate| stock.ah_premium(rate))
+            .map(|premium| format!("AH{:+.1}%", premium))
+            .unwrap_or_default(),
+    ]
+}
+
+//列表的列宽、每个分组的个数和有没有延迟、在交易的,要看全部stock,行情变化时才重新计算
+#[derive(Clone, Debug, Default)]
+pub struct ListLayout {
+    pub total: usize,
+    pub title_width: usize,
+    pub widths: [usize; LIST_COLUMNS],
+    pub counts: Vec<(Market, usize)>,
+    pub any_delayed: bool,
+    pub any_open: bool,
+}
+
+pub fn list_layout(
+    stocks: &[Stock],
+    conversion: Option<&Conversion>,
+    ah_rate: Option<f64>,
+) -> ListLayout {
+    let mut layout = ListLayout {
+        total: stocks.len(),
+        //名称列按最长的名称对齐,太长的截断
+        title_width: stocks
+            .iter()
+            .map(|stock| stock.title.width())
+            .max()
+            .unwrap_or(0)
+            .min(16),
+        any_delayed: stocks.iter().any(|stock| stock.is_delayed()),
+        //都收盘时只在标题栏显示,有的还在交易时才标出哪些已收盘
+        any_open: stocks.iter().any(Stock::is_open),
+        ..Default::default()
+    };
+    for stock in stocks {
+        for (width, text) in layout
+            .widths
+            .iter_mut()
+            .zip(list_columns(stock, conversion, ah_rate))
+        {
+            *width = (*width).max(text.width());
+        }
+        let section = stock.market().section();
+        match layout.counts.iter_mut().find(|(s, _)| *s == section) {
+            Some((_, count)) => *count += 1,
+            None => layout.counts.push((section, 1)),
+        }
+    }
+    layout
+}
+
+//只生成从offset开始的height行,全部stock的统计在layout里,列表很长时不用每帧都处理全部stock
+//名称列固定在最左边,后面的列从column开始显示,列宽按内容计算
+pub fn stock_list<'a>(
+    app: &App,
+    stocks: &'a [Stock],
+    rows: &[ListRow],
+    layout: &ListLayout,
+    height: usize,
+    borders: bool,
+) -> List<'a> {
+    let offline = *app.offline.lock().unwrap();
+    let ListLayout {
+        title_width,
+        widths,
+        any_delayed,
+        any_open,
+        ..
+    } = *layout;
+    let conversion = app.conversion();
+    let ah_rate = app.hkd_cny();
+    let items: Vec<_> = rows
         .iter()
-        .map(|stock| {
-            ListItem::new(Spans::from(vec![
+        .skip(app.list_offset)
+        .take(height)
+        .map(|row| {
+            let stock = match row {
+                ListRow::Stock(i) => &stocks[*i],
+                ListRow::Header(section) => {
+                    let count = layout
+                        .counts
+                        .iter()
+                        .find(|(s, _)| s == section)
+                        .map_or(0, |(_, count)| *count);
+                    return ListItem::new(Span::styled(
+                        format!(
+                            "{} {} ({})",
+                            match (app.plain, app.collapsed.contains(section)) {
+                                (true, true) => "[+]",
+                                (true, false) => "[-]",
+                                (false, true) => "▶",
+                                (false, false) => "▼",
+                            },
+                            section.section_title(),
+                            count
+                        ),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ));
+                }
+            };
+            //缓存的旧数据用灰色显示,纯文本模式不用颜色表示涨跌
+            let color = if app.plain {
+                Color::Reset
+            } else if stock.stale {
+                Color::DarkGray
+            } else {
+                app.palette.trend(stock.percent)
+            };
+            let mut spans = vec![];
+            //有标记时名称前面留一列显示标记
+            if !app.marked.is_empty() {
+                spans.push(Span::raw(if app.marked.contains(&stock.code) {
+                    "*"
+                } else {
+                    " "
+                }));
+            }
+            spans.push(Span::styled(
+                fit_width(&stock.title, title_width),
+                Style::default(),
+            ));
+            for (i, text) in list_columns(stock, conversion.as_ref(), ah_rate)
+                .iter()
+                .enumerate()
+                .skip(app.list_column)
+            {
+                //数字右对齐,只有涨跌列按涨跌着色
+                let style = if i == 0 {
+                    Style::default().fg(color)
+                } else {
+                    Style::default()
+                };
+                let text = if i == 0 && app.plain {
+                    direction_text(stock)
+                } else {
+                    text.clone()
+                };
+                if widths[i] > 0 {
+                    spans.push(Span::styled(format!(" {:>1$}", text, widths[i]), style));
+                }
+            }
+            spans.extend([
+                Span::styled(
+                    if stock.is_delayed() {
+                        " 延迟"
+                    } else if any_delayed {
+                        "     "
+                    } else {
+                        ""
+                    },
+                    Style::default().fg(Color::DarkGray),
+                ),
+                //快要除权除息时提示,避免误以为是下跌
                 Span::styled(
-                    format!("{:+.2}% ", stock.percent * 100.0),
-                    Style::default().fg(if stock.percent < 0.0 {
-                        Color::Green
+                    match stock.dividend_within(app.dividend_days) {
+                        Some(dividend) => {
+                            format!(" 除权{}", dividend.ex_date.get(5..).unwrap_or_default())
+                        }
+                        None => String::new(),
+                    },
+                    Style::default().fg(if app.plain {
+                        Color::Reset
                     } else {
-                        Color::Red
+                        Color::Yellow
                     }),
                 ),
-                Span::styled(stock.title.clone(), Style::default()),
-            ]))
+                //缓存太旧时提示,免得当成最新行情,离线时每行都显示数据的时间
+                Span::styled(
+                    match (offline, stock.cached_age()) {
+                        (true, Some(age)) if stock.stale => format!(" {}", age_text(age)),
+                        _ if stock.expired() => String::from(" 过期"),
+                        _ if any_open && !stock.is_open() => String::from(" 已收盘"),
+                        _ => String::new(),
+                    },
+                    Style::default().fg(Color::DarkGray),
+                ),
+                //错误直接显示在行尾,不再覆盖标题栏上的全局错误
+                Span::styled(
+                    if stock.error.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" {}", stock.error)
+                    },
+                    Style::default().fg(if app.plain { Color::Reset } else { Color::Red }),
+                ),
+            ]);
+            ListItem::new(Spans::from(spans))
         })
         .collect();
 
+    let block = if borders {
+        Block::default()
+            .borders(Borders::ALL)
+            .title("列表")
+            .border_type(BorderType::Plain)
+    } else {
+        Block::default()
+    };
+    //纯文本模式用行首的>标出选中行,读屏软件也能读出来
+    if app.plain {
+        return List::new(items)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+    }
     List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .style(Style::default().fg(Color::White))
-                .title("列表")
-                .border_type(BorderType::Plain),
-        )
+        .block(block.style(Style::default().fg(Color::White)))
         .highlight_style(
             Style::default()
                 .bg(Color::Yellow)
@@ -93,83 +723,1205 @@ pub fn stock_list(stocks: &Vec<Stock>) -> List {
         )
 }
 
-pub fn stock_detail(app: &App) -> Paragraph {
+//用文字表示涨跌,不依赖颜色
+fn direction_text(stock: &Stock) -> String {
+    let percent = stock.percent * 100.0;
+    if percent > 0.0 {
+        format!("涨{:.2}%", percent)
+    } else if percent < 0.0 {
+        format!("跌{:.2}%", -percent)
+    } else {
+        String::from("平0.00%")
+    }
+}
+
+//纯文本模式下列表下面的一行,用文字说明当前的选中项、输入框或提醒,选中变化时读屏软件会读出来
+pub fn announcement(app: &App) -> Paragraph {
+    let text = if let Some(notice) = app.notices.lock().unwrap().first() {
+        if notice.index.is_some() {
+            format!(
+                "{}: {} 关闭[Enter] 30分钟后再提醒[S]",
+                notice.title, notice.message
+            )
+        } else {
+            format!("{}: {} 关闭[Enter]", notice.title, notice.message)
+        }
+    } else if let AppState::Adding = app.state {
+        format!("输入证券代码: {}", app.input)
+    } else if let AppState::Trading(side) = app.state {
+        format!("输入模拟{}数量: {}", side.title(), app.input)
+    } else if let (AppState::Replaying, Some(replay)) = (&app.state, &app.replay) {
+        let stocks = app.stocks.lock().unwrap();
+        match stocks.iter().find(|stock| stock.code == replay.code) {
+            Some(stock) => {
+                let snapshot = replay.snapshot(stock);
+                format!(
+                    "回放第{}个,共{}个: {} 当时{} {}",
+                    replay.pos + 1,
+                    stock.history.len(),
+                    stock.title,
+                    snapshot.price,
+                    direction_text(&snapshot)
+                )
+            }
+            None => String::from("回放的stock已删除,按ESC退出"),
+        }
+    } else if let AppState::Comparing = app.state {
+        let stocks = app.stocks.lock().unwrap();
+        let pair: Vec<_> = stocks
+            .iter()
+            .filter(|stock| app.marked.contains(&stock.code))
+            .map(|stock| {
+                format!(
+                    "{} 当前{} {}",
+                    stock.title,
+                    stock.price,
+                    direction_text(stock)
+                )
+            })
+            .collect();
+        format!("对比: {} 关闭[ESC]", pair.join(" 和 "))
+    } else {
+        let stocks = app.stocks.lock().unwrap();
+        match app
+            .stocks_state
+            .selected()
+            .and_then(|sel| Some((sel, stocks.get(sel)?)))
+        {
+            Some((sel, stock)) => format!(
+                "选中第{}个,共{}个: {} {} 当前{} {}{}",
+                sel + 1,
+                stocks.len(),
+                stock.title,
+                stock.code,
+                stock.price,
+                direction_text(stock),
+                if stock.error.is_empty() {
+                    String::new()
+                } else {
+                    format!(" 错误:{}", stock.error)
+                }
+            ),
+            None => format!("共{}个,没有选中,按上下键选择", stocks.len()),
+        }
+    };
+    Paragraph::new(text)
+}
+
+//详情区里每个stock一格,1个占满,2个上下排,3到4个排成2x2
+pub fn detail_grid(area: Rect, count: usize) -> Vec<Rect> {
+    let halves = |area: Rect, direction: Direction| {
+        Layout::default()
+            .direction(direction)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(area)
+    };
+    match count {
+        0 | 1 => vec![area],
+        2 => halves(area, Direction::Vertical),
+        _ => halves(area, Direction::Vertical)
+            .into_iter()
+            .flat_map(|row| halves(row, Direction::Horizontal))
+            .take(count)
+            .collect(),
+    }
+}
+
+pub fn stock_detail(
+    stock: Option<&Stock>,
+    search: &str,
+    title: &str,
+    conversion: Option<&Conversion>,
+    ah_rate: Option<f64>,
+) -> Paragraph<'static> {
+    let approx = |stock: &Stock, amount: f64, show: fn(f64) -> String| {
+        conversion.map_or(String::new(), |c| c.approx(stock, amount, show))
+    };
     let mut info = String::new();
-    let sel = app.stocks_state.selected().unwrap_or(0);
-    //这里要防止sel超出列表范围
-    let stocks = app.stocks.lock().unwrap();
-    if app.stocks_state.selected().is_some() && sel < stocks.len() {
-        let stock = stocks.get(sel).unwrap();
+    if let Some(stock) = stock.filter(|stock| stock.market() == Market::Fund) {
+        //基金没有开盘价和最高最低,显示估值和净值
         info = format!(
-            "代码:{}\n涨跌:{:+.2}%\n当前:{}\n今开:{}\n昨收:{}\n最高:{}\n最低:{}",
+            "代码:{}\n估算涨幅:{:+.2}%\n估值:{}\n单位净值:{}\n净值日期:{}",
             stock.code,
             stock.percent * 100.0,
             stock.price,
+            stock.yestclose,
+            stock.nav_date
+        );
+        if let Some(time) = stock.trade_time() {
+            info.push_str(&format!("\n估值时间:{}", time.format("%m-%d %H:%M")));
+        }
+    } else if let Some(stock) = stock {
+        info = format!(
+            "代码:{}{}\n涨跌:{:+.2}%\n{}\n今开:{}\n昨收:{}\n最高:{}\n最低:{}",
+            stock.code,
+            if stock.is_delayed() { " [延迟]" } else { "" },
+            stock.percent * 100.0,
+            //指数显示点位,逆回购显示年化利率,都没有币种
+            if stock.is_index() {
+                format!("点位:{:.2}", stock.price)
+            } else if stock.is_repo() {
+                format!("利率:{:.3}%", stock.price)
+            } else {
+                format!(
+                    "当前:{} {}{}",
+                    stock.price,
+                    stock.currency(),
+                    approx(stock, stock.price, |v| format!("{:.2}", v))
+                )
+            },
             stock.open,
             stock.yestclose,
             stock.high,
             stock.low
         );
+        if stock.turnover > 0.0 {
+            info.push_str(&format!(
+                "\n成交额:{}{}",
+                format_amount(stock.turnover),
+                approx(stock, stock.turnover, format_amount)
+            ));
+        }
+        if let Some(flow) = stock.money_flow {
+            info.push_str(&format!(
+                "\n主力:{} 超大单:{} 大单:{}",
+                signed_amount(flow.main),
+                signed_amount(flow.super_large),
+                signed_amount(flow.large)
+            ));
+        }
+        if let Some(pair) = &stock.ah_pair {
+            info.push_str(&format!(
+                "\n{}:{} {}",
+                if stock.market() == Market::HK {
+                    "A股"
+                } else {
+                    "H股"
+                },
+                pair.code,
+                pair.price
+            ));
+            if let Some(premium) = ah_rate.and_then(|rate| stock.ah_premium(rate)) {
+                info.push_str(&format!(" A/H溢价:{:+.2}%", premium));
+            }
+        }
+        if let Some(dividend) = stock.next_dividend() {
+            info.push_str(&format!("\n除权:{} {}", dividend.ex_date, dividend.plan));
+        }
+        if let Some(report) = stock.next_report() {
+            info.push_str(&format!("\n财报:{} {}", report.date, report.period));
+        }
+        //成交时间按市场时区显示,和本地时区不同时再附上本地时间
+        if let Some(time) = stock.trade_time() {
+            info.push_str(&format!(
+                "\n成交:{} {}",
+                time.format("%m-%d %H:%M:%S"),
+                stock.market().tz_name(time.date_naive())
+            ));
+            let local = time.with_timezone(&Local);
+            if local.offset().fix() != *time.offset() {
+                info.push_str(&format!("\n本地:{}", local.format("%m-%d %H:%M:%S")));
+            }
+        }
+    }
+    if let Some(stock) = stock {
+        if stock.stale {
+            info.push_str(&format!(
+                "\n数据:{}的缓存{}",
+                stock.cached_at.get(5..16).unwrap_or("上次退出时"),
+                if stock.expired() { ",已过期" } else { "" }
+            ));
+        } else if !stock.source.is_empty() {
+            info.push_str(&format!("\n数据:{}", stock.source));
+        }
+        if !stock.error.is_empty() {
+            info.push_str(&format!("\n错误:{}", stock.error));
+        }
     }
 
-    Paragraph::new(info)
+    //命中搜索关键字的行高亮显示,忽略大小写
+    let search = search.to_lowercase();
+    let lines: Vec<_> = info
+        .lines()
+        .map(|line| {
+            if !search.is_empty() && line.to_lowercase().contains(&search) {
+                Spans::from(Span::styled(
+                    line.to_string(),
+                    Style::default().bg(Color::Yellow).fg(Color::Black),
+                ))
+            } else if line.starts_with("错误:") {
+                Spans::from(Span::styled(
+                    line.to_string(),
+                    Style::default().fg(Color::Red),
+                ))
+            } else {
+                Spans::from(line.to_string())
+            }
+        })
+        .collect();
+
+    Paragraph::new(lines)
         .alignment(Alignment::Center)
         .style(Style::default())
         .block(
             Block::default()
-                .title("详情")
+                .title(title.to_string())
                 .borders(Borders::ALL)
                 .border_type(BorderType::Plain),
         )
 }
 
+//详情文字最多的行数,剩下的高度够的话在下面画走势图
+const DETAIL_LINES: u16 = 15;
+
+pub fn detail_chart_area(area: Rect) -> Option<Rect> {
+    let height = area.height.saturating_sub(2 + DETAIL_LINES);
+    if height < 3 || area.width < 4 {
+        return None;
+    }
+    Some(Rect::new(
+        area.x + 1,
+        area.y + area.height - 1 - height,
+        area.width - 2,
+        height,
+    ))
+}
+
+//走势图右边放五档买卖盘,详情区太窄时不显示
+pub fn split_order_book(chart: Rect) -> (Rect, Option<Rect>) {
+    const BOOK_WIDTH: u16 = 22;
+    if chart.width < BOOK_WIDTH * 2 {
+        return (chart, None);
+    }
+    (
+        Rect {
+            width: chart.width - BOOK_WIDTH - 1,
+            ..chart
+        },
+        Some(Rect {
+            x: chart.x + chart.width - BOOK_WIDTH,
+            width: BOOK_WIDTH,
+            ..chart
+        }),
+    )
+}
+
+//卖五到卖一在上,买一到买五在下,高度不够时只显示靠近现价的档位
+pub fn order_book(stock: &Stock, height: u16, palette: Palette) -> Paragraph<'static> {
+    let levels = (height as usize / 2).min(5);
+    let line = |name: String, (price, volume): (f64, f64)| {
+        let color = if price > stock.yestclose {
+            palette.up()
+        } else if price < stock.yestclose {
+            palette.down()
+        } else {
+            Color::Reset
+        };
+        Spans::from(vec![
+            Span::raw(format!("{} ", name)),
+            Span::styled(format!("{:>8.2}", price), Style::default().fg(color)),
+            Span::raw(format!(" {:>8}", format_amount(volume))),
+        ])
+    };
+    let mut lines: Vec<_> = stock
+        .asks
+        .iter()
+        .enumerate()
+        .take(levels)
+        .rev()
+        .map(|(i, ask)| line(format!("卖{}", i + 1), *ask))
+        .collect();
+    lines.extend(
+        stock
+            .bids
+            .iter()
+            .enumerate()
+            .take(levels)
+            .map(|(i, bid)| line(format!("买{}", i + 1), *bid)),
+    );
+    Paragraph::new(lines)
+}
+
+//基本面,接口没有的数据显示-
+pub fn fundamentals(stock: &Stock, conversion: Option<&Conversion>) -> Paragraph<'static> {
+    let f = match &stock.fundamentals {
+        Some(f) => f,
+        None => return Paragraph::new("正在加载基本面...").alignment(Alignment::Center),
+    };
+    let value = |v: f64, text: String| if v == 0.0 { String::from("-") } else { text };
+    let cap = |v: f64| conversion.map_or(String::new(), |c| c.approx(stock, v, format_amount));
+    let mut text = format!(
+        "每股收益:{}\nROE:{}\n市盈率(动):{}\n市净率:{}\n营收:{}\n营收同比:{}\n净利润:{}\n净利润同比:{}\n毛利率:{}\n资产负债率:{}\n总市值:{}\n流通市值:{}",
+        value(f.eps, format!("{:.3}", f.eps)),
+        value(f.roe, format!("{:.2}%", f.roe)),
+        value(f.pe, format!("{:.2}", f.pe)),
+        value(f.pb, format!("{:.2}", f.pb)),
+        value(f.revenue, format_amount(f.revenue)),
+        value(f.revenue_growth, format!("{:+.2}%", f.revenue_growth)),
+        value(f.net_profit, format_amount(f.net_profit)),
+        value(f.profit_growth, format!("{:+.2}%", f.profit_growth)),
+        value(f.gross_margin, format!("{:.2}%", f.gross_margin)),
+        value(f.debt_ratio, format!("{:.2}%", f.debt_ratio)),
+        value(
+            f.market_cap,
+            format_amount(f.market_cap) + &cap(f.market_cap)
+        ),
+        value(f.float_cap, format_amount(f.float_cap) + &cap(f.float_cap)),
+    );
+    if !f.industry.is_empty() {
+        text.push_str(&format!("\n行业:{}", f.industry));
+    }
+    //目标价相对现价的空间
+    if let Some(rating) = &stock.rating {
+        text.push_str(&format!(
+            "\n评级:{}({}篇研报)",
+            rating.rating, rating.reports
+        ));
+        if rating.target > 0.0 && stock.price > 0.0 {
+            text.push_str(&format!(
+                "\n目标价:{:.2} 空间:{:+.2}%",
+                rating.target,
+                (rating.target / stock.price - 1.0) * 100.0
+            ));
+        }
+    }
+    Paragraph::new(text).alignment(Alignment::Center)
+}
+
+//公司资料,主营业务比较长,自动换行
+pub fn profile(stock: &Stock) -> Paragraph<'static> {
+    let profile: &Profile = match &stock.profile {
+        Some(profile) => profile,
+        None if stock.is_index() => {
+            return Paragraph::new("指数没有公司资料").alignment(Alignment::Center)
+        }
+        None => return Paragraph::new("正在加载公司资料...").alignment(Alignment::Center),
+    };
+    let text = |text: &str| {
+        if text.is_empty() {
+            String::from("-")
+        } else {
+            text.to_string()
+        }
+    };
+    let shares = |shares: f64| {
+        if shares > 0.0 {
+            format_amount(shares) + "股"
+        } else {
+            String::from("-")
+        }
+    };
+    Paragraph::new(format!(
+        "行业:{}\n上市日期:{}\n总股本:{}\n流通股本:{}\n主营业务:{}",
+        text(&profile.industry),
+        text(&profile.listing_date),
+        shares(profile.total_shares),
+        shares(profile.float_shares),
+        text(&profile.main_business)
+    ))
+    .wrap(Wrap { trim: true })
+}
+
+//大宗交易每笔两行,第一行日期、成交价、溢价率和成交额,第二行买卖营业部
+pub fn block_trades(stock: &Stock, height: u16, palette: Palette) -> Paragraph<'static> {
+    if stock.is_index() || !(stock.is_cn() || market::is_blind(&stock.code)) {
+        return Paragraph::new("只有沪深京个股有大宗交易数据").alignment(Alignment::Center);
+    }
+    if stock.block_trades_date.is_empty() {
+        return Paragraph::new("正在加载大宗交易...").alignment(Alignment::Center);
+    }
+    if stock.block_trades.is_empty() {
+        return Paragraph::new("最近3个月没有大宗交易").alignment(Alignment::Center);
+    }
+    let lines: Vec<_> = stock
+        .block_trades
+        .iter()
+        .take(height as usize / 2)
+        .flat_map(|trade| {
+            [
+                Spans::from(vec![
+                    Span::raw(format!("{} {:>9} ", trade.date, trade.price)),
+                    Span::styled(
+                        format!("{:>+7.2}%", trade.premium),
+                        Style::default().fg(palette.trend(trade.premium)),
+                    ),
+                    Span::raw(format!(" {:>8}", format_amount(trade.amount))),
+                ]),
+                Spans::from(Span::styled(
+                    format!(" 买:{} 卖:{}", trade.buyer, trade.seller),
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ]
+        })
+        .collect();
+    Paragraph::new(lines)
+}
+
+//第一行是最新的融资余额和最近5日、20日的变化,下面每天一行融资余额、较前一天的变化和融资买入额
+pub fn margins(stock: &Stock, height: u16, palette: Palette) -> Paragraph<'static> {
+    if stock.is_index() || !(stock.is_cn() || market::is_blind(&stock.code)) {
+        return Paragraph::new("只有沪深京个股有融资融券数据").alignment(Alignment::Center);
+    }
+    if stock.margins_date.is_empty() {
+        return Paragraph::new("正在加载融资融券...").alignment(Alignment::Center);
+    }
+    let latest = match stock.margins.first() {
+        Some(latest) => latest,
+        None => return Paragraph::new("不是两融标的").alignment(Alignment::Center),
+    };
+    let change = |before: Option<&Margin>| match before {
+        Some(before) if before.balance > 0.0 => {
+            let percent = (latest.balance / before.balance - 1.0) * 100.0;
+            Span::styled(
+                format!("{:+.2}%", percent),
+                Style::default().fg(palette.trend(percent)),
+            )
+        }
+        _ => Span::raw("-"),
+    };
+    let mut lines = vec![Spans::from(vec![
+        Span::raw(format!(
+            "融资余额:{} 融券余额:{} 5日:",
+            format_amount(latest.balance),
+            format_amount(latest.short_balance)
+        )),
+        change(stock.margins.get(5)),
+        Span::raw(" 20日:"),
+        change(stock.margins.last().filter(|_| stock.margins.len() >= 20)),
+    ])];
+    for (i, margin) in stock
+        .margins
+        .iter()
+        .enumerate()
+        .take((height as usize).saturating_sub(1))
+    {
+        let delta = stock
+            .margins
+            .get(i + 1)
+            .map_or(0.0, |before| margin.balance - before.balance);
+        let sign = if delta < 0.0 { "-" } else { "+" };
+        lines.push(Spans::from(vec![
+            Span::raw(format!(
+                "{} {:>8} ",
+                margin.date,
+                format_amount(margin.balance)
+            )),
+            Span::styled(
+                format!("{:>9}", sign.to_string() + &format_amount(delta.abs())),
+                Style::default().fg(palette.trend(delta)),
+            ),
+            Span::raw(format!(" 买入{:>8}", format_amount(margin.buy))),
+        ]));
+    }
+    Paragraph::new(lines)
+}
+
+//新闻或公告的标题,选中的反色显示,选中的超出高度时往下滚动
+//标题命中搜索关键字的和详情里一样高亮,忽略大小写
+pub fn news_list(
+    items: &[News],
+    selected: usize,
+    height: u16,
+    name: &str,
+    search: &str,
+) -> Paragraph<'static> {
+    if items.is_empty() {
+        return Paragraph::new(format!("正在加载{}...", name)).alignment(Alignment::Center);
+    }
+    let search = search.to_lowercase();
+    let height = height as usize;
+    let offset = (selected + 1).saturating_sub(height);
+    let lines: Vec<_> = items
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(height)
+        .map(|(i, news)| {
+            let mut style = if !search.is_empty() && news.title.to_lowercase().contains(&search) {
+                Style::default().bg(Color::Yellow).fg(Color::Black)
+            } else {
+                Style::default()
+            };
+            if i == selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            Spans::from(vec![
+                //公告只有日期,不显示00:00
+                Span::styled(
+                    format!(
+                        "{} ",
+                        news.time
+                            .get(5..16)
+                            .unwrap_or_default()
+                            .trim_end_matches(" 00:00")
+                    ),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(news.title.clone(), style),
+            ])
+        })
+        .collect();
+    Paragraph::new(lines)
+}
+
+//逐笔成交,最新的在最上面,放不下的旧成交不显示
+pub fn tick_list(stock: &Stock, height: u16, palette: Palette) -> Paragraph<'static> {
+    let lines: Vec<_> = stock
+        .ticks
+        .iter()
+        .rev()
+        .take(height as usize)
+        .map(|tick| {
+            let (mark, color) = match tick.direction {
+                1 => ("买", palette.up()),
+                -1 => ("卖", palette.down()),
+                _ => ("  ", Color::Reset),
+            };
+            Spans::from(vec![
+                Span::raw(format!("{} ", tick.time)),
+                Span::styled(
+                    format!(
+                        "{:>9} {:>8} {}",
+                        tick.price,
+                        format_amount(tick.volume),
+                        mark
+                    ),
+                    Style::default().fg(color),
+                ),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        return Paragraph::new("没有逐笔成交数据").alignment(Alignment::Center);
+    }
+    Paragraph::new(lines).alignment(Alignment::Center)
+}
+
+//最近价格的走势图,盲文点阵每个字符2x4个点,比按字符画精细得多,字体不支持时退回到方块
+pub fn price_chart(
+    stock: &Stock,
+    braille: bool,
+    palette: Palette,
+) -> Canvas<'static, impl Fn(&mut Context)> {
+    let history = stock.chart_prices();
+    let yestclose = stock.yestclose;
+    let mut low = history.iter().copied().fold(f64::MAX, f64::min);
+    let mut high = history.iter().copied().fold(f64::MIN, f64::max);
+    if yestclose > 0.0 {
+        low = low.min(yestclose);
+        high = high.max(yestclose);
+    }
+    //价格没有变化时上下留一点空间,不然画不出来
+    if high - low < f64::EPSILON {
+        low -= 1.0;
+        high += 1.0;
+    }
+    let color = palette.trend(stock.percent);
+    let last = history.len().saturating_sub(1).max(1) as f64;
+    Canvas::default()
+        .marker(if braille {
+            Marker::Braille
+        } else {
+            Marker::Block
+        })
+        .x_bounds([0.0, last])
+        .y_bounds([low, high])
+        .paint(move |ctx| {
+            //昨收作为参考线
+            if yestclose > 0.0 {
+                ctx.draw(&Line {
+                    x1: 0.0,
+                    y1: yestclose,
+                    x2: last,
+                    y2: yestclose,
+                    color: Color::DarkGray,
+                });
+                ctx.layer();
+            }
+            for (i, pair) in history.windows(2).enumerate() {
+                ctx.draw(&Line {
+                    x1: i as f64,
+                    y1: pair[0],
+                    x2: (i + 1) as f64,
+                    y2: pair[1],
+                    color,
+                });
+            }
+        })
+}
+
+//日K线,上下影线画在中间,实体画成左右两条线,阳线用上涨的颜色
+pub fn kline_chart(
+    stock: &Stock,
+    braille: bool,
+    palette: Palette,
+) -> Canvas<'static, impl Fn(&mut Context)> {
+    let candles = stock.candles.clone();
+    let low = candles.iter().map(|c| c.low).fold(f64::MAX, f64::min);
+    let high = candles.iter().map(|c| c.high).fold(f64::MIN, f64::max);
+    Canvas::default()
+        .marker(if braille {
+            Marker::Braille
+        } else {
+            Marker::Block
+        })
+        .x_bounds([-0.5, candles.len() as f64 - 0.5])
+        .y_bounds([low, high.max(low + f64::EPSILON)])
+        .paint(move |ctx| {
+            for (i, candle) in candles.iter().enumerate() {
+                let x = i as f64;
+                let color = palette.trend(candle.close - candle.open);
+                ctx.draw(&Line {
+                    x1: x,
+                    y1: candle.low,
+                    x2: x,
+                    y2: candle.high,
+                    color,
+                });
+                for dx in [-0.3, 0.3] {
+                    ctx.draw(&Line {
+                        x1: x + dx,
+                        y1: candle.open,
+                        x2: x + dx,
+                        y2: candle.close,
+                        color,
+                    });
+                }
+            }
+        })
+}
+
 pub fn stock_input(app: &App) -> Paragraph {
+    let title = match app.state {
+        AppState::Trading(side) => format!("模拟{}数量", side.title()),
+        _ => String::from("输入证券代码"),
+    };
     Paragraph::new(app.input.as_ref())
         .style(Style::default().fg(Color::Yellow))
-        .block(Block::default().borders(Borders::ALL).title("输入证券代码"))
+        .block(Block::default().borders(Borders::ALL).title(title))
+}
+
+//新建时的候选,选中的反色显示
+pub fn suggestion_list(app: &App) -> Paragraph<'static> {
+    let lines: Vec<_> = app
+        .suggestions
+        .lock()
+        .unwrap()
+        .iter()
+        .enumerate()
+        .map(|(i, suggestion)| {
+            let style = if Some(i) == app.suggestion {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Spans::from(Span::styled(
+                format!(
+                    "{} {} {}",
+                    suggestion.code, suggestion.name, suggestion.kind
+                ),
+                style,
+            ))
+        })
+        .collect();
+    Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("候选"))
+}
+
+//日志页,默认显示最后一屏,按级别着色
+pub fn log_view(app: &mut App, height: usize) -> Paragraph<'static> {
+    let lines = logger::read_latest(app.log_level);
+    let height = height.saturating_sub(2);
+    app.log_scroll = app.log_scroll.min(lines.len().saturating_sub(height));
+    let end = lines.len() - app.log_scroll;
+    let spans: Vec<_> = lines[end.saturating_sub(height)..end]
+        .iter()
+        .map(|line| {
+            let color = match line.split_whitespace().nth(1).and_then(Level::parse) {
+                Some(Level::Error) => Color::Red,
+                Some(Level::Warn) => Color::Yellow,
+                Some(Level::Debug) => Color::DarkGray,
+                _ => Color::Reset,
+            };
+            Spans::from(Span::styled(line.clone(), Style::default().fg(color)))
+        })
+        .collect();
+    let level = match app.log_level {
+        Level::Error => "错误",
+        Level::Warn => "警告及以上",
+        Level::Info => "信息及以上",
+        Level::Debug => "全部",
+    };
+    Paragraph::new(spans).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("最近的日志 {} 共{}条", level, lines.len()))
+            .border_type(BorderType::Plain),
+    )
+}
+
+//自选股接下来的财报披露日,按日期从近到远,上下键滚动
+pub fn report_view(app: &mut App, height: usize) -> Paragraph<'static> {
+    let stocks = app.stocks.lock().unwrap();
+    let mut reports: Vec<_> = stocks
+        .iter()
+        .filter_map(|stock| Some((stock, stock.next_report()?)))
+        .collect();
+    reports.sort_by(|a, b| a.1.date.cmp(&b.1.date));
+    let height = height.saturating_sub(2);
+    app.report_scroll = app.report_scroll.min(reports.len().saturating_sub(height));
+    let name_width = reports
+        .iter()
+        .map(|(stock, _)| stock.title.width())
+        .max()
+        .unwrap_or(0);
+    let today = Local::now().date_naive();
+    let mut lines: Vec<_> = reports
+        .iter()
+        .skip(app.report_scroll)
+        .take(height)
+        .map(|(stock, report)| {
+            let days = NaiveDate::parse_from_str(&report.date, "%Y-%m-%d")
+                .map(|date| (date - today).num_days())
+                .unwrap_or(0);
+            Spans::from(vec![
+                Span::raw(format!("{} ", report.date)),
+                Span::styled(
+                    if days == 0 {
+                        String::from("  今天 ")
+                    } else {
+                        format!("{:>4}天后 ", days)
+                    },
+                    //一周以内的突出显示
+                    Style::default().fg(if days <= 7 {
+                        Color::Yellow
+                    } else {
+                        Color::Reset
+                    }),
+                ),
+                Span::raw(format!(
+                    "{}{} {}",
+                    stock.title,
+                    " ".repeat(name_width - stock.title.width()),
+                    report.period
+                )),
+            ])
+        })
+        .collect();
+    let title = format!("财报日历 共{}个", reports.len());
+    if lines.is_empty() {
+        lines.push(Spans::from("没有预约披露的财报,只支持沪深"));
+    }
+    Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_type(BorderType::Plain),
+    )
+}
+
+//板块涨跌幅排行和领涨股,上下键滚动
+pub fn board_view(app: &mut App, height: usize) -> Paragraph<'static> {
+    let boards = app.boards.lock().unwrap();
+    let height = height.saturating_sub(2);
+    app.board_scroll = app.board_scroll.min(boards.len().saturating_sub(height));
+    let name_width = boards
+        .iter()
+        .map(|board| board.name.width())
+        .max()
+        .unwrap_or(0);
+    let mut lines: Vec<_> = boards
+        .iter()
+        .skip(app.board_scroll)
+        .take(height)
+        .map(|board| {
+            let trend = |percent: f64| Style::default().fg(app.palette.trend(percent));
+            Spans::from(vec![
+                Span::raw(format!(
+                    "{}{} ",
+                    board.name,
+                    " ".repeat(name_width - board.name.width())
+                )),
+                Span::styled(
+                    format!("{:>+7.2}%", board.percent * 100.0),
+                    trend(board.percent),
+                ),
+                Span::raw(format!("  领涨 {} ", board.leader)),
+                Span::styled(
+                    format!("{:+.2}%", board.leader_percent * 100.0),
+                    trend(board.leader_percent),
+                ),
+            ])
+        })
+        .collect();
+    let title = format!(
+        "{}板块 共{}个",
+        if app.concept { "概念" } else { "行业" },
+        boards.len()
+    );
+    if lines.is_empty() {
+        lines.push(Spans::from("正在加载板块..."));
+    }
+    Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_type(BorderType::Plain),
+    )
+}
+
+//模拟账户:资产汇总、持仓盈亏和最近的成交
+pub fn paper_view(app: &App) -> Paragraph {
+    let stocks = app.stocks.lock().unwrap();
+    let paper = &app.paper;
+    let equity = paper.equity(&stocks);
+    let profit = equity - paper.initial;
+    let trend = |value: f64| Style::default().fg(app.palette.trend(value));
+    let mut lines = vec![
+        Spans::from(vec![
+            Span::raw(format!("总资产:{:.2} 可用:{:.2} 盈亏:", equity, paper.cash)),
+            Span::styled(
+                format!(
+                    "{}{:+.2} ({:+.2}%)",
+                    arrow(profit),
+                    profit,
+                    profit / paper.initial * 100.0
+                ),
+                trend(profit),
+            ),
+        ]),
+        Spans::from(format!(
+            "佣金{}% 最低{} 滑点{}%",
+            paper.fee_rate * 100.0,
+            paper.min_fee,
+            paper.slippage * 100.0
+        )),
+        Spans::default(),
+        Spans::from(Span::styled(
+            "持仓",
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+    ];
+    for position in paper.positions.iter() {
+        let value = paper.market_value(position, &stocks);
+        let profit = value - position.cost;
+        lines.push(Spans::from(vec![
+            Span::raw(format!(
+                "{} {} {}股 成本{:.3} 市值{:.2} ",
+                fit_width(&position.title, 10),
+                position.code,
+                position.shares,
+                position.cost / position.shares as f64,
+                value
+            )),
+            Span::styled(
+                format!(
+                    "{}{:+.2} ({:+.2}%)",
+                    arrow(profit),
+                    profit,
+                    profit / position.cost * 100.0
+                ),
+                trend(profit),
+            ),
+        ]));
+    }
+    if paper.positions.is_empty() {
+        lines.push(Spans::from("空仓,在列表里选中后按B买入"));
+    }
+    //风险:按市场的仓位、最大持仓占比、按刷新间隔计算的VaR
+    if !paper.positions.is_empty() {
+        lines.push(Spans::default());
+        lines.push(Spans::from(Span::styled(
+            "风险",
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        let exposure: Vec<_> = paper
+            .exposure(&stocks)
+            .iter()
+            .map(|(market, ratio)| format!("{}{:.1}%", market.section_title(), ratio * 100.0))
+            .collect();
+        lines.push(Spans::from(format!("仓位: {}", exposure.join(" "))));
+        let sectors: Vec<_> = paper
+            .sector_exposure(&stocks)
+            .iter()
+            .map(|(sector, ratio)| format!("{}{:.1}%", sector, ratio * 100.0))
+            .collect();
+        lines.push(Spans::from(format!("行业: {}", sectors.join(" "))));
+        if let Some((position, ratio)) = paper.concentration(&stocks) {
+            lines.push(Spans::from(format!(
+                "最大持仓: {} {:.1}%",
+                position.title,
+                ratio * 100.0
+            )));
+        }
+        lines.push(Spans::from(match paper.value_at_risk(&stocks) {
+            Ok(var) => format!("VaR(95%,1日): {:.2}", var),
+            Err(err) => format!("VaR: {}", err),
+        }));
+    }
+    lines.push(Spans::default());
+    lines.push(Spans::from(Span::styled(
+        "最近成交",
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+    for trade in paper.trades.iter().rev().take(10) {
+        lines.push(Spans::from(format!(
+            "{} {} {} {}股 价格{:.3} 费用{:.2}",
+            trade.time,
+            trade.side.title(),
+            trade.code,
+            trade.shares,
+            trade.price,
+            trade.fee
+        )));
+    }
+    Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("模拟交易")
+            .border_type(BorderType::Plain),
+    )
+}
+
+//在area中间取一块区域,超出时缩小到area的大小
+pub fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+//年初至今的涨幅,用去年最后一个交易日的收盘价算,日K线不够时是None
+fn ytd_return(stock: &Stock) -> Option<f64> {
+    let year = format!("{}", Local::now().format("%Y"));
+    let base = stock
+        .candles
+        .iter()
+        .rev()
+        .find(|candle| candle.date < year)?
+        .close;
+    (base > 0.0 && stock.price > 0.0).then(|| stock.price / base - 1.0)
+}
+
+//基本面还没加载或接口没有这一项时显示-
+fn fundamental(
+    stock: &Stock,
+    get: fn(&Fundamentals) -> f64,
+    show: impl Fn(f64) -> String,
+) -> String {
+    match stock.fundamentals.as_ref().map(get) {
+        Some(v) if v != 0.0 => show(v),
+        _ => String::from("-"),
+    }
+}
+
+//两个标记的stock左右对比,行情数据来自刷新,市盈率、市净率、市值来自基本面,年初至今来自日K线
+pub fn compare_popup(app: &App) -> Paragraph {
+    let stocks = app.stocks.lock().unwrap();
+    let pair: Vec<_> = app
+        .marked
+        .iter()
+        .map(|code| stocks.iter().find(|stock| stock.code == *code))
+        .collect();
+    let amplitude = |stock: &Stock| {
+        if stock.yestclose > 0.0 {
+            format!("{:.2}%", (stock.high - stock.low) / stock.yestclose * 100.0)
+        } else {
+            String::from("-")
+        }
+    };
+    //打开换算时当前价、成交额和市值换成统一的币种,可以直接比较
+    let conversion = app.conversion();
+    let converted = |s: &Stock, amount: f64| match conversion
+        .as_ref()
+        .and_then(|c| Some((c.rate(s)?, c.currency.as_str())))
+    {
+        Some((rate, currency)) => (amount * rate, currency.to_string()),
+        None => (amount, s.currency().to_string()),
+    };
+    let rows: Vec<(&str, Box<dyn Fn(&Stock) -> String + '_>)> = vec![
+        ("名称", Box::new(|s: &Stock| s.title.clone())),
+        ("代码", Box::new(|s: &Stock| s.code.clone())),
+        (
+            "当前",
+            Box::new(|s: &Stock| match converted(s, s.price) {
+                (price, currency) if conversion.is_some() => format!("{:.2} {}", price, currency),
+                _ => format!("{} {}", s.price, s.currency()),
+            }),
+        ),
+        (
+            "涨跌",
+            Box::new(|s: &Stock| format!("{}{:+.2}%", arrow(s.percent), s.percent * 100.0)),
+        ),
+        ("今开", Box::new(|s: &Stock| s.open.to_string())),
+        ("昨收", Box::new(|s: &Stock| s.yestclose.to_string())),
+        ("最高", Box::new(|s: &Stock| s.high.to_string())),
+        ("最低", Box::new(|s: &Stock| s.low.to_string())),
+        ("振幅", Box::new(amplitude)),
+        (
+            "成交额",
+            Box::new(|s: &Stock| format_amount(converted(s, s.turnover).0)),
+        ),
+        (
+            "市盈率",
+            Box::new(|s: &Stock| fundamental(s, |f| f.pe, |v| format!("{:.2}", v))),
+        ),
+        (
+            "市净率",
+            Box::new(|s: &Stock| fundamental(s, |f| f.pb, |v| format!("{:.2}", v))),
+        ),
+        (
+            "总市值",
+            Box::new(|s: &Stock| {
+                fundamental(s, |f| f.market_cap, |v| format_amount(converted(s, v).0))
+            }),
+        ),
+        (
+            "年初至今",
+            Box::new(|s: &Stock| {
+                ytd_return(s).map_or(String::from("-"), |ytd| format!("{:+.2}%", ytd * 100.0))
+            }),
+        ),
+    ];
+    let lines: Vec<_> = rows
+        .iter()
+        .map(|(name, value)| {
+            let cells: Vec<_> = pair
+                .iter()
+                .map(|stock| fit_width(&stock.map_or(String::from("-"), |s| value(s)), 20))
+                .collect();
+            Spans::from(format!("{} {}", fit_width(name, 6), cells.join(" ")))
+        })
+        .collect();
+    Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("对比")
+            .border_type(BorderType::Plain),
+    )
+}
+
+pub fn alert_toast(app: &App) -> Paragraph {
+    let (title, message) = app
+        .notices
+        .lock()
+        .unwrap()
+        .first()
+        .map(|notice| (notice.title, notice.message.clone()))
+        .unwrap_or_default();
+    Paragraph::new(message)
+        .style(Style::default().fg(Color::Red))
+        .block(Block::default().borders(Borders::ALL).title(title))
 }
 
 pub fn title_bar(app: &App, rect: Rect) -> Paragraph {
-    let left = format!("Stock v{}", VERSION);
+    let left = format!("Stock v{} ", VERSION);
+    //标签页跟在版本号后面,当前页反色显示
+    let mut tabs = vec![];
+    for (i, tab) in Tab::ALL.iter().enumerate() {
+        let style = if *tab == app.tab {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        tabs.push(Span::styled(format!(" {}{} ", i + 1, tab.title()), style));
+    }
+    let tabs_width: usize = tabs.iter().map(|span| span.width()).sum();
     let error = app.error.lock().unwrap();
-    let right = if error.is_empty() {
+    //快速刷新时很频繁,不显示刷新中,免得闪烁
+    let throttled = app.throttle.lock().unwrap().remaining();
+    let offline = *app.offline.lock().unwrap();
+    let right = if offline {
+        String::from("离线 恢复[O]")
+    } else if let Some(left) = throttled {
+        format!("被限流,{}秒后重试", left.as_secs() + 1)
+    } else if error.is_empty() && !app.fast_refreshing() && *app.refreshing.lock().unwrap() {
+        String::from("刷新中...")
+    } else if error.is_empty() {
+        //收盘后不再自动刷新,最后更新时间就是收盘时取到的
+        let empty = app.stocks.lock().unwrap().is_empty();
+        let closed = !empty && !app.is_market_open();
         app.last_refresh
             .lock()
             .unwrap()
-            .format("最后更新 %H:%M:%S")
+            .format(if closed {
+                "已收盘 最后更新 %H:%M:%S"
+            } else if app.fast_refreshing() {
+                "快速 最后更新 %H:%M:%S"
+            } else {
+                "最后更新 %H:%M:%S"
+            })
             .to_string()
     } else {
         error.clone()
     };
-    Paragraph::new(Spans::from(vec![
-        Span::raw(left.clone()),
-        //使用checked_sub防止溢出
-        Span::raw(
-            " ".repeat(
-                (rect.width as usize)
-                    .checked_sub(right.width() + left.width())
-                    .unwrap_or(0),
-            ),
-        ),
-        Span::styled(
-            right,
-            Style::default().fg(if error.is_empty() {
-                Color::White
-            } else {
-                Color::Red
-            }),
+    //打开换算时标出换算成的币种
+    let right = if app.convert {
+        format!("[{}] {}", app.display_currency, right)
+    } else {
+        right
+    };
+    let mut spans = vec![Span::raw(left.clone())];
+    spans.append(&mut tabs);
+    //使用checked_sub防止溢出
+    spans.push(Span::raw(
+        " ".repeat(
+            (rect.width as usize)
+                .checked_sub(right.width() + left.width() + tabs_width)
+                .unwrap_or(0),
         ),
-    ]))
-    .alignment(Alignment::Left)
+    ));
+    spans.push(Span::styled(
+        right,
+        Style::default().fg(if offline || throttled.is_some() {
+            Color::Yellow
+        } else if error.is_empty() {
+            Color::White
+        } else {
+            Color::Red
+        }),
+    ));
+    Paragraph::new(Spans::from(spans)).alignment(Alignment::Left)
 }
 
 pub fn status_bar(app: &mut App) -> Paragraph {
-    Paragraph::new(
-        match app.state {
-            AppState::Normal => "退出[Q] | 新建[N] | 删除[D] | 刷新[R] | 上移[U] | 下移[J]",
-            AppState::Adding => "确认[Enter] | 取消[ESC] | 上交所代码前需要加0，深市加1",
+    Paragraph::new(match app.state {
+        AppState::Normal if !app.notices.lock().unwrap().is_empty() => {
+            let snooze = app.notices.lock().unwrap()[0].index.is_some();
+            if snooze {
+                "关闭[Enter] | 30分钟后再提醒[S]".to_string()
+            } else {
+                "关闭[Enter]".to_string()
+            }
         }
-        .to_string(),
-    )
+        AppState::Normal if !app.new_version.lock().unwrap().is_empty() => {
+            format!("新版本v{}可用 | 退出[Q] | 新建[N] | 刷新[R]", app.new_version.lock().unwrap())
+        }
+        AppState::Normal if app.tab == Tab::Boards => {
+            "退出[Q] | 行业/概念[K] | 滚动[↑↓] | 切换页[Tab]".to_string()
+        }
+        AppState::Normal if app.tab == Tab::Reports => {
+            "退出[Q] | 滚动[↑↓] | 切换页[Tab]".to_string()
+        }
+        AppState::Normal if app.tab == Tab::Log => {
+            "退出[Q] | 过滤级别[L] | 滚动[↑↓] | 切换页[Tab]".to_string()
+        }
+        AppState::Normal => {
+            "退出[Q] | 新建[N] | 删除[D] | 刷新[R] | 快速刷新[I] | 离线[O] | 板块[K] | 财报[E] | 大盘[V] | 换算币种[X] | 上移[U] | 下移[J] | 搜索[/] | 固定[P] | 回放[Y] | 模拟买卖[B/S] | 标记[M] | 对比[C] | 走势/日K/逐笔/基本面/简介/大宗/两融/新闻/公告[T] | 上下条新闻[[/]] | 打开新闻[W] | 专注[F] | 纯文本[A] | 左右滚动[←→] | 分组[G] | 折叠[Z] | 切换页[Tab] | 调整宽度[Ctrl+←→]"
+                .to_string()
+        }
+        AppState::Adding => {
+            "确认[Enter] | 取消[ESC] | 沪深代码直接输入6位数字,指数可输入名称如上证指数,基金加F前缀如F161725,也可以输入名称从候选[↑↓]里选".to_string()
+        }
+        AppState::Comparing => "关闭[ESC]".to_string(),
+        AppState::Trading(side) => format!("确认{}[Enter] | 取消[ESC] | 输入股数", side.title()),
+        AppState::Replaying => {
+            "播放/暂停[空格] | 加速[+] | 减速[-] | 单步[←→] | 退出[ESC]".to_string()
+        }
+        AppState::Searching => format!("搜索: {} | 确认[Enter] | 清除[ESC]", app.search),
+    })
     .alignment(Alignment::Left)
 }